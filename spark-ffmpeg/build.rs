@@ -33,6 +33,7 @@ fn main() {
     println!("cargo:rustc-link-lib=avformat");
     println!("cargo:rustc-link-lib=avutil");
     println!("cargo:rustc-link-lib=swscale");
+    println!("cargo:rustc-link-lib=swresample");
     println!("cargo:rustc-link-lib=avfilter");
 
     // The bindgen::Builder is the main entry point
@@ -74,6 +75,9 @@ fn main() {
 
     let codec_id = extract_enum(&bindings.to_string(), "AVPixelFormat_AV_PIX_FMT_", "i32");
     std::fs::write(out_path.join("pixel.rs"), codec_id).unwrap();
+
+    let sample_format = extract_enum(&bindings.to_string(), "AVSampleFormat_AV_SAMPLE_FMT_", "i32");
+    std::fs::write(out_path.join("sample_format.rs"), sample_format).unwrap();
 }
 
 fn extract_enum(target: &str, pattern: &str, types: &str) -> String {
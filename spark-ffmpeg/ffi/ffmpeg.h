@@ -2,6 +2,7 @@
 #include "libavformat/avformat.h"
 
 #include "libswscale/swscale.h"
+#include "libswresample/swresample.h"
 
 #include "libavfilter/avfilter.h"
 #include "libavfilter/buffersrc.h"
@@ -9,4 +10,6 @@
 
 #include "libavutil/avutil.h"
 #include "libavutil/imgutils.h"
-#include "libavutil/log.h"
\ No newline at end of file
+#include "libavutil/log.h"
+#include "libavutil/channel_layout.h"
+#include "libavutil/samplefmt.h"
\ No newline at end of file
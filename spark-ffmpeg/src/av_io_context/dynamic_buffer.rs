@@ -0,0 +1,52 @@
+use crate::ffi::{av_free, avio_close_dyn_buf, avio_open_dyn_buf, AVIOContext};
+use anyhow::Result;
+use std::ptr::null_mut;
+
+/// Seekable in-memory output, lets muxers which rewrite their header (WAV, MP4)
+/// write without touching the file system.
+#[derive(Debug)]
+pub struct AVDynamicBuffer {
+    pub(crate) inner: *mut AVIOContext,
+}
+
+impl AVDynamicBuffer {
+    pub fn new() -> Result<Self> {
+        let mut context: *mut AVIOContext = null_mut();
+        ffmpeg! {
+            avio_open_dyn_buf(&mut context) or "Failed to open dynamic buffer"
+        }
+
+        Ok(AVDynamicBuffer { inner: context })
+    }
+
+    /// Closes the buffer and returns everything written into it.
+    pub fn into_bytes(mut self) -> Vec<u8> {
+        let bytes = unsafe { Self::close(self.inner) };
+        self.inner = null_mut();
+        bytes
+    }
+
+    unsafe fn close(context: *mut AVIOContext) -> Vec<u8> {
+        let mut buffer: *mut u8 = null_mut();
+        let size = avio_close_dyn_buf(context, &mut buffer);
+
+        let bytes = if buffer.is_null() || size <= 0 {
+            Vec::new()
+        } else {
+            std::slice::from_raw_parts(buffer, size as usize).to_vec()
+        };
+        av_free(buffer.cast());
+
+        bytes
+    }
+}
+
+impl Drop for AVDynamicBuffer {
+    fn drop(&mut self) {
+        if !self.inner.is_null() {
+            unsafe {
+                Self::close(self.inner);
+            }
+        }
+    }
+}
@@ -3,6 +3,8 @@ use anyhow::anyhow;
 use spark_proc_macro::wrap_ffmpeg;
 use std::os::raw::{c_int, c_void};
 
+pub mod dynamic_buffer;
+
 wrap_ffmpeg!(
     AVIOContext drop+ [avio_context_free]
 );
@@ -2,9 +2,16 @@ use crate::avcodec::AVCodecContext;
 use crate::avframe::AVFrame;
 use crate::avpacket::AVPacket;
 use crate::ffi::{
-    avcodec_receive_frame, avcodec_receive_packet, avcodec_send_frame, avcodec_send_packet,
+    avcodec_receive_frame, avcodec_receive_packet, avcodec_send_frame, avcodec_send_packet, EAGAIN,
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
+use std::ptr::null;
+
+/// `AVERROR(EAGAIN)`: the encoder needs more input before it can output a packet.
+/// Taken from the platform's `errno.h` through the bindings, it is not 11 everywhere.
+const AVERROR_EAGAIN: i32 = -(EAGAIN as i32);
+/// `AVERROR_EOF`: the encoder was flushed and has no packets left.
+const AVERROR_EOF: i32 = -0x20464F45;
 
 impl AVCodecContext {
     pub fn send_packet(&self, packet: &AVPacket) -> Result<()> {
@@ -41,4 +48,28 @@ impl AVCodecContext {
 
         Ok(())
     }
+
+    /// Enters draining mode, the remaining packets are then collected with `try_receive_packet`.
+    pub fn send_eof(&self) -> Result<()> {
+        ffmpeg! {
+            avcodec_send_frame(self.inner, null())
+        }
+
+        Ok(())
+    }
+
+    /// Like `receive_packet`, but returns `None` when the encoder needs more input or is fully drained.
+    pub fn try_receive_packet(&self) -> Result<Option<AVPacket>> {
+        let packet = AVPacket::new()?;
+        let code = unsafe { avcodec_receive_packet(self.inner, packet.inner) };
+
+        match code {
+            0 => Ok(Some(packet)),
+            AVERROR_EAGAIN | AVERROR_EOF => Ok(None),
+            _ => bail!(
+                "Error when calling native function `avcodec_receive_packet`, error code: {}.",
+                code
+            ),
+        }
+    }
 }
@@ -2,10 +2,10 @@ use crate::avcodec::codec::codec_parameter::AVCodecParameters;
 use crate::avcodec::{AVCodec, AVCodecContext};
 use crate::avframe::AVFrame;
 use crate::ffi::{
-    avcodec_alloc_context3, avcodec_open2, avcodec_parameters_to_context, AVDictionary, AVRational,
-    AVStream,
+    av_channel_layout_default, avcodec_alloc_context3, avcodec_open2,
+    avcodec_parameters_to_context, AVDictionary, AVRational, AVStream, AV_CODEC_FLAG_GLOBAL_HEADER,
 };
-use crate::ffi_enum::AVPixelFormat;
+use crate::ffi_enum::{AVPixelFormat, AVSampleFormat};
 use crate::DeepClone;
 use anyhow::{bail, Result};
 use std::ptr::{null, null_mut};
@@ -114,6 +114,49 @@ impl AVCodecContext {
         Ok(context)
     }
 
+    /// Opens an audio encoder. `global_header` has to be set when the output container
+    /// keeps the codec extradata in its header (see `AVFormatContext::needs_global_header`).
+    pub fn new_audio(
+        codec: &AVCodec,
+        sample_format: AVSampleFormat,
+        sample_rate: i32,
+        channels: i32,
+        bit_rate: Option<i64>,
+        global_header: bool,
+        av_dictionary: Option<AVDictionary>,
+    ) -> Result<Self> {
+        let ptr = unsafe { avcodec_alloc_context3(codec.inner.cast_const()) };
+
+        if ptr.is_null() {
+            bail!("Failed to allocate codec context.");
+        }
+
+        let mut context = AVCodecContext { inner: ptr };
+        context.sample_fmt = sample_format as i32;
+        context.sample_rate = sample_rate;
+        unsafe {
+            av_channel_layout_default(&mut (*ptr).ch_layout, channels);
+        }
+        if let Some(bit_rate) = bit_rate {
+            context.bit_rate = bit_rate;
+        }
+        if global_header {
+            context.flags |= AV_CODEC_FLAG_GLOBAL_HEADER as i32;
+        }
+
+        context.time_base = AVRational {
+            num: 1,
+            den: sample_rate,
+        };
+
+        context.codec_id = codec.id;
+        context.codec = codec.inner;
+
+        context.open(Some(codec), av_dictionary)?;
+
+        Ok(context)
+    }
+
     fn open(&self, codec: Option<&AVCodec>, av_dictionary: Option<AVDictionary>) -> Result<()> {
         ffmpeg! {
             avcodec_open2(
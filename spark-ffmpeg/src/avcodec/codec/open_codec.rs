@@ -1,7 +1,10 @@
 use crate::avcodec::AVCodec;
-use crate::ffi::{avcodec_find_decoder, avcodec_find_encoder, AVStream};
+use crate::ffi::{
+    avcodec_find_decoder, avcodec_find_encoder, avcodec_find_encoder_by_name, AVStream,
+};
 use crate::ffi_enum::AVCodecID;
 use anyhow::{anyhow, Result};
+use std::ffi::CString;
 
 impl AVCodec {
     pub fn new_decoder(stream: &AVStream) -> Result<Self> {
@@ -37,4 +40,18 @@ impl AVCodec {
             })
         }
     }
+
+    /// Looks up an encoder by its FFmpeg name, e.g. `libopus`, for codecs with several implementations.
+    pub fn new_encoder_with_name(name: &str) -> Result<Self> {
+        let c_name = CString::new(name)?;
+        let codec = unsafe { avcodec_find_encoder_by_name(c_name.as_ptr()) };
+
+        if codec.is_null() {
+            Err(anyhow!("Failed to find encoder {}", name))
+        } else {
+            Ok(AVCodec {
+                inner: codec.cast_mut(),
+            })
+        }
+    }
 }
@@ -35,4 +35,9 @@ impl AVCodecContext {
     pub fn set_pixel_format(&mut self, format: AVPixelFormat) {
         self.pix_fmt = format as i32;
     }
+
+    /// Samples per channel each audio frame must carry, 0 when the encoder takes any size.
+    pub fn frame_size(&self) -> i32 {
+        self.frame_size
+    }
 }
@@ -3,7 +3,7 @@ use crate::avformat::{AVFormatContext, AVFormatContextRaw};
 use crate::avpacket::AVPacket;
 use crate::ffi::{
     av_read_frame, avformat_alloc_context, avformat_open_input, AVDictionary, AVInputFormat,
-    AVFMT_FLAG_CUSTOM_IO,
+    AVFMT_FLAG_CUSTOM_IO, AV_TIME_BASE,
 };
 use crate::ffi_enum::AVPixelFormat;
 use anyhow::{anyhow, Result};
@@ -131,6 +131,17 @@ impl AVFormatContext {
 
         Ok(AVPixelFormat::try_from(format)?)
     }
+
+    /// Length of the input in seconds, `None` when the container does not tell.
+    /// Some containers only know it once the streams were scanned with `find_stream`.
+    pub fn duration_secs(&self) -> Option<f64> {
+        // `AV_NOPTS_VALUE`, which bindgen cannot translate
+        if self.duration == i64::MIN {
+            return None;
+        }
+
+        Some(self.duration as f64 / AV_TIME_BASE as f64)
+    }
 }
 
 #[test]
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 pub mod av_frame_reader;
 pub mod av_stream;
 pub mod avformat_context;
+pub mod output_context;
 
 #[repr(i32)]
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
@@ -0,0 +1,106 @@
+use crate::av_io_context::dynamic_buffer::AVDynamicBuffer;
+use crate::avcodec::AVCodecContext;
+use crate::avformat::{AVFormatContext, AVFormatContextRaw};
+use crate::avpacket::AVPacket;
+use crate::ffi::{
+    av_interleaved_write_frame, av_packet_rescale_ts, av_write_trailer,
+    avcodec_parameters_from_context, avformat_alloc_output_context2, avformat_new_stream,
+    avformat_write_header, AVFMT_FLAG_CUSTOM_IO, AVFMT_GLOBALHEADER,
+};
+use anyhow::{anyhow, Result};
+use std::ffi::{c_int, CString};
+use std::ptr::{null, null_mut};
+
+impl AVFormatContext {
+    /// Allocates a muxer by its FFmpeg short name, e.g. `ogg`, `ipod`, `mp3` or `wav`.
+    pub fn alloc_output(format_name: &str) -> Result<Self> {
+        let mut av_format_context: *mut AVFormatContextRaw = null_mut();
+        let c_name = CString::new(format_name)?;
+
+        native! {
+            avformat_alloc_output_context2(
+                &mut av_format_context as *mut *mut AVFormatContextRaw,
+                null(),
+                c_name.as_ptr(),
+                null()
+            ) or "Failed to allocate output context"
+        };
+        if av_format_context.is_null() {
+            return Err(anyhow!(
+                "Failed to allocate output context for {}",
+                format_name
+            ));
+        }
+
+        Ok(AVFormatContext {
+            inner: av_format_context,
+            opened: false,
+            scanned_stream: Default::default(),
+        })
+    }
+
+    /// Whether encoders have to put their extradata in the container header instead of the stream.
+    pub fn needs_global_header(&self) -> bool {
+        unsafe { (*(*self.inner).oformat).flags & AVFMT_GLOBALHEADER as c_int != 0 }
+    }
+
+    /// Adds an output stream carrying the packets of `codec_context`, returns its index.
+    pub fn add_stream(&mut self, codec_context: &AVCodecContext) -> Result<i32> {
+        let stream = unsafe { avformat_new_stream(self.inner, null()) };
+        if stream.is_null() {
+            return Err(anyhow!("Failed to create output stream"));
+        }
+
+        unsafe {
+            (*stream).time_base = codec_context.time_base;
+        }
+        ffmpeg! {
+            avcodec_parameters_from_context((*stream).codecpar, codec_context.inner)
+                or "Failed to copy codec parameters to stream"
+        }
+
+        Ok(unsafe { (*stream).index })
+    }
+
+    pub fn set_output(&mut self, buffer: &AVDynamicBuffer) {
+        self.pb = buffer.inner;
+        self.flags |= AVFMT_FLAG_CUSTOM_IO as c_int;
+    }
+
+    pub fn write_header(&mut self) -> Result<()> {
+        native! {
+            avformat_write_header(self.inner, null_mut()) or "Failed to write header"
+        };
+
+        Ok(())
+    }
+
+    /// Writes a packet of the encoder `codec_context` into the stream `stream_index`.
+    pub fn write_packet(
+        &mut self,
+        packet: &mut AVPacket,
+        stream_index: i32,
+        codec_context: &AVCodecContext,
+    ) -> Result<()> {
+        let stream_time_base =
+            unsafe { (**(*self.inner).streams.add(stream_index as usize)).time_base };
+        packet.stream_index = stream_index;
+        unsafe {
+            av_packet_rescale_ts(packet.inner, codec_context.time_base, stream_time_base);
+        }
+
+        ffmpeg! {
+            av_interleaved_write_frame(self.inner, packet.inner) or "Failed to write packet"
+        }
+
+        Ok(())
+    }
+
+    pub fn write_trailer(&mut self) -> Result<()> {
+        ffmpeg! {
+            av_write_trailer(self.inner) or "Failed to write trailer"
+        }
+
+        Ok(())
+    }
+}
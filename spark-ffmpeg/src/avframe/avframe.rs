@@ -1,9 +1,9 @@
 use crate::avframe::AVFrame;
 use crate::ffi::{
-    av_frame_alloc, av_frame_copy, av_frame_copy_props, av_frame_get_buffer, av_image_alloc,
-    av_image_fill_arrays,
+    av_channel_layout_default, av_frame_alloc, av_frame_copy, av_frame_copy_props,
+    av_frame_get_buffer, av_image_alloc, av_image_fill_arrays,
 };
-use crate::ffi_enum::AVPixelFormat;
+use crate::ffi_enum::{AVPixelFormat, AVSampleFormat};
 use crate::DeepClone;
 use anyhow::{anyhow, Result};
 
@@ -42,6 +42,37 @@ impl AVFrame {
         Ok(need_size)
     }
 
+    /// Sets up an audio frame and allocates room for `nb_samples` samples per channel.
+    pub fn alloc_samples(
+        &mut self,
+        format: AVSampleFormat,
+        sample_rate: i32,
+        channels: i32,
+        nb_samples: i32,
+    ) -> Result<()> {
+        self.format = format as i32;
+        self.sample_rate = sample_rate;
+        self.nb_samples = nb_samples;
+        unsafe {
+            av_channel_layout_default(&mut (*self.inner).ch_layout, channels);
+        }
+
+        ffmpeg! {
+            av_frame_get_buffer(self.inner, 0)
+        }
+
+        Ok(())
+    }
+
+    /// Sets up an audio frame without data, e.g. as the output of a resampler which allocates it itself.
+    pub fn set_audio_format(&mut self, format: AVSampleFormat, sample_rate: i32, channels: i32) {
+        self.format = format as i32;
+        self.sample_rate = sample_rate;
+        unsafe {
+            av_channel_layout_default(&mut (*self.inner).ch_layout, channels);
+        }
+    }
+
     pub fn pixel_format(&self) -> Result<AVPixelFormat> {
         Ok(AVPixelFormat::try_from(self.format)?)
     }
@@ -1,4 +1,6 @@
 use crate::avframe::AVFrame;
+use crate::ffi::{av_get_bytes_per_sample, av_sample_fmt_is_planar};
+use std::slice::{from_raw_parts, from_raw_parts_mut};
 
 impl AVFrame {
     pub fn get_width(&self) -> i32 {
@@ -12,4 +14,49 @@ impl AVFrame {
     pub fn line_size(&self, index: usize) -> i32 {
         unsafe { (*self.inner).linesize[index] }
     }
+
+    pub fn nb_samples(&self) -> i32 {
+        unsafe { (*self.inner).nb_samples }
+    }
+
+    pub fn set_pts(&mut self, pts: i64) {
+        self.pts = pts;
+    }
+
+    /// Samples of the first plane: all channels of packed audio, the first channel of planar audio.
+    pub fn audio_data(&self) -> &[u8] {
+        let size = self.audio_plane_size();
+        if size == 0 {
+            return &[];
+        }
+        unsafe { from_raw_parts(self.data[0], size) }
+    }
+
+    pub fn audio_data_mut(&mut self) -> &mut [u8] {
+        let size = self.audio_plane_size();
+        if size == 0 {
+            return &mut [];
+        }
+        unsafe { from_raw_parts_mut(self.data[0], size) }
+    }
+
+    fn audio_plane_size(&self) -> usize {
+        if self.data[0].is_null() || self.nb_samples <= 0 {
+            return 0;
+        }
+
+        let (bytes_per_sample, planar) = unsafe {
+            (
+                av_get_bytes_per_sample(self.format),
+                av_sample_fmt_is_planar(self.format) != 0,
+            )
+        };
+        let channels = if planar {
+            1
+        } else {
+            self.ch_layout.nb_channels
+        };
+
+        (self.nb_samples * bytes_per_sample.max(0) * channels) as usize
+    }
 }
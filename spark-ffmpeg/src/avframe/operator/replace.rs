@@ -13,12 +13,14 @@ impl AVFrame {
     }
 
     /// Fill the frame with data.
-    /// Data format: RGB|RGB|RGB|...
+    /// Data format: RGB|RGB|RGB|..., one byte per pixel for gray and rows of packed bits for mono.
     pub fn fill_data(&mut self, data: &[u8], pix_fmt: AVPixelFormat) {
-        let width = if pix_fmt == AVPixelFormat::Gray8 {
-            unsafe { *self.inner }.width
-        } else {
-            unsafe { *self.inner }.width * 3
+        let width = match pix_fmt {
+            AVPixelFormat::Gray8 => unsafe { *self.inner }.width,
+            AVPixelFormat::Monoblack | AVPixelFormat::Monowhite => {
+                (unsafe { *self.inner }.width + 7) / 8
+            }
+            _ => unsafe { *self.inner }.width * 3,
         };
         let height = unsafe { *self.inner }.height;
         let line_size = self.linesize[0];
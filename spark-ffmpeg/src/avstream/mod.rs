@@ -5,4 +5,9 @@ impl AVStream {
     pub fn codec_id(&self) -> AVCodecID {
         unsafe { AVCodecID::try_from((*self.codecpar).codec_id).unwrap() }
     }
+
+    /// Samples per second of an audio stream, 0 for other streams.
+    pub fn sample_rate(&self) -> i32 {
+        unsafe { (*self.codecpar).sample_rate }
+    }
 }
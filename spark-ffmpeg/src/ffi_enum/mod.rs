@@ -1,5 +1,6 @@
 include!(concat!(env!("OUT_DIR"), "/codec_id.rs"));
 include!(concat!(env!("OUT_DIR"), "/pixel.rs"));
+include!(concat!(env!("OUT_DIR"), "/sample_format.rs"));
 
 /*use num_enum::TryFromPrimitive;
 
@@ -17,6 +17,7 @@ pub mod avstream;
 pub mod ffi_enum;
 pub mod pixel;
 pub mod sws;
+pub mod swr;
 
 pub trait DeepClone {
     fn deep_clone(&self) -> anyhow::Result<Self>
@@ -0,0 +1,7 @@
+use spark_proc_macro::wrap_ffmpeg;
+
+mod new_swr;
+
+wrap_ffmpeg!(
+  SwrContext drop+ [swr_free]
+);
@@ -0,0 +1,63 @@
+use crate::avframe::AVFrame;
+use crate::ffi::{av_channel_layout_default, swr_alloc_set_opts2, swr_convert_frame, swr_init};
+use crate::ffi::{AVChannelLayout, SwrContext as SwrContextRaw};
+use crate::ffi_enum::AVSampleFormat;
+use crate::swr::SwrContext;
+use anyhow::{bail, Result};
+use std::ptr::{null, null_mut};
+
+impl SwrContext {
+    /// Converts audio between sample formats and sample rates, keeping the channel count.
+    pub fn new(
+        src_format: AVSampleFormat,
+        src_rate: i32,
+        dst_format: AVSampleFormat,
+        dst_rate: i32,
+        channels: i32,
+    ) -> Result<Self> {
+        let mut layout = AVChannelLayout::default();
+        unsafe {
+            av_channel_layout_default(&mut layout, channels);
+        }
+
+        let mut swr: *mut SwrContextRaw = null_mut();
+        ffmpeg! {
+            swr_alloc_set_opts2(
+                &mut swr,
+                &layout,
+                dst_format as i32,
+                dst_rate,
+                &layout,
+                src_format as i32,
+                src_rate,
+                0,
+                null_mut()
+            ) or "Failed to allocate SwrContext"
+        }
+        if swr.is_null() {
+            bail!("Failed to allocate SwrContext");
+        }
+
+        let context = SwrContext { inner: swr };
+        ffmpeg! {
+            swr_init(context.inner) or "Failed to initialize SwrContext"
+        }
+
+        Ok(context)
+    }
+
+    /// Converts `input` into `output`, whose format, sample rate and channel layout must be set.
+    /// An `output` without data is allocated by the resampler. Passing no input flushes the
+    /// samples still buffered in the resampler.
+    pub fn convert_frame(&self, output: &mut AVFrame, input: Option<&AVFrame>) -> Result<()> {
+        ffmpeg! {
+            swr_convert_frame(
+                self.inner,
+                output.inner,
+                input.map(|frame| frame.inner.cast_const()).unwrap_or(null())
+            ) or "Failed to resample audio frame"
+        }
+
+        Ok(())
+    }
+}
@@ -1,11 +1,18 @@
-use anyhow::Result;
+use crate::engine::manifest::ModelSpec;
+use crate::engine::session_pool::{PoolOptions, SessionPool};
+use anyhow::{ensure, Context, Result};
+use log::info;
+use ort::session::builder::PrepackedWeights;
 use ort::session::Session;
+use ort::value::ValueType;
 use std::ops::{Deref, DerefMut};
 use std::path::Path;
 
 pub struct OnnxSession {
     pub(crate) session: Session,
     pub(crate) executor: ExecutionProvider,
+    /// What the model was loaded as, empty for sessions created from a bare file.
+    pub(crate) spec: ModelSpec,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -15,6 +22,17 @@ pub enum ExecutionProvider {
     TensorRT(i32),
 }
 
+impl Default for ExecutionProvider {
+    /// The first GPU when built with the `cuda` feature, the CPU otherwise.
+    fn default() -> Self {
+        if cfg!(feature = "cuda") {
+            ExecutionProvider::CUDA(0)
+        } else {
+            ExecutionProvider::CPU
+        }
+    }
+}
+
 impl Deref for OnnxSession {
     type Target = Session;
 
@@ -31,8 +49,21 @@ impl DerefMut for OnnxSession {
 
 impl OnnxSession {
     pub fn new(url: impl AsRef<Path>, executor: ExecutionProvider) -> Result<Self> {
-        let session = Session::builder()?
-            .with_intra_threads(6)?
+        Self::load(url, executor, None)
+    }
+
+    /// Sessions created with the same `weights` keep one copy of the pre-packed weights of
+    /// the CPU kernels between them.
+    fn load(
+        url: impl AsRef<Path>,
+        executor: ExecutionProvider,
+        weights: Option<&PrepackedWeights>,
+    ) -> Result<Self> {
+        let mut builder = Session::builder()?.with_intra_threads(6)?;
+        if let Some(weights) = weights {
+            builder = builder.with_prepacked_weights(weights)?;
+        }
+        let session = builder
             .with_execution_providers([match executor {
                 ExecutionProvider::CUDA(id) => {
                     ort::execution_providers::CUDAExecutionProvider::default()
@@ -52,6 +83,92 @@ impl OnnxSession {
             }])?
             .commit_from_file(url)?;
 
-        Ok(OnnxSession { session, executor })
+        Ok(OnnxSession {
+            session,
+            executor,
+            spec: ModelSpec::default(),
+        })
+    }
+
+    /// Loads the model `spec` describes from `folder_path` and checks that its graph has the
+    /// declared inputs and outputs, so a wrong export fails here instead of in the first request.
+    pub fn with_spec(
+        folder_path: impl AsRef<Path>,
+        spec: ModelSpec,
+        executor: ExecutionProvider,
+    ) -> Result<Self> {
+        Self::load_spec(folder_path, spec, executor, None)
+    }
+
+    /// [`Self::with_spec`] `options.size` times. The sessions share their pre-packed weights,
+    /// the rest of the weights is loaded once per session, as ORT has no way to share it here.
+    pub fn pool(
+        folder_path: impl AsRef<Path>,
+        spec: ModelSpec,
+        executor: ExecutionProvider,
+        options: PoolOptions,
+    ) -> Result<SessionPool<Self>> {
+        ensure!(options.size > 0, "A pool of {} needs sessions", spec.file);
+        let weights = PrepackedWeights::new();
+        let sessions = (0..options.size)
+            .map(|_| Self::load_spec(&folder_path, spec.clone(), executor, Some(&weights)))
+            .collect::<Result<Vec<_>>>()?;
+        info!("Loaded {} sessions of {}", options.size, spec.file);
+
+        Ok(SessionPool::new(spec.file, sessions, options.wait_timeout))
+    }
+
+    fn load_spec(
+        folder_path: impl AsRef<Path>,
+        spec: ModelSpec,
+        executor: ExecutionProvider,
+        weights: Option<&PrepackedWeights>,
+    ) -> Result<Self> {
+        let path = folder_path.as_ref().join(&spec.file);
+        let mut session = Self::load(&path, executor, weights)
+            .with_context(|| format!("Failed to load model {}", path.display()))?;
+
+        let inputs = session
+            .inputs
+            .iter()
+            .map(|input| (input.name.clone(), tensor_shape(&input.input_type)))
+            .collect::<Vec<_>>();
+        let outputs = session
+            .outputs
+            .iter()
+            .map(|output| (output.name.clone(), tensor_shape(&output.output_type)))
+            .collect::<Vec<_>>();
+        spec.validate(&inputs, &outputs)?;
+        info!("Model {} matches its manifest", path.display());
+
+        session.spec = spec;
+        Ok(session)
+    }
+
+    pub fn spec(&self) -> &ModelSpec {
+        &self.spec
+    }
+
+    /// Shape the graph declares for its `index`th input, -1 for dynamic dimensions.
+    pub(crate) fn input_shape(&self, index: usize) -> Option<Vec<i64>> {
+        self.inputs
+            .get(index)
+            .map(|input| tensor_shape(&input.input_type))
+    }
+
+    /// Shape the graph declares for the output used as `role`, -1 for dynamic dimensions.
+    pub(crate) fn output_shape(&self, role: &str) -> Option<Vec<i64>> {
+        let name = self.spec.output(role).ok()?;
+        self.outputs
+            .iter()
+            .find(|output| output.name == name)
+            .map(|output| tensor_shape(&output.output_type))
+    }
+}
+
+fn tensor_shape(value_type: &ValueType) -> Vec<i64> {
+    match value_type {
+        ValueType::Tensor { shape, .. } => shape.to_vec(),
+        _ => Vec::new(),
     }
 }
@@ -0,0 +1,248 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Optional file in a model folder describing the models in it.
+pub const MANIFEST_FILE_NAME: &str = "manifest.toml";
+
+/// What the models of a folder look like, one table per model, e.g.
+///
+/// ```toml
+/// [yolo_detect]
+/// file = "road.onnx"
+/// input_size = [640, 640]
+/// classes = ["highway", "sidewalk"]
+/// inputs.images = { name = "images", shape = [1, 3, 640, 640] }
+/// outputs.boxes = { name = "output0", shape = [1, 6, -1] }
+/// ```
+///
+/// Whatever a table leaves out is taken from the defaults of the session loading the model,
+/// which are the file and tensor names of the usual exports.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ModelManifest {
+    pub models: BTreeMap<String, ModelSpec>,
+}
+
+/// One model of a [`ModelManifest`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModelSpec {
+    /// Path of the ONNX file, relative to the folder.
+    pub file: String,
+    /// `[width, height]` the image input is scaled to.
+    pub input_size: Option<[usize; 2]>,
+    /// Per channel mean and standard deviation of the pixels scaled to `[0, 1]`.
+    pub mean: Option<[f32; 3]>,
+    pub std: Option<[f32; 3]>,
+    /// Class names in class id order.
+    pub classes: Vec<String>,
+    /// Inputs and outputs by the role the session uses them for.
+    pub inputs: BTreeMap<String, TensorSpec>,
+    pub outputs: BTreeMap<String, TensorSpec>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TensorSpec {
+    /// Name of the tensor in the ONNX graph.
+    pub name: String,
+    /// Expected shape, -1 for dimensions of any size. Not checked when unset.
+    #[serde(default)]
+    pub shape: Option<Vec<i64>>,
+}
+
+impl TensorSpec {
+    pub fn new(name: &str, shape: Option<Vec<i64>>) -> Self {
+        Self {
+            name: name.to_string(),
+            shape,
+        }
+    }
+}
+
+impl ModelManifest {
+    /// Reads the manifest of `folder_path`, an empty one when the folder has none.
+    pub fn load(folder_path: impl AsRef<Path>) -> Result<Self> {
+        let path = folder_path.as_ref().join(MANIFEST_FILE_NAME);
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read model manifest {}", path.display()))?;
+        Self::parse(&content).with_context(|| format!("Invalid model manifest {}", path.display()))
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+
+    /// The model called `key`, completed with `defaults`.
+    pub fn spec(&self, key: &str, defaults: ModelSpec) -> ModelSpec {
+        match self.models.get(key) {
+            Some(spec) => spec.clone().or(defaults),
+            None => defaults,
+        }
+    }
+}
+
+impl ModelSpec {
+    /// Fills everything this spec leaves out from `defaults`.
+    pub fn or(mut self, defaults: ModelSpec) -> Self {
+        if self.file.is_empty() {
+            self.file = defaults.file;
+        }
+        self.input_size = self.input_size.or(defaults.input_size);
+        self.mean = self.mean.or(defaults.mean);
+        self.std = self.std.or(defaults.std);
+        if self.classes.is_empty() {
+            self.classes = defaults.classes;
+        }
+        for (role, tensor) in defaults.inputs {
+            self.inputs.entry(role).or_insert(tensor);
+        }
+        for (role, tensor) in defaults.outputs {
+            self.outputs.entry(role).or_insert(tensor);
+        }
+
+        self
+    }
+
+    /// Graph name of the input used as `role`.
+    pub fn input(&self, role: &str) -> Result<&str> {
+        self.inputs
+            .get(role)
+            .map(|tensor| tensor.name.as_str())
+            .ok_or_else(|| anyhow!("Model {} has no {} input", self.file, role))
+    }
+
+    /// Graph name of the output used as `role`.
+    pub fn output(&self, role: &str) -> Result<&str> {
+        self.outputs
+            .get(role)
+            .map(|tensor| tensor.name.as_str())
+            .ok_or_else(|| anyhow!("Model {} has no {} output", self.file, role))
+    }
+
+    /// `(width, height)` of the image input.
+    pub fn input_size(&self) -> Result<(usize, usize)> {
+        self.input_size
+            .map(|[width, height]| (width, height))
+            .ok_or_else(|| anyhow!("Model {} has no input size", self.file))
+    }
+
+    /// Mean and standard deviation, no shift and no scaling when unset.
+    pub fn normalisation(&self) -> ([f32; 3], [f32; 3]) {
+        (self.mean.unwrap_or([0.0; 3]), self.std.unwrap_or([1.0; 3]))
+    }
+
+    /// Checks the spec against the inputs and outputs of the loaded graph, given by name with
+    /// their shape, -1 for dynamic dimensions. Every mismatch is reported at once.
+    pub fn validate(
+        &self,
+        graph_inputs: &[(String, Vec<i64>)],
+        graph_outputs: &[(String, Vec<i64>)],
+    ) -> Result<()> {
+        let mut problems = Vec::new();
+        if let Some([width, height]) = self.input_size {
+            if width == 0 || height == 0 {
+                problems.push(format!("input_size {}x{} is empty", width, height));
+            }
+        }
+        if let Some(std) = self.std {
+            if std.iter().any(|value| *value == 0.0 || !value.is_finite()) {
+                problems.push(format!("std {:?} must be finite and non-zero", std));
+            }
+        }
+
+        for (kind, declared, graph) in [
+            ("input", &self.inputs, graph_inputs),
+            ("output", &self.outputs, graph_outputs),
+        ] {
+            for (role, tensor) in declared {
+                let Some((_, shape)) = graph.iter().find(|(name, _)| *name == tensor.name) else {
+                    let names = graph
+                        .iter()
+                        .map(|(name, _)| name.as_str())
+                        .collect::<Vec<_>>();
+                    problems.push(format!(
+                        "{} {} ({}) is not in the graph, which has {:?}",
+                        kind, tensor.name, role, names
+                    ));
+                    continue;
+                };
+
+                let Some(expected) = &tensor.shape else {
+                    continue;
+                };
+                let matches = expected.len() == shape.len()
+                    && expected.iter().zip(shape).all(|(&expected, &actual)| {
+                        expected < 0 || actual < 0 || expected == actual
+                    });
+                if !matches {
+                    problems.push(format!(
+                        "{} {} ({}) has shape {:?}, expected {:?}",
+                        kind, tensor.name, role, shape, expected
+                    ));
+                }
+            }
+        }
+
+        if !problems.is_empty() {
+            bail!(
+                "Model {} does not match its manifest: {}",
+                self.file,
+                problems.join("; ")
+            );
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_manifest_completes_and_validates() {
+    let manifest = ModelManifest::parse(
+        r#"
+        [yolo_detect]
+        file = "road.onnx"
+        classes = ["highway", "sidewalk"]
+        outputs.boxes = { name = "output0", shape = [1, 6, -1] }
+        "#,
+    )
+    .unwrap();
+    let defaults = ModelSpec {
+        file: "yolo_detect.onnx".to_string(),
+        input_size: Some([640, 640]),
+        inputs: BTreeMap::from([(
+            "images".to_string(),
+            TensorSpec::new("images", Some(vec![1, 3, 640, 640])),
+        )]),
+        outputs: BTreeMap::from([("boxes".to_string(), TensorSpec::new("output0", None))]),
+        ..ModelSpec::default()
+    };
+
+    let spec = manifest.spec("yolo_detect", defaults.clone());
+    assert_eq!(spec.file, "road.onnx");
+    assert_eq!(spec.input_size().unwrap(), (640, 640));
+    assert_eq!(spec.classes.len(), 2);
+    assert_eq!(spec.input("images").unwrap(), "images");
+    assert_eq!(spec.output("boxes").unwrap(), "output0");
+    assert!(spec.output("prototypes").is_err());
+    assert_eq!(manifest.spec("yolo_seg", defaults.clone()), defaults);
+
+    let inputs = vec![("images".to_string(), vec![-1, 3, 640, 640])];
+    spec.validate(&inputs, &[("output0".to_string(), vec![1, 6, 8400])])
+        .unwrap();
+
+    // Every problem ends up in the one error
+    let error = spec
+        .validate(&[], &[("output0".to_string(), vec![1, 84, 8400])])
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("input images (images) is not in the graph"));
+    assert!(error.contains("has shape [1, 84, 8400], expected [1, 6, -1]"));
+
+    assert!(ModelManifest::parse("[yolo_detect]\nfiles = \"typo.onnx\"").is_err());
+}
@@ -1,2 +1,4 @@
 pub(crate) mod entity;
 pub mod inference_engine;
+pub mod manifest;
+pub mod session_pool;
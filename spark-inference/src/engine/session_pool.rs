@@ -0,0 +1,290 @@
+use anyhow::{bail, Result};
+use log::{debug, warn};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::Thread;
+use std::time::{Duration, Instant};
+
+/// How many sessions of a model run side by side and how long a request waits for one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolOptions {
+    pub size: usize,
+    pub wait_timeout: Duration,
+}
+
+impl Default for PoolOptions {
+    /// One session, which serialises every request like a plain mutex.
+    fn default() -> Self {
+        Self {
+            size: 1,
+            wait_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Sessions of one model, checked out by one request at a time each.
+/// Waiters are served in no particular order, every returned session wakes all of them.
+pub struct SessionPool<T> {
+    name: String,
+    state: Mutex<PoolState<T>>,
+    size: usize,
+    wait_timeout: Duration,
+    metrics: PoolMetrics,
+}
+
+struct PoolState<T> {
+    idle: Vec<T>,
+    waiters: VecDeque<Waker>,
+}
+
+#[derive(Default)]
+struct PoolMetrics {
+    checkouts: AtomicU64,
+    timeouts: AtomicU64,
+    wait_micros: AtomicU64,
+    max_wait_micros: AtomicU64,
+}
+
+/// Counters of a pool since it was created.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PoolStats {
+    pub name: String,
+    pub size: usize,
+    pub in_use: usize,
+    pub waiting: usize,
+    pub checkouts: u64,
+    pub timeouts: u64,
+    pub mean_wait_ms: f64,
+    pub max_wait_ms: f64,
+}
+
+impl<T> SessionPool<T> {
+    /// `name` identifies the pool in errors, logs and stats, usually the model file.
+    pub fn new(name: impl Into<String>, sessions: Vec<T>, wait_timeout: Duration) -> Self {
+        Self {
+            name: name.into(),
+            size: sessions.len(),
+            state: Mutex::new(PoolState {
+                idle: sessions,
+                waiters: VecDeque::new(),
+            }),
+            wait_timeout,
+            metrics: PoolMetrics::default(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Blocks until a session is free, at most for the wait timeout of the pool.
+    pub fn checkout(&self) -> Result<PooledSession<'_, T>> {
+        let started = Instant::now();
+        let deadline = started + self.wait_timeout;
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        loop {
+            if let Some(session) = self.try_take(&waker) {
+                return Ok(self.checked_out(session, started));
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                self.state
+                    .lock()
+                    .waiters
+                    .retain(|waiting| !waiting.will_wake(&waker));
+                self.metrics.timeouts.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "No {} session became free within {:?}",
+                    self.name, self.wait_timeout
+                );
+                bail!(
+                    "Timed out after {:?} waiting for a free {} session, all {} are busy",
+                    self.wait_timeout,
+                    self.name,
+                    self.size
+                );
+            }
+            std::thread::park_timeout(deadline - now);
+        }
+    }
+
+    /// Resolves once a session is free. The future does not time out by itself, wrap it in the
+    /// timeout of the runtime polling it, e.g. with [`Self::wait_timeout`].
+    pub fn checkout_async(&self) -> Checkout<'_, T> {
+        Checkout {
+            pool: self,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn wait_timeout(&self) -> Duration {
+        self.wait_timeout
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let (idle, waiting) = {
+            let state = self.state.lock();
+            (state.idle.len(), state.waiters.len())
+        };
+        let checkouts = self.metrics.checkouts.load(Ordering::Relaxed);
+        let wait_ms = self.metrics.wait_micros.load(Ordering::Relaxed) as f64 / 1000.0;
+
+        PoolStats {
+            name: self.name.clone(),
+            size: self.size,
+            in_use: self.size - idle,
+            waiting,
+            checkouts,
+            timeouts: self.metrics.timeouts.load(Ordering::Relaxed),
+            mean_wait_ms: if checkouts == 0 {
+                0.0
+            } else {
+                wait_ms / checkouts as f64
+            },
+            max_wait_ms: self.metrics.max_wait_micros.load(Ordering::Relaxed) as f64 / 1000.0,
+        }
+    }
+
+    /// A free session, or `None` after registering `waker` to hear about the next one.
+    fn try_take(&self, waker: &Waker) -> Option<T> {
+        let mut state = self.state.lock();
+        match state.idle.pop() {
+            Some(session) => Some(session),
+            None => {
+                if !state.waiters.iter().any(|waiting| waiting.will_wake(waker)) {
+                    state.waiters.push_back(waker.clone());
+                }
+                None
+            }
+        }
+    }
+
+    fn checked_out(&self, session: T, started: Instant) -> PooledSession<'_, T> {
+        let waited = started.elapsed();
+        let micros = waited.as_micros() as u64;
+        self.metrics.checkouts.fetch_add(1, Ordering::Relaxed);
+        self.metrics
+            .wait_micros
+            .fetch_add(micros, Ordering::Relaxed);
+        self.metrics
+            .max_wait_micros
+            .fetch_max(micros, Ordering::Relaxed);
+        if !waited.is_zero() {
+            debug!("Waited {:?} for a {} session", waited, self.name);
+        }
+
+        PooledSession {
+            pool: self,
+            session: Some(session),
+        }
+    }
+
+    fn give_back(&self, session: T) {
+        let waiters = {
+            let mut state = self.state.lock();
+            state.idle.push(session);
+            std::mem::take(&mut state.waiters)
+        };
+        waiters.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// A session taken out of its pool, returned when dropped.
+pub struct PooledSession<'a, T> {
+    pool: &'a SessionPool<T>,
+    session: Option<T>,
+}
+
+impl<T> Deref for PooledSession<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.session
+            .as_ref()
+            .expect("Session is only taken on drop")
+    }
+}
+
+impl<T> DerefMut for PooledSession<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.session
+            .as_mut()
+            .expect("Session is only taken on drop")
+    }
+}
+
+impl<T> Drop for PooledSession<'_, T> {
+    fn drop(&mut self) {
+        if let Some(session) = self.session.take() {
+            self.pool.give_back(session);
+        }
+    }
+}
+
+/// Future of [`SessionPool::checkout_async`].
+pub struct Checkout<'a, T> {
+    pool: &'a SessionPool<T>,
+    started: Instant,
+}
+
+impl<'a, T> Future for Checkout<'a, T> {
+    type Output = PooledSession<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.pool.try_take(cx.waker()) {
+            Some(session) => Poll::Ready(self.pool.checked_out(session, self.started)),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Lets a blocked [`SessionPool::checkout`] wait on the same wakers as the futures.
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+#[test]
+fn test_pool_checkout_and_timeout() {
+    let pool = SessionPool::new("test.onnx", vec![1, 2], Duration::from_millis(20));
+    let first = pool.checkout().unwrap();
+    let mut second = pool.checkout().unwrap();
+    *second += 10;
+    let changed = *second;
+    assert_eq!(pool.stats().in_use, 2);
+
+    // Both are out, so the third request waits out the timeout
+    let error = pool.checkout().err().unwrap().to_string();
+    assert!(error.contains("all 2 are busy"));
+
+    let mut waiting = Box::pin(pool.checkout_async());
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(waiting.as_mut().poll(&mut cx).is_pending());
+    assert_eq!(pool.stats().waiting, 1);
+    drop(second);
+    match waiting.as_mut().poll(&mut cx) {
+        Poll::Ready(session) => assert_eq!(*session, changed),
+        Poll::Pending => panic!("A returned session was not handed out"),
+    }
+    drop(first);
+
+    let stats = pool.stats();
+    assert_eq!((stats.in_use, stats.waiting), (0, 0));
+    assert_eq!((stats.checkouts, stats.timeouts), (3, 1));
+    assert!(stats.max_wait_ms >= stats.mean_wait_ms);
+}
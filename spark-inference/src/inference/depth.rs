@@ -0,0 +1,247 @@
+use crate::engine::inference_engine::{ExecutionProvider, OnnxSession};
+use crate::engine::manifest::{ModelManifest, ModelSpec, TensorSpec};
+use crate::engine::session_pool::{PoolOptions, PoolStats, SessionPool};
+use crate::utils::letterbox::Letterbox;
+use crate::utils::tensor::normalise_pixel_mean;
+use anyhow::{ensure, Result};
+use log::{debug, info};
+use ndarray::{s, Array2};
+use ort::inputs;
+use ort::value::Tensor;
+use serde::{Deserialize, Serialize};
+use spark_media::Image;
+use std::collections::BTreeMap;
+use std::ops::Deref;
+use std::path::Path;
+
+/// Side length of the square frames the depth model takes unless its manifest says otherwise,
+/// the 37 patches of 14 pixels Depth Anything is trained on.
+const DEPTH_INPUT_SIZE: usize = 518;
+/// Per channel mean and standard deviation Depth Anything and MiDaS normalise the pixels with.
+const PIXEL_MEAN: [f32; 3] = [0.485, 0.456, 0.406];
+const PIXEL_STD: [f32; 3] = [0.229, 0.224, 0.225];
+/// Pixels around a sampled point whose median is taken, so single noisy pixels do not count.
+const SAMPLE_RADIUS: usize = 2;
+
+/// Manifest key of the depth model.
+pub const DEPTH_MODEL: &str = "depth";
+
+/// What the values of the depth model stand for.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "unit", rename_all = "lowercase", deny_unknown_fields)]
+pub enum DepthOutput {
+    /// Metric depth, e.g. the outdoor models of Depth Anything V2.
+    #[default]
+    Meters,
+    /// Relative inverse depth as MiDaS and the relative Depth Anything models output it,
+    /// turned into meters as `scale / value`. The scale is the disparity one meter away,
+    /// which depends on the model and has to be calibrated with the camera.
+    Disparity { scale: f32 },
+}
+
+impl DepthOutput {
+    pub fn to_meters(self, value: f32) -> f32 {
+        match self {
+            DepthOutput::Meters => value,
+            DepthOutput::Disparity { scale } if value > 0.0 => scale / value,
+            DepthOutput::Disparity { .. } => f32::INFINITY,
+        }
+    }
+}
+
+/// Estimated distance of every pixel from the camera, in meters, row by row.
+#[derive(Debug, Clone)]
+pub struct DepthMap {
+    meters: Array2<f32>,
+}
+
+impl DepthMap {
+    /// `meters` holds `height` rows of `width` values.
+    pub fn new(meters: Array2<f32>) -> Self {
+        Self { meters }
+    }
+
+    /// `(width, height)` of the map.
+    pub fn size(&self) -> (usize, usize) {
+        let (height, width) = self.meters.dim();
+        (width, height)
+    }
+
+    pub fn meters(&self) -> &Array2<f32> {
+        &self.meters
+    }
+
+    /// Meters at `(x, y)`, the median of the pixels around it. None outside the map
+    /// or when none of them holds a usable depth.
+    pub fn sample(&self, x: f32, y: f32) -> Option<f32> {
+        let (width, height) = self.size();
+        if !(x >= 0.0 && y >= 0.0 && x < width as f32 && y < height as f32) {
+            return None;
+        }
+
+        let (column, row) = (x as usize, y as usize);
+        let mut values = self
+            .meters
+            .slice(s![
+                row.saturating_sub(SAMPLE_RADIUS)..(row + SAMPLE_RADIUS + 1).min(height),
+                column.saturating_sub(SAMPLE_RADIUS)..(column + SAMPLE_RADIUS + 1).min(width)
+            ])
+            .iter()
+            .copied()
+            .filter(|meters| meters.is_finite() && *meters > 0.0)
+            .collect::<Vec<_>>();
+        if values.is_empty() {
+            return None;
+        }
+
+        let middle = values.len() / 2;
+        let (_, median, _) = values.select_nth_unstable_by(middle, f32::total_cmp);
+        Some(*median)
+    }
+}
+
+pub trait DepthEstimation {
+    /// The depth map of `image`, stretched over `out_size` (`(width, height)`) so that it lines
+    /// up with masks of that size, the image size when unset.
+    fn estimate_depth(&self, image: Image, out_size: Option<(usize, usize)>) -> Result<DepthMap>;
+
+    /// Usage of the sessions behind the model, empty for backends without any.
+    fn pool_stats(&self) -> Vec<PoolStats> {
+        Vec::new()
+    }
+}
+
+/// A monocular depth model like Depth Anything or MiDaS, taking one letterboxed frame.
+pub struct DepthSession {
+    sessions: SessionPool<OnnxSession>,
+    /// `(width, height)` frames are letterboxed to.
+    input_size: (usize, usize),
+    output: DepthOutput,
+}
+
+impl DepthSession {
+    /// Runs on [`ExecutionProvider::default`].
+    pub fn new(folder_path: impl AsRef<Path>, output: DepthOutput) -> Result<Self> {
+        Self::with_provider(folder_path, output, ExecutionProvider::default())
+    }
+
+    /// A single session on `provider`.
+    pub fn with_provider(
+        folder_path: impl AsRef<Path>,
+        output: DepthOutput,
+        provider: ExecutionProvider,
+    ) -> Result<Self> {
+        Self::with_pool(folder_path, output, provider, PoolOptions::default())
+    }
+
+    /// Loads `pool.size` sessions of `depth.onnx`, or of the `depth` model of the folder's
+    /// manifest, from `folder_path`. Its values are read as `output` says.
+    pub fn with_pool(
+        folder_path: impl AsRef<Path>,
+        output: DepthOutput,
+        provider: ExecutionProvider,
+        pool: PoolOptions,
+    ) -> Result<Self> {
+        let spec = ModelManifest::load(&folder_path)?.spec(
+            DEPTH_MODEL,
+            ModelSpec {
+                file: "depth.onnx".to_string(),
+                input_size: Some([DEPTH_INPUT_SIZE; 2]),
+                mean: Some(PIXEL_MEAN),
+                std: Some(PIXEL_STD),
+                inputs: BTreeMap::from([("image".to_string(), TensorSpec::new("image", None))]),
+                outputs: BTreeMap::from([("depth".to_string(), TensorSpec::new("depth", None))]),
+                ..ModelSpec::default()
+            },
+        );
+        if let DepthOutput::Disparity { scale } = output {
+            ensure!(
+                scale > 0.0,
+                "Disparity scale of {} must be positive, got {}",
+                spec.file,
+                scale
+            );
+        }
+
+        let input_size = spec.input_size()?;
+        let sessions = OnnxSession::pool(folder_path, spec, provider, pool)?;
+        info!("Depth Inference Session created");
+
+        Ok(Self {
+            sessions,
+            input_size,
+            output,
+        })
+    }
+}
+
+impl Deref for DepthSession {
+    type Target = SessionPool<OnnxSession>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.sessions
+    }
+}
+
+impl DepthEstimation for DepthSession {
+    fn estimate_depth(
+        &self,
+        mut image: Image,
+        out_size: Option<(usize, usize)>,
+    ) -> Result<DepthMap> {
+        let (width, height) = self.input_size;
+        let letterbox = Letterbox::apply(&mut image, self.input_size)?;
+        let out_size = out_size.unwrap_or(letterbox.source_size);
+
+        let output = {
+            let mut guard = self.checkout()?;
+            let (mean, std) = guard.spec.normalisation();
+            let depth = guard.spec.output("depth")?.to_string();
+            let tensor =
+                normalise_pixel_mean(image.raw_data()?.as_slice(), width, height, mean, std)?;
+            let outputs = guard.run(inputs![Tensor::from_array(tensor)?])?;
+            debug!("Finish running depth model");
+
+            outputs[depth.as_str()]
+                .try_extract_array::<f32>()?
+                .into_owned()
+        };
+
+        // `[1, H, W]` or `[1, 1, H, W]`, at the input resolution or below it
+        let shape = output.shape().to_vec();
+        ensure!(
+            shape.len() >= 2 && shape[..shape.len() - 2].iter().all(|&size| size == 1),
+            "Depth model returned a {:?} output for a single image",
+            shape
+        );
+        let output =
+            output.into_shape_with_order((shape[shape.len() - 2], shape[shape.len() - 1]))?;
+
+        let meters = output.mapv(|value| self.output.to_meters(value));
+        Ok(DepthMap::new(
+            letterbox.mask_to_source(meters.view(), out_size),
+        ))
+    }
+
+    fn pool_stats(&self) -> Vec<PoolStats> {
+        vec![self.stats()]
+    }
+}
+
+#[test]
+fn test_depth_map_samples_meters() {
+    assert_eq!(DepthOutput::Meters.to_meters(3.5), 3.5);
+    let disparity = DepthOutput::Disparity { scale: 10.0 };
+    assert_eq!(disparity.to_meters(5.0), 2.0);
+    assert_eq!(disparity.to_meters(0.0), f32::INFINITY);
+
+    // Ten rows getting closer towards the bottom, with one broken pixel
+    let mut meters = Array2::from_shape_fn((10, 8), |(row, _)| 20.0 - 2.0 * row as f32);
+    meters[[5, 4]] = f32::NAN;
+    let depth = DepthMap::new(meters);
+    assert_eq!(depth.size(), (8, 10));
+    assert_eq!(depth.sample(4.0, 5.0), Some(10.0));
+    assert_eq!(depth.sample(0.0, 9.5), Some(4.0));
+    assert_eq!(depth.sample(8.0, 5.0), None);
+    assert_eq!(depth.sample(-1.0, 5.0), None);
+}
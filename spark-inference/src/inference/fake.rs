@@ -0,0 +1,230 @@
+//! Deterministic stand-ins for the ONNX and TTS backends. They need no GPU, CUDA or model
+//! files and their output only depends on the input size, so the whole server can run in tests.
+
+use crate::inference::depth::{DepthEstimation, DepthMap};
+use crate::inference::sam::image_inference::{
+    SamDecoded, SamEmbedding, SamImageInference, SamMask, LOW_RES_MASK_SIZE,
+};
+use crate::inference::tts::tts_engine::{SpeechSynthesizer, TTS};
+use crate::inference::yolo::inference_yolo_detect::{YoloDetectInference, YoloDetectResult};
+use crate::utils::graph::SamPrompt;
+use anyhow::{anyhow, Result};
+use bitvec::prelude::BitVec;
+use ndarray::Array2;
+use spark_media::{Audio, Image};
+use std::path::Path;
+
+/// Returns the same detections for every image.
+/// Coordinates are fractions of the image size and get scaled to the image on each call.
+#[derive(Debug, Clone)]
+pub struct FakeDetector {
+    detections: Vec<YoloDetectResult>,
+}
+
+impl FakeDetector {
+    pub fn new(detections: Vec<YoloDetectResult>) -> Self {
+        Self { detections }
+    }
+}
+
+impl Default for FakeDetector {
+    /// A single sidewalk in front of the camera, reaching the bottom edge of the image.
+    fn default() -> Self {
+        Self::new(vec![YoloDetectResult {
+            score: vec![0.05, 0.9],
+            class_id: 1,
+            label: "sidewalk".to_string(),
+            x: 0.5,
+            y: 0.75,
+            width: 0.6,
+            height: 0.5,
+        }])
+    }
+}
+
+impl YoloDetectInference for FakeDetector {
+    fn inference_yolo(&self, image: Image, confidence: f32) -> Result<Vec<YoloDetectResult>> {
+        let (width, height) = image.get_size();
+        let (width, height) = (width as f32, height as f32);
+
+        Ok(self
+            .detections
+            .iter()
+            .filter(|detection| detection.score.iter().any(|&score| score > confidence))
+            .map(|detection| YoloDetectResult {
+                x: detection.x * width,
+                y: detection.y * height,
+                width: detection.width * width,
+                height: detection.height * height,
+                ..detection.clone()
+            })
+            .collect())
+    }
+}
+
+/// Segments exactly what was prompted: a box becomes a filled rectangle,
+/// a point a square of an eighth of the mask width around it, a negative point cuts such a square
+/// out of it. The one mask is fully confident.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FakeSegmenter;
+
+impl SamImageInference for FakeSegmenter {
+    fn encode(&self, image: Image) -> Result<SamEmbedding> {
+        Ok(SamEmbedding::empty(image.get_size()))
+    }
+
+    fn decode(
+        &self,
+        embedding: &SamEmbedding,
+        prompt: &SamPrompt<f32>,
+        _mask_input: Option<&Array2<f32>>,
+        out_put_size: Option<(i32, i32)>,
+    ) -> Result<SamDecoded> {
+        let image_size = embedding.image_size();
+        let (width, height) = out_put_size.unwrap_or(image_size);
+        let (width, height) = (width as usize, height as usize);
+        let scale_x = width as f32 / image_size.0 as f32;
+        let scale_y = height as f32 / image_size.1 as f32;
+        let half_point = width as f32 / 16.0;
+
+        let mut mask = BitVec::repeat(false, width * height);
+        let mut fill = |x: f32, y: f32, half_width: f32, half_height: f32, value: bool| {
+            let clamp_x = |x: f32| (x.round().max(0.0) as usize).min(width);
+            let clamp_y = |y: f32| (y.round().max(0.0) as usize).min(height);
+            for row in clamp_y(y - half_height)..clamp_y(y + half_height) {
+                let start = row * width;
+                mask[start + clamp_x(x - half_width)..start + clamp_x(x + half_width)].fill(value);
+            }
+        };
+
+        // The box and positive points are filled, negative points cut a hole into them
+        if let Some(boxes) = &prompt.boxes {
+            fill(
+                boxes.x * scale_x,
+                boxes.y * scale_y,
+                boxes.width * scale_x / 2.0,
+                boxes.height * scale_y / 2.0,
+                true,
+            );
+        }
+        for point in &prompt.positive {
+            fill(
+                point.x * scale_x,
+                point.y * scale_y,
+                half_point,
+                half_point,
+                true,
+            );
+        }
+        for point in &prompt.negative {
+            fill(
+                point.x * scale_x,
+                point.y * scale_y,
+                half_point,
+                half_point,
+                false,
+            );
+        }
+
+        // Sharp logits sampled from the mask, so it is perfectly stable
+        let low_res_logits =
+            Array2::from_shape_fn((LOW_RES_MASK_SIZE, LOW_RES_MASK_SIZE), |(row, column)| {
+                let row = row * height / LOW_RES_MASK_SIZE;
+                let column = column * width / LOW_RES_MASK_SIZE;
+                if mask[row * width + column] {
+                    10.0
+                } else {
+                    -10.0
+                }
+            });
+
+        Ok(SamDecoded {
+            masks: vec![SamMask {
+                mask,
+                iou: 1.0,
+                low_res_logits,
+            }],
+        })
+    }
+}
+
+/// Flat ground seen by a level camera 1.5 m above it with a vertical field of view of 60°:
+/// rows get closer towards the bottom edge, everything above the middle row is sky.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FakeDepth;
+
+/// Meters between the ground and the camera of [`FakeDepth`].
+const FAKE_CAMERA_HEIGHT: f32 = 1.5;
+/// Depth of the sky and of the ground too far away to tell apart from it.
+const FAKE_SKY_METERS: f32 = 100.0;
+
+impl DepthEstimation for FakeDepth {
+    fn estimate_depth(&self, image: Image, out_size: Option<(usize, usize)>) -> Result<DepthMap> {
+        let (image_width, image_height) = image.get_size();
+        let (width, height) = out_size.unwrap_or((image_width as usize, image_height as usize));
+        let horizon = height as f32 / 2.0;
+        let focal_length = horizon / 30_f32.to_radians().tan();
+
+        Ok(DepthMap::new(Array2::from_shape_fn(
+            (height, width),
+            |(row, _)| {
+                let below_horizon = row as f32 + 0.5 - horizon;
+                if below_horizon <= 0.0 {
+                    return FAKE_SKY_METERS;
+                }
+                (FAKE_CAMERA_HEIGHT * focal_length / below_horizon).min(FAKE_SKY_METERS)
+            },
+        )))
+    }
+}
+
+/// Produces silence, a twentieth of a second per character of the text.
+#[derive(Debug, Clone, Copy)]
+pub struct FakeSpeech {
+    sample_rate: u32,
+}
+
+impl FakeSpeech {
+    pub fn new(sample_rate: u32) -> Self {
+        Self { sample_rate }
+    }
+}
+
+impl Default for FakeSpeech {
+    fn default() -> Self {
+        Self::new(22050)
+    }
+}
+
+impl SpeechSynthesizer for FakeSpeech {
+    fn speak(&self, source: &str) -> Result<Audio> {
+        if source.is_empty() {
+            return Err(anyhow!("Empty source string"));
+        }
+
+        let samples = source.chars().count() * self.sample_rate as usize / 20;
+        Ok(Audio::new(vec![0.0; samples], self.sample_rate))
+    }
+}
+
+impl TTS for FakeSpeech {
+    fn new_zh(_folder: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::default())
+    }
+
+    fn new_en(_folder: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::default())
+    }
+}
+
+#[test]
+fn test_fake_speech_is_silent_wav() {
+    let wav = FakeSpeech::default().generate("hello").unwrap();
+    let reader = hound::WavReader::new(std::io::Cursor::new(wav)).unwrap();
+
+    assert_eq!(reader.spec().sample_rate, 22050);
+    assert_eq!(reader.len(), 5 * 22050 / 20);
+    assert!(reader
+        .into_samples::<f32>()
+        .all(|sample| sample.unwrap() == 0.0));
+}
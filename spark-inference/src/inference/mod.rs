@@ -1,3 +1,6 @@
+pub mod depth;
+pub mod fake;
 pub mod sam;
+pub mod tracker;
 pub mod tts;
 pub mod yolo;
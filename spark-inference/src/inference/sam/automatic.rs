@@ -0,0 +1,186 @@
+use crate::inference::sam::image_inference::{SamEmbedding, SamImageInference};
+use crate::utils::graph::{Point, SamPrompt};
+use anyhow::{ensure, Result};
+use bitvec::prelude::*;
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+
+/// How [`SamMaskGenerator::generate_masks`] prompts the decoder and which of its masks are kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaskGeneratorOptions {
+    /// The grid has this many points on each side, each of them one decoder run.
+    pub points_per_side: usize,
+    /// Masks with a lower predicted IoU are dropped.
+    pub min_iou: f32,
+    /// Masks whose stability score, the IoU between the masks at the logit threshold moved down
+    /// and up, is below this are dropped.
+    pub min_stability: f32,
+    /// How far the logit threshold is moved in both directions to measure the stability.
+    pub stability_offset: f32,
+    /// A mask overlapping an already kept one by more than this IoU is a duplicate of it.
+    pub duplicate_iou: f32,
+    /// Masks covering fewer pixels are dropped.
+    pub min_area: usize,
+}
+
+impl Default for MaskGeneratorOptions {
+    fn default() -> Self {
+        Self {
+            points_per_side: 32,
+            min_iou: 0.88,
+            min_stability: 0.95,
+            stability_offset: 1.0,
+            duplicate_iou: 0.7,
+            min_area: 0,
+        }
+    }
+}
+
+/// A mask found without a prompt from the caller.
+#[derive(Debug, Clone)]
+pub struct SamAutoMask {
+    pub mask: BitVec,
+    /// Number of pixels in the mask.
+    pub area: usize,
+    /// IoU the decoder predicted for the mask.
+    pub iou: f32,
+    pub stability: f32,
+    /// The grid point that prompted the mask, in coordinates of the encoded image.
+    pub point: Point<f32>,
+}
+
+/// Segments everything in an image by prompting the decoder with a grid of points.
+pub trait SamMaskGenerator: SamImageInference {
+    /// Decodes every point of the grid against `embedding` and keeps the confident and stable
+    /// masks, skipping those that mostly cover a mask with a higher predicted IoU.
+    /// The masks are `out_put_size` like the ones of [`SamImageInference::decode`], largest first.
+    /// The decoder runs `points_per_side` squared times, so the grid is better kept coarse.
+    fn generate_masks(
+        &self,
+        embedding: &SamEmbedding,
+        options: &MaskGeneratorOptions,
+        out_put_size: Option<(i32, i32)>,
+    ) -> Result<Vec<SamAutoMask>> {
+        ensure!(
+            options.points_per_side > 0,
+            "The point grid needs at least one point per side"
+        );
+
+        let mut candidates = Vec::new();
+        for point in point_grid(options.points_per_side, embedding.image_size()) {
+            let prompt = SamPrompt::from_point(point);
+            let decoded = self.decode(embedding, &prompt, None, out_put_size)?;
+            for mask in decoded.masks {
+                if mask.iou < options.min_iou {
+                    continue;
+                }
+                let stability = stability_score(&mask.low_res_logits, options.stability_offset);
+                let area = mask.mask.count_ones();
+                if stability < options.min_stability || area < options.min_area.max(1) {
+                    continue;
+                }
+
+                candidates.push(SamAutoMask {
+                    mask: mask.mask,
+                    area,
+                    iou: mask.iou,
+                    stability,
+                    point,
+                });
+            }
+        }
+
+        // The most confident of overlapping masks survives
+        candidates.sort_by(|a, b| b.iou.total_cmp(&a.iou));
+        let mut masks: Vec<SamAutoMask> = Vec::with_capacity(candidates.len());
+        for candidate in candidates {
+            let duplicate = masks
+                .iter()
+                .any(|kept| mask_iou(kept, &candidate) > options.duplicate_iou);
+            if !duplicate {
+                masks.push(candidate);
+            }
+        }
+        masks.sort_by_key(|mask| std::cmp::Reverse(mask.area));
+
+        Ok(masks)
+    }
+}
+
+impl<T: SamImageInference + ?Sized> SamMaskGenerator for T {}
+
+/// `points_per_side` squared points at the centres of equally sized cells covering the image,
+/// row by row.
+fn point_grid(points_per_side: usize, image_size: (i32, i32)) -> Vec<Point<f32>> {
+    let cell_width = image_size.0 as f32 / points_per_side as f32;
+    let cell_height = image_size.1 as f32 / points_per_side as f32;
+
+    (0..points_per_side)
+        .flat_map(|row| {
+            (0..points_per_side).map(move |column| Point {
+                x: (column as f32 + 0.5) * cell_width,
+                y: (row as f32 + 0.5) * cell_height,
+            })
+        })
+        .collect()
+}
+
+/// IoU of the mask thresholded at `offset` with the one thresholded at `-offset`.
+/// Close to 1 when the edges of the mask are sharp, the former always lies inside the latter.
+fn stability_score(logits: &Array2<f32>, offset: f32) -> f32 {
+    let inner = logits.iter().filter(|&&logit| logit > offset).count();
+    let outer = logits.iter().filter(|&&logit| logit > -offset).count();
+    if outer == 0 {
+        return 0.0;
+    }
+
+    inner as f32 / outer as f32
+}
+
+fn mask_iou(a: &SamAutoMask, b: &SamAutoMask) -> f32 {
+    let intersection = (a.mask.clone() & b.mask.as_bitslice()).count_ones();
+    let union = a.area + b.area - intersection;
+    if union == 0 {
+        return 0.0;
+    }
+
+    intersection as f32 / union as f32
+}
+
+#[test]
+fn test_generate_masks_over_grid() {
+    use crate::inference::fake::FakeSegmenter;
+
+    // The fake segments a square an eighth of the image wide around each point,
+    // four points per side do not overlap
+    let embedding = SamEmbedding::empty((64, 64));
+    let options = MaskGeneratorOptions {
+        points_per_side: 4,
+        ..MaskGeneratorOptions::default()
+    };
+    let masks = FakeSegmenter
+        .generate_masks(&embedding, &options, None)
+        .unwrap();
+    assert_eq!(masks.len(), 16);
+    assert!(masks
+        .iter()
+        .all(|mask| mask.area == 64 && mask.stability == 1.0));
+    assert_eq!((masks[0].point.x, masks[0].point.y), (8.0, 8.0));
+
+    // With twelve points per side every square overlaps its neighbours
+    let options = MaskGeneratorOptions {
+        points_per_side: 12,
+        duplicate_iou: 0.1,
+        ..MaskGeneratorOptions::default()
+    };
+    let masks = FakeSegmenter
+        .generate_masks(&embedding, &options, None)
+        .unwrap();
+    assert!(masks.len() < 12 * 12);
+    assert!(masks
+        .iter()
+        .enumerate()
+        .all(|(index, a)| masks[index + 1..].iter().all(|b| mask_iou(a, b) <= 0.1)));
+    assert!(masks.windows(2).all(|pair| pair[0].area >= pair[1].area));
+}
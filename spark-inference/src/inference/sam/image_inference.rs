@@ -1,67 +1,255 @@
 use crate::engine::inference_engine::{ExecutionProvider, OnnxSession};
+use crate::engine::manifest::{ModelManifest, ModelSpec, TensorSpec};
+use crate::engine::session_pool::{PoolOptions, PoolStats, SessionPool};
 use crate::utils::graph::SamPrompt;
-use crate::{INFERENCE_SAM, RUNNING_SAM_DEVICE};
-use anyhow::{anyhow, Result};
+use crate::utils::lru::LruCache;
+use crate::utils::tensor::normalise_pixel_mean;
+#[cfg(feature = "cuda")]
+use crate::{inference_sam, RUNNING_SAM_DEVICE};
+use anyhow::{anyhow, ensure, Result};
 use bitvec::prelude::BitVec;
+#[cfg(feature = "cuda")]
 use cudarc::driver::{CudaSlice, DevicePtr, LaunchConfig, PushKernelArg};
 use log::info;
-use ndarray::{array, s, Array1, Array2, Array4, Axis};
+use ndarray::{array, s, Array2, Array3, Array4, ArrayD, Ix2, IxDyn};
 use ort::inputs;
 use ort::io_binding::IoBinding;
+#[cfg(feature = "cuda")]
 use ort::memory::{AllocationDevice, Allocator, AllocatorType, MemoryInfo, MemoryType};
 use ort::session::SessionOutputs;
+#[cfg(feature = "cuda")]
 use ort::tensor::Shape;
-use ort::value::{Tensor, TensorRefMut};
-use parking_lot::Mutex;
+#[cfg(feature = "cuda")]
+use ort::value::TensorRefMut;
+use ort::value::{Tensor, TensorRef};
 use spark_media::filter::filter::AVFilter;
 use spark_media::Image;
 use std::ops::{Deref, DerefMut};
 use std::path::Path;
-use std::sync::LazyLock;
+use std::sync::Arc;
+
+/// Side length of the square frames the SAM encoder takes, unless its manifest says otherwise.
+const SAM_INPUT_SIZE: usize = 1024;
+/// Side length of the logits the decoder outputs next to the full size masks.
+pub const LOW_RES_MASK_SIZE: usize = 256;
+/// Per channel mean and standard deviation the SAM encoder expects the pixels normalised with.
+const PIXEL_MEAN: [f32; 3] = [0.485, 0.456, 0.406];
+const PIXEL_STD: [f32; 3] = [0.229, 0.224, 0.225];
+
+/// Manifest keys of the SAM models.
+pub const SAM_ENCODER_MODEL: &str = "image_encoder";
+pub const SAM_DECODER_MODEL: &str = "image_decoder";
+/// Encoder outputs the decoder takes as inputs of the same role.
+const EMBEDDING_ROLES: [&str; 3] = ["image_embed", "high_res_feats_0", "high_res_feats_1"];
+/// Decoder inputs describing the prompt, and its outputs.
+const PROMPT_ROLES: [&str; 5] = [
+    "point_coords",
+    "point_labels",
+    "mask_input",
+    "has_mask_input",
+    "orig_im_size",
+];
+const DECODER_OUTPUT_ROLES: [&str; 3] = ["masks", "iou_predictions", "low_res_masks"];
+
+/// Tensors named after the role they are used for, as the usual SAM 2 exports have them.
+fn tensors(roles: &[&str]) -> std::collections::BTreeMap<String, TensorSpec> {
+    roles
+        .iter()
+        .map(|role| (role.to_string(), TensorSpec::new(role, None)))
+        .collect()
+}
 
 pub trait SamImageInference {
+    /// Runs the image encoder. The embedding can be decoded against any number of times.
+    fn encode(&self, image: Image) -> Result<SamEmbedding>;
+
+    /// Runs the mask decoder for one prompt, given in coordinates of the encoded image.
+    /// `mask_input` is the low resolution logits of an earlier result for the same object,
+    /// which the decoder refines instead of starting over.
+    /// The masks are `out_put_size` (the image size when unset) row by row.
+    fn decode(
+        &self,
+        embedding: &SamEmbedding,
+        prompt: &SamPrompt<f32>,
+        mask_input: Option<&Array2<f32>>,
+        out_put_size: Option<(i32, i32)>,
+    ) -> Result<SamDecoded>;
+
+    /// Encodes the image once and decodes every group of prompts against it.
+    fn inference_frame_scored(
+        &self,
+        image: Image,
+        out_put_size: Option<(i32, i32)>,
+        prompts: Vec<Vec<SamPrompt<f32>>>,
+    ) -> Result<Vec<Vec<SamDecoded>>> {
+        let embedding = self.encode(image)?;
+        prompts
+            .iter()
+            .map(|prompts| {
+                prompts
+                    .iter()
+                    .map(|prompt| self.decode(&embedding, prompt, None, out_put_size))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Same as [`Self::inference_frame_scored`], keeping only the best mask of each prompt.
     fn inference_frame(
         &self,
         image: Image,
         out_put_size: Option<(i32, i32)>,
         prompts: Vec<Vec<SamPrompt<f32>>>,
-    ) -> Result<Vec<Vec<BitVec>>>;
+    ) -> Result<Vec<Vec<BitVec>>> {
+        let decoded = self.inference_frame_scored(image, out_put_size, prompts)?;
+        decoded
+            .into_iter()
+            .map(|decoded| {
+                decoded
+                    .into_iter()
+                    .map(|decoded| decoded.into_best().map(|best| best.mask))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Usage of the sessions behind the models, empty for backends without any.
+    fn pool_stats(&self) -> Vec<PoolStats> {
+        Vec::new()
+    }
+}
+
+/// One candidate mask of the decoder.
+#[derive(Debug, Clone)]
+pub struct SamMask {
+    /// Pixels whose logit is above 0.
+    pub mask: BitVec,
+    /// IoU the decoder predicts the mask to have with the object, its confidence.
+    pub iou: f32,
+    /// `LOW_RES_MASK_SIZE` squared logits, to be passed back as `mask_input`.
+    pub low_res_logits: Array2<f32>,
+}
+
+/// Every candidate the decoder produced for one prompt, sorted by predicted IoU, best first.
+#[derive(Debug, Clone)]
+pub struct SamDecoded {
+    pub masks: Vec<SamMask>,
+}
+
+impl SamDecoded {
+    pub fn best(&self) -> Option<&SamMask> {
+        self.masks.first()
+    }
+
+    pub fn into_best(self) -> Result<SamMask> {
+        self.masks
+            .into_iter()
+            .next()
+            .ok_or(anyhow!("SAM decoder returned no masks"))
+    }
+}
+
+/// Encoder output for one image, copied out of the session so it outlives the encoder lock.
+pub struct SamEmbedding {
+    image_size: (i32, i32),
+    image_embed: ArrayD<f32>,
+    high_res_feats_0: ArrayD<f32>,
+    high_res_feats_1: ArrayD<f32>,
+}
+
+impl SamEmbedding {
+    /// An embedding without features, for backends that do not run the decoder.
+    pub(crate) fn empty(image_size: (i32, i32)) -> Self {
+        Self {
+            image_size,
+            image_embed: ArrayD::zeros(IxDyn(&[0])),
+            high_res_feats_0: ArrayD::zeros(IxDyn(&[0])),
+            high_res_feats_1: ArrayD::zeros(IxDyn(&[0])),
+        }
+    }
+
+    /// Size of the image before it was scaled for the encoder.
+    pub fn image_size(&self) -> (i32, i32) {
+        self.image_size
+    }
 }
 
+/// Embeddings of recent images by whatever identifies them to the caller, e.g. a session id,
+/// so a client can re-prompt the same image without running the encoder again.
+pub type SamEmbeddingCache<K> = LruCache<K, Arc<SamEmbedding>>;
+
 pub struct SAMImageInferenceSession {
-    pub(super) image_encoder: Mutex<OnnxSession>,
-    pub(super) image_decoder: Mutex<OnnxSession>,
+    pub(super) image_encoder: SessionPool<OnnxSession>,
+    pub(super) image_decoder: SessionPool<OnnxSession>,
+    /// `(width, height)` of the frames the encoder takes.
+    pub(super) input_size: (usize, usize),
 }
 
 impl SAMImageInferenceSession {
+    /// Runs on [`ExecutionProvider::default`].
     pub fn new(folder_path: impl AsRef<Path>) -> Result<Self> {
-        let image_encoder = OnnxSession::new(
-            folder_path.as_ref().join("image_encoder.onnx"),
-            ExecutionProvider::CUDA(RUNNING_SAM_DEVICE),
-        )?;
-        let image_decoder = OnnxSession::new(
-            folder_path.as_ref().join("image_decoder.onnx"),
-            ExecutionProvider::CUDA(RUNNING_SAM_DEVICE),
-        )?;
+        Self::with_provider(folder_path, ExecutionProvider::default())
+    }
+
+    /// A single encoder and decoder session on `provider`.
+    pub fn with_provider(
+        folder_path: impl AsRef<Path>,
+        provider: ExecutionProvider,
+    ) -> Result<Self> {
+        Self::with_pool(folder_path, provider, PoolOptions::default())
+    }
+
+    /// Loads `pool.size` sessions each of `image_encoder.onnx` and `image_decoder.onnx`, or of
+    /// the `image_encoder` and `image_decoder` models of the folder's manifest, from `folder_path`.
+    /// An image is encoded and decoded on sessions checked out separately, so one request can
+    /// decode while the next one is encoded.
+    pub fn with_pool(
+        folder_path: impl AsRef<Path>,
+        provider: ExecutionProvider,
+        pool: PoolOptions,
+    ) -> Result<Self> {
+        let manifest = ModelManifest::load(&folder_path)?;
+        let encoder_spec = manifest.spec(
+            SAM_ENCODER_MODEL,
+            ModelSpec {
+                file: "image_encoder.onnx".to_string(),
+                input_size: Some([SAM_INPUT_SIZE; 2]),
+                mean: Some(PIXEL_MEAN),
+                std: Some(PIXEL_STD),
+                inputs: tensors(&["image"]),
+                outputs: tensors(&EMBEDDING_ROLES),
+                ..ModelSpec::default()
+            },
+        );
+        let decoder_spec = manifest.spec(
+            SAM_DECODER_MODEL,
+            ModelSpec {
+                file: "image_decoder.onnx".to_string(),
+                inputs: tensors(&[EMBEDDING_ROLES.as_slice(), &PROMPT_ROLES].concat()),
+                outputs: tensors(&DECODER_OUTPUT_ROLES),
+                ..ModelSpec::default()
+            },
+        );
+
+        let input_size = encoder_spec.input_size()?;
+        let image_encoder = OnnxSession::pool(&folder_path, encoder_spec, provider, pool)?;
+        let image_decoder = OnnxSession::pool(&folder_path, decoder_spec, provider, pool)?;
         info!("SAM Image Inference Session created");
 
         Ok(Self {
-            image_encoder: Mutex::new(image_encoder),
-            image_decoder: Mutex::new(image_decoder),
+            image_encoder,
+            image_decoder,
+            input_size,
         })
     }
 }
 
 impl SamImageInference for SAMImageInferenceSession {
-    fn inference_frame(
-        &self,
-        mut image: Image,
-        out_put_size: Option<(i32, i32)>,
-        prompts: Vec<Vec<SamPrompt<f32>>>,
-    ) -> Result<Vec<Vec<BitVec>>> {
+    fn encode(&self, mut image: Image) -> Result<SamEmbedding> {
         let (image, image_size) = {
+            let (width, height) = self.input_size;
             let filter = AVFilter::builder(image.pixel_format()?, image.get_size())?
-                .add_context("scale", "1024:1024")?
+                .add_context("scale", &format!("{}:{}", width, height))?
                 .add_context("format", "rgb24")?
                 .build()?;
 
@@ -70,59 +258,94 @@ impl SamImageInference for SAMImageInferenceSession {
             (image, image_size)
         };
 
-        let mut image_encoder = self.image_encoder.lock();
+        let mut image_encoder = self.image_encoder.checkout()?;
+        let [image_embed, high_res_feats_0, high_res_feats_1] =
+            EMBEDDING_ROLES.map(|role| image_encoder.spec.output(role).map(str::to_string));
+        let (image_embed, high_res_feats_0, high_res_feats_1) =
+            (image_embed?, high_res_feats_0?, high_res_feats_1?);
         let mut encoder_binding = image_encoder.create_binding()?;
         let encoder_output = self.inference_image_encoder(
             image.raw_data()?.deref(),
             &mut encoder_binding,
             image_encoder.deref_mut(),
         )?;
+        let feature = |name: &str| -> Result<ArrayD<f32>> {
+            Ok(encoder_output[name]
+                .try_extract_array::<f32>()?
+                .into_owned())
+        };
+
+        Ok(SamEmbedding {
+            image_size,
+            image_embed: feature(&image_embed)?,
+            high_res_feats_0: feature(&high_res_feats_0)?,
+            high_res_feats_1: feature(&high_res_feats_1)?,
+        })
+    }
 
-        let mut decoder = self.image_decoder.lock();
+    fn decode(
+        &self,
+        embedding: &SamEmbedding,
+        prompt: &SamPrompt<f32>,
+        mask_input: Option<&Array2<f32>>,
+        out_put_size: Option<(i32, i32)>,
+    ) -> Result<SamDecoded> {
+        let mut decoder = self.image_decoder.checkout()?;
+        let spec = decoder.spec.clone();
         let mut decoder_binding = decoder.create_binding()?;
-        decoder_binding.bind_input("image_embed", &encoder_output["image_embed"])?;
-        decoder_binding.bind_input("high_res_feats_0", &encoder_output["high_res_feats_0"])?;
-        decoder_binding.bind_input("high_res_feats_1", &encoder_output["high_res_feats_1"])?;
-
-        let mut back = vec![];
-        for prompt in prompts {
-            let mut back_inner = vec![];
-            for prompt in prompt {
-                let mask_decoder_output = self.inference_image_decoder(
-                    &prompt,
-                    image_size,
-                    out_put_size,
-                    &mut decoder_binding,
-                    decoder.deref_mut(),
-                )?;
-
-                let max_index = {
-                    let iou_predictions =
-                        mask_decoder_output["iou_predictions"].try_extract_array::<f32>()?;
-                    iou_predictions
-                        .iter()
-                        .enumerate()
-                        .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
-                        .ok_or(anyhow!("No max index found"))?
-                        .0
-                };
+        for (role, feature) in EMBEDDING_ROLES.into_iter().zip([
+            &embedding.image_embed,
+            &embedding.high_res_feats_0,
+            &embedding.high_res_feats_1,
+        ]) {
+            decoder_binding.bind_input(spec.input(role)?, &TensorRef::from_array_view(feature)?)?;
+        }
 
-                let pred_mask = mask_decoder_output["masks"].try_extract_array::<f32>()?;
+        let mask_decoder_output = self.inference_image_decoder(
+            prompt,
+            mask_input,
+            embedding.image_size,
+            out_put_size,
+            &spec,
+            &mut decoder_binding,
+            decoder.deref_mut(),
+        )?;
 
-                let mut temp = BitVec::with_capacity(pred_mask.len());
-                pred_mask
-                    .slice(s![0, max_index, .., ..])
+        let output = |role: &str| -> Result<_> {
+            Ok(mask_decoder_output[spec.output(role)?].try_extract_array::<f32>()?)
+        };
+        let iou_predictions = output("iou_predictions")?;
+        let pred_masks = output("masks")?;
+        let low_res_masks = output("low_res_masks")?;
+
+        let mut masks = iou_predictions
+            .iter()
+            .enumerate()
+            .map(|(index, &iou)| {
+                let mask = pred_masks
+                    .slice(s![0, index, .., ..])
                     .iter()
-                    .for_each(|x| {
-                        temp.push(*x > 0f32);
-                    });
-
-                back_inner.push(temp);
-            }
-            back.push(back_inner);
-        }
+                    .map(|&logit| logit > 0f32)
+                    .collect::<BitVec>();
+                let low_res_logits = low_res_masks
+                    .slice(s![0, index, .., ..])
+                    .into_dimensionality::<Ix2>()?
+                    .to_owned();
+
+                Ok(SamMask {
+                    mask,
+                    iou,
+                    low_res_logits,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        masks.sort_by(|a, b| b.iou.total_cmp(&a.iou));
+
+        Ok(SamDecoded { masks })
+    }
 
-        Ok(back)
+    fn pool_stats(&self) -> Vec<PoolStats> {
+        vec![self.image_encoder.stats(), self.image_decoder.stats()]
     }
 }
 
@@ -133,72 +356,73 @@ impl SAMImageInferenceSession {
         encoder_binding: &'a mut IoBinding,
         image_encoder: &'b mut OnnxSession,
     ) -> Result<SessionOutputs<'a, 'b>> {
-        static MEAN: LazyLock<CudaSlice<f32>> = LazyLock::new(|| {
-            let stream = INFERENCE_SAM.new_stream().unwrap();
-            stream.memcpy_stod(&[0.485, 0.456, 0.406]).unwrap()
-        });
-        static STD: LazyLock<CudaSlice<f32>> = LazyLock::new(|| {
-            let stream = INFERENCE_SAM.new_stream().unwrap();
-            stream.memcpy_stod(&[0.229, 0.224, 0.225]).unwrap()
-        });
-        let buffer = {
-            let stream = INFERENCE_SAM.new_stream()?;
-            stream.memcpy_stod(image.as_slice())?
-        };
-        let cfg = LaunchConfig::for_num_elems((image.len() / 3) as u32);
-
-        let (stream, tensor) = unsafe {
-            let image_size = image.len();
-            let stream = INFERENCE_SAM.new_stream()?;
-            let mut tensor = stream.alloc::<f32>(image_size)?;
-
-            let mut builder = stream.launch_builder(INFERENCE_SAM.normalise_pixel_mean());
-            builder.arg(&mut tensor);
-            builder.arg(&buffer);
-            builder.arg(MEAN.deref());
-            builder.arg(STD.deref());
-            builder.arg(&image_size);
-            builder.launch(cfg)?;
-
-            (stream, tensor)
-        };
-
+        let spec = image_encoder.spec.clone();
+        let (width, height) = self.input_size;
+        let (mean, std) = spec.normalisation();
         let session_outputs = match image_encoder.executor {
-            ExecutionProvider::CPU => {
-                let tensor = stream.memcpy_dtov(&tensor)?;
-                let tensor = Array4::from_shape_vec((1, 3, 1024, 1024), tensor)?;
+            #[cfg(feature = "cuda")]
+            ExecutionProvider::CUDA(_) | ExecutionProvider::TensorRT(_) => {
+                let cuda = inference_sam()?;
+                let (buffer, mean, std): (CudaSlice<u8>, CudaSlice<f32>, CudaSlice<f32>) = {
+                    let stream = cuda.new_stream()?;
+                    (
+                        stream.memcpy_stod(image.as_slice())?,
+                        stream.memcpy_stod(&mean)?,
+                        stream.memcpy_stod(&std)?,
+                    )
+                };
+                let cfg = LaunchConfig::for_num_elems((image.len() / 3) as u32);
+
+                let (stream, tensor) = unsafe {
+                    let image_size = image.len();
+                    let stream = cuda.new_stream()?;
+                    let mut tensor = stream.alloc::<f32>(image_size)?;
+
+                    let mut builder = stream.launch_builder(cuda.normalise_pixel_mean());
+                    builder.arg(&mut tensor);
+                    builder.arg(&buffer);
+                    builder.arg(&mean);
+                    builder.arg(&std);
+                    builder.arg(&image_size);
+                    builder.launch(cfg)?;
+
+                    (stream, tensor)
+                };
+
+                unsafe {
+                    let tensor: TensorRefMut<'_, f32> = TensorRefMut::from_raw(
+                        MemoryInfo::new(
+                            AllocationDevice::CUDA,
+                            RUNNING_SAM_DEVICE,
+                            AllocatorType::Device,
+                            MemoryType::Default,
+                        )?,
+                        (tensor.device_ptr(&stream).0 as usize as *mut ()).cast(),
+                        Shape::new([1, 3, height as i64, width as i64]),
+                    )?;
+
+                    encoder_binding.bind_input(spec.input("image")?, &tensor)?;
+
+                    let allocator = Allocator::new(
+                        image_encoder,
+                        MemoryInfo::new(
+                            AllocationDevice::CUDA_PINNED,
+                            RUNNING_SAM_DEVICE,
+                            AllocatorType::Device,
+                            MemoryType::CPUOutput,
+                        )?,
+                    )?;
+                    for role in EMBEDDING_ROLES {
+                        encoder_binding
+                            .bind_output_to_device(spec.output(role)?, &allocator.memory_info())?;
+                    }
+                    image_encoder.run_binding(encoder_binding)?
+                }
+            }
+            _ => {
+                let tensor = normalise_pixel_mean(image, width, height, mean, std)?;
                 image_encoder.run(inputs![Tensor::from_array(tensor)?])?
             }
-            _ => unsafe {
-                let tensor: TensorRefMut<'_, f32> = TensorRefMut::from_raw(
-                    MemoryInfo::new(
-                        AllocationDevice::CUDA,
-                        RUNNING_SAM_DEVICE,
-                        AllocatorType::Device,
-                        MemoryType::Default,
-                    )?,
-                    (tensor.device_ptr(&stream).0 as usize as *mut ()).cast(),
-                    Shape::new([1, 3, 1024, 1024]),
-                )?;
-
-                encoder_binding.bind_input("image", &tensor)?;
-
-                let allocator = Allocator::new(
-                    image_encoder,
-                    MemoryInfo::new(
-                        AllocationDevice::CUDA_PINNED,
-                        RUNNING_SAM_DEVICE,
-                        AllocatorType::Device,
-                        MemoryType::CPUOutput,
-                    )?,
-                )?;
-                encoder_binding.bind_output_to_device("image_embed", &allocator.memory_info())?;
-                encoder_binding
-                    .bind_output_to_device("high_res_feats_0", &allocator.memory_info())?;
-                encoder_binding
-                    .bind_output_to_device("high_res_feats_1", &allocator.memory_info())?;
-                image_encoder.run_binding(encoder_binding)?
-            },
         };
 
         Ok(session_outputs)
@@ -207,72 +431,51 @@ impl SAMImageInferenceSession {
     fn inference_image_decoder<'a, 'b: 'a>(
         &self,
         prompt: &SamPrompt<f32>,
+        mask_input: Option<&Array2<f32>>,
         image_size: (i32, i32),
         out_put_size: Option<(i32, i32)>,
+        spec: &ModelSpec,
         decoder_binding: &'a mut IoBinding,
         decoder: &'b mut OnnxSession,
     ) -> Result<SessionOutputs<'a, 'b>> {
-        let mask_input = Array4::<f32>::zeros((1, 1, 256, 256));
-
-        let trans_prompt = match &prompt {
-            SamPrompt::Box(boxes) => {
-                array![
-                    1024f32 * ((boxes.x - boxes.width / 2_f32) / image_size.0 as f32),
-                    1024f32 * ((boxes.y - boxes.height / 2_f32) / image_size.1 as f32),
-                    1024f32 * ((boxes.x + boxes.width / 2_f32) / image_size.0 as f32),
-                    1024f32 * ((boxes.y + boxes.height / 2_f32) / image_size.1 as f32),
-                ]
+        let (mask_input, has_mask_input) = match mask_input {
+            Some(mask_input) => {
+                ensure!(
+                    mask_input.dim() == (LOW_RES_MASK_SIZE, LOW_RES_MASK_SIZE),
+                    "mask_input must be {0}x{0}, got {1:?}",
+                    LOW_RES_MASK_SIZE,
+                    mask_input.dim()
+                );
+                let mask_input = mask_input.to_owned().into_shape_with_order((
+                    1,
+                    1,
+                    LOW_RES_MASK_SIZE,
+                    LOW_RES_MASK_SIZE,
+                ))?;
+                (mask_input, 1_f32)
             }
-            SamPrompt::Point(point) => {
-                array![
-                    1024f32 * (point.x / image_size.0 as f32),
-                    1024f32 * (point.y / image_size.1 as f32),
-                ]
-            }
-            SamPrompt::Points(points) => {
-                let mut base = Array1::zeros(points.len() * 2);
-                for (i, point) in points.iter().enumerate() {
-                    base[i * 2] = 1024f32 * (point.x / image_size.0 as f32);
-                    base[i * 2 + 1] = 1024f32 * (point.y / image_size.1 as f32);
-                }
-                base
-            }
-            SamPrompt::Both(point, boxes) => {
-                array![
-                    1024f32 * (point.x / image_size.0 as f32),
-                    1024f32 * (point.y / image_size.1 as f32),
-                    1024f32 * ((boxes.x - boxes.width / 2_f32) / image_size.0 as f32),
-                    1024f32 * ((boxes.y - boxes.height / 2_f32) / image_size.1 as f32),
-                    1024f32 * ((boxes.x + boxes.width / 2_f32) / image_size.0 as f32),
-                    1024f32 * ((boxes.y + boxes.height / 2_f32) / image_size.1 as f32),
-                ]
-            }
-        };
-
-        let point_labels = match prompt {
-            SamPrompt::Point(_) => array![[1_f32]],
-            SamPrompt::Points(point) => {
-                let mut point_labels = Array2::zeros((1, point.len()));
-                for (i, _) in point.iter().enumerate() {
-                    point_labels[[0, i]] = 1_f32;
-                }
-                point_labels
-            }
-            SamPrompt::Box(_) => array![[2_f32, 3_f32]],
-            SamPrompt::Both(_, _) => array![[1_f32, 2_f32, 3_f32]],
-        };
-
-        let prompt_out = {
-            let size = trans_prompt.shape()[0] / 2;
-            trans_prompt.into_shape_with_order((1, size, 2))?
+            None => (
+                Array4::<f32>::zeros((1, 1, LOW_RES_MASK_SIZE, LOW_RES_MASK_SIZE)),
+                0_f32,
+            ),
         };
 
-        decoder_binding.bind_input("point_coords", &Tensor::from_array(prompt_out)?)?;
-        decoder_binding.bind_input("point_labels", &Tensor::from_array(point_labels)?)?;
-        decoder_binding.bind_input("mask_input", &Tensor::from_array(mask_input)?)?;
-        decoder_binding.bind_input("has_mask_input", &Tensor::from_array(array![0_f32])?)?;
+        let (point_coords, point_labels) = decoder_prompt(prompt, image_size, self.input_size)?;
+        decoder_binding.bind_input(
+            spec.input("point_coords")?,
+            &Tensor::from_array(point_coords)?,
+        )?;
         decoder_binding.bind_input(
-            "orig_im_size",
+            spec.input("point_labels")?,
+            &Tensor::from_array(point_labels)?,
+        )?;
+        decoder_binding.bind_input(spec.input("mask_input")?, &Tensor::from_array(mask_input)?)?;
+        decoder_binding.bind_input(
+            spec.input("has_mask_input")?,
+            &Tensor::from_array(array![has_mask_input])?,
+        )?;
+        decoder_binding.bind_input(
+            spec.input("orig_im_size")?,
             &Tensor::from_array(
                 out_put_size
                     .map(|x| array![x.0, x.1])
@@ -289,13 +492,86 @@ impl SAMImageInferenceSession {
                 MemoryType::CPUOutput,
             )?,
         )?;
-        decoder_binding.bind_output_to_device("masks", &allocator.memory_info())?;
-        decoder_binding.bind_output_to_device("iou_predictions", &allocator.memory_info())?;
+        for role in DECODER_OUTPUT_ROLES {
+            decoder_binding.bind_output_to_device(spec.output(role)?, &allocator.memory_info())?;
+        }
 
         Ok(decoder.run_binding(decoder_binding)?)
     }
 }
 
+/// Decoder labels of negative points, positive points and the corners of a box.
+const NEGATIVE_POINT: f32 = 0.0;
+const POSITIVE_POINT: f32 = 1.0;
+const BOX_TOP_LEFT: f32 = 2.0;
+const BOX_BOTTOM_RIGHT: f32 = 3.0;
+
+/// Turns a prompt in image coordinates into the decoder's `point_coords` (`1xNx2`, in pixels of
+/// the `input_size` encoder frame) and `point_labels` (`1xN`): the positive points, the negative
+/// points, then the two corners of the box. Prompts the decoder cannot make sense of are rejected.
+fn decoder_prompt(
+    prompt: &SamPrompt<f32>,
+    image_size: (i32, i32),
+    input_size: (usize, usize),
+) -> Result<(Array3<f32>, Array2<f32>)> {
+    ensure!(
+        prompt.has_target(),
+        "A SAM prompt needs at least one positive point or box"
+    );
+
+    let mut points = Vec::with_capacity(prompt.positive.len() + prompt.negative.len() + 2);
+    points.extend(
+        prompt
+            .positive
+            .iter()
+            .map(|point| (point.x, point.y, POSITIVE_POINT)),
+    );
+    points.extend(
+        prompt
+            .negative
+            .iter()
+            .map(|point| (point.x, point.y, NEGATIVE_POINT)),
+    );
+    if let Some(boxes) = &prompt.boxes {
+        ensure!(
+            boxes.width > 0.0 && boxes.height > 0.0,
+            "SAM prompt boxes need a positive size, got {:?}",
+            boxes
+        );
+        points.push((
+            boxes.x - boxes.width / 2.0,
+            boxes.y - boxes.height / 2.0,
+            BOX_TOP_LEFT,
+        ));
+        points.push((
+            boxes.x + boxes.width / 2.0,
+            boxes.y + boxes.height / 2.0,
+            BOX_BOTTOM_RIGHT,
+        ));
+    }
+    ensure!(
+        points
+            .iter()
+            .all(|(x, y, _)| x.is_finite() && y.is_finite()),
+        "SAM prompt coordinates must be finite"
+    );
+
+    let scale_x = input_size.0 as f32 / image_size.0 as f32;
+    let scale_y = input_size.1 as f32 / image_size.1 as f32;
+    let point_coords = Array3::from_shape_fn((1, points.len(), 2), |(_, index, axis)| {
+        let (x, y, _) = points[index];
+        if axis == 0 {
+            x * scale_x
+        } else {
+            y * scale_y
+        }
+    });
+    let point_labels = Array2::from_shape_fn((1, points.len()), |(_, index)| points[index].2);
+
+    Ok((point_coords, point_labels))
+}
+
+#[cfg(feature = "cuda")]
 pub fn compute_launch_config(out_width: u32, out_height: u32) -> LaunchConfig {
     let block_dim_x: u32 = 16;
     let block_dim_y: u32 = 16;
@@ -313,3 +589,46 @@ pub fn compute_launch_config(out_width: u32, out_height: u32) -> LaunchConfig {
         shared_mem_bytes,
     }
 }
+
+#[test]
+fn test_decoder_prompt_labels_and_scaling() {
+    use crate::utils::graph::{Box, Point};
+
+    // 512x256 is stretched to 1024x1024, x doubles and y quadruples
+    let prompt = SamPrompt::from_box(Box {
+        x: 256.0,
+        y: 128.0,
+        width: 100.0,
+        height: 50.0,
+    })
+    .positive(Point { x: 256.0, y: 128.0 })
+    .negative(Point { x: 0.0, y: 0.0 });
+    let (point_coords, point_labels) = decoder_prompt(&prompt, (512, 256), (1024, 1024)).unwrap();
+
+    assert_eq!(
+        point_coords,
+        array![[[512.0, 512.0], [0.0, 0.0], [412.0, 412.0], [612.0, 612.0]]]
+    );
+    assert_eq!(point_labels, array![[1.0, 0.0, 2.0, 3.0]]);
+
+    let only_negative = SamPrompt::default().negative(Point { x: 1.0, y: 1.0 });
+    assert!(decoder_prompt(&only_negative, (512, 256), (1024, 1024)).is_err());
+    let empty_box = SamPrompt::from_box(Box {
+        x: 1.0,
+        y: 1.0,
+        width: 0.0,
+        height: 1.0,
+    });
+    assert!(decoder_prompt(&empty_box, (512, 256), (1024, 1024)).is_err());
+    let replaced = prompt.with_box(Box {
+        x: 50.0,
+        y: 50.0,
+        width: 10.0,
+        height: 10.0,
+    });
+    let (point_coords, _) = decoder_prompt(&replaced, (512, 256), (1024, 1024)).unwrap();
+    assert_eq!(
+        point_coords,
+        array![[[512.0, 512.0], [0.0, 0.0], [90.0, 180.0], [110.0, 220.0]]]
+    );
+}
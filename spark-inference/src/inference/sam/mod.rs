@@ -1 +1,2 @@
+pub mod automatic;
 pub mod image_inference;
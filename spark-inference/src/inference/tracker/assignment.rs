@@ -0,0 +1,97 @@
+use ndarray::ArrayView2;
+
+/// Pairs of `(row, column)` with the lowest total cost, found with the Hungarian algorithm in
+/// `O(rows² columns)`. Every row gets a column when there are at least as many columns,
+/// otherwise every column gets a row. Sorted by row.
+pub fn min_cost_assignment(cost: ArrayView2<f32>) -> Vec<(usize, usize)> {
+    let (rows, columns) = cost.dim();
+    if rows == 0 || columns == 0 {
+        return Vec::new();
+    }
+    if rows > columns {
+        let mut pairs = min_cost_assignment(cost.t())
+            .into_iter()
+            .map(|(column, row)| (row, column))
+            .collect::<Vec<_>>();
+        pairs.sort_unstable();
+        return pairs;
+    }
+
+    // Potentials of rows and columns, and the row assigned to each column, all 1-based so that
+    // column 0 can stand for the row being inserted
+    let mut row_potential = vec![0_f64; rows + 1];
+    let mut column_potential = vec![0_f64; columns + 1];
+    let mut assigned = vec![0_usize; columns + 1];
+    let mut previous = vec![0_usize; columns + 1];
+
+    for row in 1..=rows {
+        assigned[0] = row;
+        let mut column = 0;
+        let mut slack = vec![f64::INFINITY; columns + 1];
+        let mut visited = vec![false; columns + 1];
+
+        // Grows the alternating tree until it reaches a free column
+        while assigned[column] != 0 {
+            visited[column] = true;
+            let current = assigned[column];
+            let (mut delta, mut next) = (f64::INFINITY, 0);
+            for candidate in 1..=columns {
+                if visited[candidate] {
+                    continue;
+                }
+                let reduced = cost[[current - 1, candidate - 1]] as f64
+                    - row_potential[current]
+                    - column_potential[candidate];
+                if reduced < slack[candidate] {
+                    slack[candidate] = reduced;
+                    previous[candidate] = column;
+                }
+                if slack[candidate] < delta {
+                    delta = slack[candidate];
+                    next = candidate;
+                }
+            }
+
+            for candidate in 0..=columns {
+                if visited[candidate] {
+                    row_potential[assigned[candidate]] += delta;
+                    column_potential[candidate] -= delta;
+                } else {
+                    slack[candidate] -= delta;
+                }
+            }
+            column = next;
+        }
+
+        // Flips the path back to the root
+        while column != 0 {
+            let before = previous[column];
+            assigned[column] = assigned[before];
+            column = before;
+        }
+    }
+
+    let mut pairs = (1..=columns)
+        .filter(|&column| assigned[column] != 0)
+        .map(|column| (assigned[column] - 1, column - 1))
+        .collect::<Vec<_>>();
+    pairs.sort_unstable();
+    pairs
+}
+
+#[test]
+fn test_min_cost_assignment() {
+    // Greedy would take the 1 and then pay 9, the optimum is 2 + 2
+    let cost = ndarray::arr2(&[[1.0, 2.0], [2.0, 9.0]]);
+    assert_eq!(min_cost_assignment(cost.view()), vec![(0, 1), (1, 0)]);
+
+    let cost = ndarray::arr2(&[[4.0, 1.0, 3.0], [2.0, 0.0, 5.0], [3.0, 2.0, 2.0]]);
+    let pairs = min_cost_assignment(cost.view());
+    let total = pairs.iter().map(|&pair| cost[pair]).sum::<f32>();
+    assert_eq!(total, 5.0);
+
+    // More rows than columns leaves the most expensive row out
+    let cost = ndarray::arr2(&[[5.0], [1.0], [3.0]]);
+    assert_eq!(min_cost_assignment(cost.view()), vec![(1, 0)]);
+    assert!(min_cost_assignment(ndarray::Array2::<f32>::zeros((0, 3)).view()).is_empty());
+}
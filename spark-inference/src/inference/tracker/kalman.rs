@@ -0,0 +1,97 @@
+/// Standard deviation of the position noise, relative to the size of the box, as in DeepSORT.
+const POSITION_NOISE: f32 = 1.0 / 20.0;
+/// Standard deviation of the velocity noise, relative to the size of the box.
+const VELOCITY_NOISE: f32 = 1.0 / 160.0;
+
+/// Constant velocity Kalman filter of a box, `[x, y, width, height]` with `x`/`y` its centre.
+/// The noise of each coordinate only depends on the box, so the filter of SORT falls apart into
+/// one small filter per coordinate, which is what this keeps.
+#[derive(Debug, Clone)]
+pub(crate) struct KalmanBox {
+    axes: [KalmanAxis; 4],
+}
+
+/// Value and velocity of one coordinate and their covariance.
+#[derive(Debug, Clone, Copy)]
+struct KalmanAxis {
+    value: f32,
+    velocity: f32,
+    covariance: [[f32; 2]; 2],
+}
+
+impl KalmanBox {
+    /// Starts at `measurement` without any motion, unsure about the velocity.
+    pub fn new(measurement: [f32; 4]) -> Self {
+        let sizes = axis_sizes(measurement);
+        Self {
+            axes: std::array::from_fn(|index| {
+                let position = 2.0 * POSITION_NOISE * sizes[index];
+                let velocity = 10.0 * VELOCITY_NOISE * sizes[index];
+                KalmanAxis {
+                    value: measurement[index],
+                    velocity: 0.0,
+                    covariance: [[position * position, 0.0], [0.0, velocity * velocity]],
+                }
+            }),
+        }
+    }
+
+    /// Moves the box on by one frame.
+    pub fn predict(&mut self) {
+        let sizes = axis_sizes(self.state());
+        for (axis, size) in self.axes.iter_mut().zip(sizes) {
+            axis.predict(
+                (POSITION_NOISE * size).powi(2),
+                (VELOCITY_NOISE * size).powi(2),
+            );
+        }
+    }
+
+    /// Corrects the prediction with the box detected in this frame.
+    pub fn update(&mut self, measurement: [f32; 4]) {
+        let sizes = axis_sizes(self.state());
+        for ((axis, value), size) in self.axes.iter_mut().zip(measurement).zip(sizes) {
+            axis.update(value, (POSITION_NOISE * size).powi(2));
+        }
+    }
+
+    pub fn state(&self) -> [f32; 4] {
+        self.axes.map(|axis| axis.value)
+    }
+
+    /// Change of `[x, y, width, height]` per frame.
+    pub fn velocity(&self) -> [f32; 4] {
+        self.axes.map(|axis| axis.velocity)
+    }
+}
+
+impl KalmanAxis {
+    fn predict(&mut self, position_noise: f32, velocity_noise: f32) {
+        let [[p00, p01], [p10, p11]] = self.covariance;
+        self.value += self.velocity;
+        self.covariance = [
+            [p00 + p01 + p10 + p11 + position_noise, p01 + p11],
+            [p10 + p11, p11 + velocity_noise],
+        ];
+    }
+
+    fn update(&mut self, measurement: f32, measurement_noise: f32) {
+        let [[p00, p01], [p10, p11]] = self.covariance;
+        let innovation = p00 + measurement_noise;
+        let (gain_value, gain_velocity) = (p00 / innovation, p10 / innovation);
+        let residual = measurement - self.value;
+
+        self.value += gain_value * residual;
+        self.velocity += gain_velocity * residual;
+        self.covariance = [
+            [(1.0 - gain_value) * p00, (1.0 - gain_value) * p01],
+            [p10 - gain_velocity * p00, p11 - gain_velocity * p01],
+        ];
+    }
+}
+
+/// The size the noise of each coordinate scales with: the width along `x`, the height along `y`.
+fn axis_sizes([_, _, width, height]: [f32; 4]) -> [f32; 4] {
+    let (width, height) = (width.abs().max(1.0), height.abs().max(1.0));
+    [width, height, width, height]
+}
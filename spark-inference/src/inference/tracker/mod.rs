@@ -0,0 +1,346 @@
+use crate::inference::tracker::assignment::min_cost_assignment;
+use crate::inference::tracker::kalman::KalmanBox;
+use crate::inference::yolo::inference_yolo_detect::YoloDetectResult;
+use crate::inference::yolo::{box_iou, BoxFormat};
+use crate::utils::graph::Point;
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+
+pub mod assignment;
+mod kalman;
+
+/// How [`Tracker`] matches detections to tracks and when tracks start and end.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrackerOptions {
+    /// Detections scoring at least this are matched first and may start new tracks.
+    pub high_score: f32,
+    /// Detections between this and `high_score` only keep tracks seen in the last frame alive,
+    /// as ByteTrack does for objects that are partly hidden. Lower ones are ignored.
+    pub low_score: f32,
+    /// A detection needs at least this IoU with the predicted box of a track to be matched to it.
+    pub min_iou: f32,
+    /// A track is confirmed once it was matched in this many frames.
+    pub min_hits: usize,
+    /// A confirmed track is dropped after this many frames without a match.
+    pub max_age: usize,
+}
+
+impl Default for TrackerOptions {
+    fn default() -> Self {
+        Self {
+            high_score: 0.5,
+            low_score: 0.1,
+            min_iou: 0.2,
+            min_hits: 3,
+            max_age: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackState {
+    /// Seen too few times to be reported, dropped on the first miss.
+    Tentative,
+    Confirmed,
+    /// Confirmed, but not matched in the last frame. Its box keeps moving with its velocity.
+    Lost,
+}
+
+/// Whether a tracked object comes closer, judged by the growth of its box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackMotion {
+    Approaching,
+    Receding,
+    Steady,
+}
+
+/// One object followed across frames.
+#[derive(Debug, Clone)]
+pub struct Track {
+    /// Unique for the lifetime of the tracker, never reused.
+    pub id: u64,
+    pub class_id: usize,
+    pub label: String,
+    /// Class score of the last detection matched to the track.
+    pub score: f32,
+    /// Filtered box, `x`/`y` being its centre like in [`YoloDetectResult`].
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    /// Pixels the centre moves per frame.
+    pub velocity: Point<f32>,
+    /// Relative change of the box height per frame, positive while the box grows.
+    pub growth: f32,
+    /// Frames since the track started.
+    pub age: usize,
+    /// Frames the track was matched in.
+    pub hits: usize,
+    /// Frames since the track was last matched.
+    pub misses: usize,
+    pub state: TrackState,
+    filter: KalmanBox,
+}
+
+impl Track {
+    fn new(id: u64, detection: &YoloDetectResult) -> Self {
+        let mut track = Self {
+            id,
+            class_id: detection.class_id,
+            label: detection.label.clone(),
+            score: detection.class_score(),
+            x: detection.x,
+            y: detection.y,
+            width: detection.width,
+            height: detection.height,
+            velocity: Point { x: 0.0, y: 0.0 },
+            growth: 0.0,
+            age: 0,
+            hits: 1,
+            misses: 0,
+            state: TrackState::Tentative,
+            filter: KalmanBox::new([detection.x, detection.y, detection.width, detection.height]),
+        };
+        track.refresh();
+        track
+    }
+
+    /// `[x1, y1, x2, y2]` of the box.
+    pub fn corners(&self) -> [f32; 4] {
+        BoxFormat::Center.corners(self.x, self.y, self.width, self.height)
+    }
+
+    /// The box as a detection, for code that works on those.
+    pub fn detection(&self) -> YoloDetectResult {
+        let mut score = vec![0.0; self.class_id + 1];
+        score[self.class_id] = self.score;
+        YoloDetectResult {
+            score,
+            class_id: self.class_id,
+            label: self.label.clone(),
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// Approaching or receding once the box grows or shrinks by more than `min_growth` per frame.
+    pub fn motion(&self, min_growth: f32) -> TrackMotion {
+        if self.growth > min_growth {
+            TrackMotion::Approaching
+        } else if self.growth < -min_growth {
+            TrackMotion::Receding
+        } else {
+            TrackMotion::Steady
+        }
+    }
+
+    fn predict(&mut self) {
+        self.filter.predict();
+        self.age += 1;
+        self.misses += 1;
+        self.refresh();
+    }
+
+    fn update(&mut self, detection: &YoloDetectResult, min_hits: usize) {
+        self.filter
+            .update([detection.x, detection.y, detection.width, detection.height]);
+        self.score = detection.class_score();
+        self.hits += 1;
+        self.misses = 0;
+        if self.state == TrackState::Lost || self.hits >= min_hits {
+            self.state = TrackState::Confirmed;
+        }
+        self.refresh();
+    }
+
+    /// Copies the state of the filter into the public fields.
+    fn refresh(&mut self) {
+        let [x, y, width, height] = self.filter.state();
+        let [velocity_x, velocity_y, _, velocity_height] = self.filter.velocity();
+        (self.x, self.y, self.width, self.height) = (x, y, width, height);
+        self.velocity = Point {
+            x: velocity_x,
+            y: velocity_y,
+        };
+        self.growth = velocity_height / height.max(1.0);
+    }
+}
+
+/// Gives detections of consecutive frames stable identities, following SORT with the two round
+/// matching of ByteTrack: boxes are Kalman filtered with constant velocity and matched to
+/// detections of the same class by IoU with the Hungarian algorithm, confident detections first,
+/// then weak ones for the tracks still unmatched.
+#[derive(Debug, Clone)]
+pub struct Tracker {
+    options: TrackerOptions,
+    tracks: Vec<Track>,
+    next_id: u64,
+}
+
+impl Default for Tracker {
+    fn default() -> Self {
+        Self::new(TrackerOptions::default())
+    }
+}
+
+impl Tracker {
+    pub fn new(options: TrackerOptions) -> Self {
+        Self {
+            options,
+            tracks: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Advances every track by one frame and matches it against the `detections` of that frame.
+    /// Returns the confirmed tracks matched in this frame.
+    pub fn update(&mut self, detections: &[YoloDetectResult]) -> Vec<Track> {
+        for track in self.tracks.iter_mut() {
+            track.predict();
+        }
+
+        let (high, low): (Vec<_>, Vec<_>) = detections
+            .iter()
+            .filter(|detection| detection.class_score() >= self.options.low_score)
+            .partition(|detection| detection.class_score() >= self.options.high_score);
+
+        let all = (0..self.tracks.len()).collect::<Vec<_>>();
+        let matched = self.associate(&all, &high);
+        let unmatched_high = (0..high.len())
+            .filter(|index| !matched.iter().any(|(_, detection)| detection == index))
+            .collect::<Vec<_>>();
+        let mut unmatched = all
+            .into_iter()
+            .filter(|index| !matched.iter().any(|(track, _)| track == index))
+            .collect::<Vec<_>>();
+        for (track, detection) in matched {
+            self.tracks[track].update(high[detection], self.options.min_hits);
+        }
+
+        // Weak detections only extend tracks that were seen in the last frame
+        unmatched.retain(|&index| self.tracks[index].misses == 1);
+        for (track, detection) in self.associate(&unmatched, &low) {
+            self.tracks[track].update(low[detection], self.options.min_hits);
+        }
+
+        let max_age = self.options.max_age;
+        self.tracks.retain_mut(|track| {
+            if track.misses == 0 {
+                return true;
+            }
+            if track.state == TrackState::Tentative || track.misses > max_age {
+                return false;
+            }
+            track.state = TrackState::Lost;
+            true
+        });
+
+        for index in unmatched_high {
+            let mut track = Track::new(self.next_id, high[index]);
+            if self.options.min_hits <= 1 {
+                track.state = TrackState::Confirmed;
+            }
+            self.next_id += 1;
+            self.tracks.push(track);
+        }
+
+        self.tracks
+            .iter()
+            .filter(|track| track.state == TrackState::Confirmed && track.misses == 0)
+            .cloned()
+            .collect()
+    }
+
+    /// Every live track, lost and tentative ones included.
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    /// Forgets every track, e.g. when the camera cut to another scene. Ids keep counting up.
+    pub fn clear(&mut self) {
+        self.tracks.clear();
+    }
+
+    /// `(track, detection)` pairs of the given tracks and detections with the highest total IoU,
+    /// as indices into `self.tracks` and `detections`. Classes have to match.
+    fn associate(&self, tracks: &[usize], detections: &[&YoloDetectResult]) -> Vec<(usize, usize)> {
+        let iou = Array2::from_shape_fn((tracks.len(), detections.len()), |(row, column)| {
+            let (track, detection) = (&self.tracks[tracks[row]], detections[column]);
+            if track.class_id != detection.class_id {
+                return 0.0;
+            }
+            box_iou(track.corners(), detection.corners())
+        });
+
+        min_cost_assignment(iou.mapv(|iou| 1.0 - iou).view())
+            .into_iter()
+            .filter(|&(row, column)| iou[[row, column]] >= self.options.min_iou)
+            .map(|(row, column)| (tracks[row], column))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+fn detection(class_id: usize, score: f32, x: f32, y: f32, size: f32) -> YoloDetectResult {
+    let mut scores = vec![0.0; 2];
+    scores[class_id] = score;
+    YoloDetectResult {
+        score: scores,
+        class_id,
+        label: class_id.to_string(),
+        x,
+        y,
+        width: size,
+        height: size,
+    }
+}
+
+#[test]
+fn test_tracker_keeps_identities() {
+    let mut tracker = Tracker::new(TrackerOptions {
+        min_hits: 2,
+        max_age: 2,
+        ..TrackerOptions::default()
+    });
+
+    // A car coming closer from the left and a person walking away to the right
+    let frame = |step: f32| {
+        vec![
+            detection(0, 0.9, 100.0 + 4.0 * step, 200.0, 40.0 + 2.0 * step),
+            detection(1, 0.8, 300.0 + 3.0 * step, 200.0, 60.0 - 2.0 * step),
+        ]
+    };
+    assert!(tracker.update(&frame(0.0)).is_empty());
+    let tracks = tracker.update(&frame(1.0));
+    assert_eq!(tracks.len(), 2);
+    let ids = tracks.iter().map(|track| track.id).collect::<Vec<_>>();
+    for step in 2..10 {
+        let tracks = tracker.update(&frame(step as f32));
+        assert_eq!(tracks.iter().map(|track| track.id).collect::<Vec<_>>(), ids);
+    }
+
+    let tracks = tracker.tracks();
+    let (car, person) = (&tracks[0], &tracks[1]);
+    assert_eq!((car.class_id, person.class_id), (0, 1));
+    assert!(car.velocity.x > 3.0 && person.velocity.x > 2.0);
+    assert_eq!(car.motion(0.01), TrackMotion::Approaching);
+    assert_eq!(person.motion(0.01), TrackMotion::Receding);
+    assert_eq!(car.age, 9);
+
+    // A weak detection keeps the car, the person is lost and dropped after `max_age` frames
+    let weak = vec![detection(0, 0.3, 140.0, 200.0, 60.0)];
+    assert_eq!(tracker.update(&weak)[0].id, ids[0]);
+    assert_eq!(tracker.tracks()[1].state, TrackState::Lost);
+    tracker.update(&weak);
+    assert_eq!(tracker.update(&weak).len(), 1);
+    assert_eq!(tracker.tracks().len(), 1);
+
+    // Weak detections do not start tracks, confident ones get a new id
+    tracker.update(&[detection(1, 0.3, 500.0, 100.0, 20.0)]);
+    assert!(tracker.tracks().iter().all(|track| track.id == ids[0]));
+    tracker.update(&[detection(1, 0.9, 500.0, 100.0, 20.0)]);
+    assert_eq!(tracker.tracks().last().unwrap().id, 3);
+}
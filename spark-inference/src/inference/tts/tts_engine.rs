@@ -2,18 +2,73 @@ use crate::inference::tts::raw::{
     destroy_tts_engine, free_tts_audio, generate_tts_audio, init_tts_engine_en, init_tts_engine_zh,
 };
 use log::info;
+use spark_media::{Audio, AudioFormat};
 use std::ffi::{c_void, CString};
 use std::io::{BufWriter, Cursor};
+use std::path::Path;
 use std::str::FromStr;
 
-pub trait TTS {
-    fn new_zh() -> anyhow::Result<Self>
+/// Turns text into samples. Object safe, so callers can hold any engine behind a `dyn`.
+pub trait SpeechSynthesizer: Send + Sync {
+    fn speak(&self, source: &str) -> anyhow::Result<Audio>;
+
+    /// Synthesizes `source` and encodes it into `format`, resampled to `sample_rate` if given.
+    fn speak_as(
+        &self,
+        source: &str,
+        format: AudioFormat,
+        sample_rate: Option<u32>,
+    ) -> anyhow::Result<Vec<u8>> {
+        let audio = self.speak(source)?;
+        if format == AudioFormat::WavFloat && sample_rate.is_none() {
+            return wav_float(audio);
+        }
+
+        audio.encode(format, sample_rate)
+    }
+}
+
+pub trait TTS: SpeechSynthesizer {
+    /// Loads the Chinese Matcha model from `folder`, e.g. `./data/model/tts/matcha-zh`.
+    fn new_zh(folder: impl AsRef<Path>) -> anyhow::Result<Self>
     where
         Self: Sized;
-    fn new_en() -> anyhow::Result<Self>
+    /// Loads the English Matcha model from `folder`, e.g. `./data/model/tts/matcha-en`.
+    fn new_en(folder: impl AsRef<Path>) -> anyhow::Result<Self>
     where
         Self: Sized;
-    fn generate<'a, T: Into<&'a str>>(&self, source: T) -> anyhow::Result<Vec<u8>>;
+    fn generate<'a, T: Into<&'a str>>(&self, source: T) -> anyhow::Result<Vec<u8>> {
+        wav_float(self.speak(source.into())?)
+    }
+    /// Synthesizes `source` and encodes it into `format`, resampled to `sample_rate` if given.
+    fn generate_as<'a, T: Into<&'a str>>(
+        &self,
+        source: T,
+        format: AudioFormat,
+        sample_rate: Option<u32>,
+    ) -> anyhow::Result<Vec<u8>> {
+        self.speak_as(source.into(), format, sample_rate)
+    }
+}
+
+/// Writes 32-bit float WAV with hound, which needs no FFmpeg encoder.
+fn wav_float(audio: Audio) -> anyhow::Result<Vec<u8>> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: audio.sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+
+    let mut buf = Vec::new();
+    let writer = BufWriter::new(Cursor::new(&mut buf));
+    let mut writer = hound::WavWriter::new(writer, spec)?;
+    for sample in audio.samples {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()?;
+
+    Ok(buf)
 }
 
 pub struct TTSEngine {
@@ -31,86 +86,88 @@ impl Drop for TTSEngine {
     }
 }
 
+/// The path of `name` inside `folder`, as the C string the engine takes.
+fn model_path(folder: &Path, name: &str) -> anyhow::Result<CString> {
+    let path = folder.join(name);
+    let path = path
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("TTS model path {} is not UTF-8", path.display()))?;
+
+    Ok(CString::from_str(path)?)
+}
+
 impl TTS for TTSEngine {
-    fn new_zh() -> anyhow::Result<Self> {
+    fn new_zh(folder: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let folder = folder.as_ref();
+        let rule_fsts = ["date.fst", "number.fst", "phone.fst"]
+            .map(|name| folder.join(name).display().to_string())
+            .join(",");
         let engine = unsafe {
             init_tts_engine_zh(
-                CString::from_str("./data/model/tts/matcha-zh/model-steps-3.onnx")?.as_ptr() as *const i8,
-                CString::from_str("./data/model/tts/matcha-zh/hifigan_v3.onnx")?.as_ptr() as *const i8,
-                CString::from_str("./data/model/tts/matcha-zh/lexicon.txt")?.as_ptr() as *const i8,
-                CString::from_str("./data/model/tts/matcha-zh/tokens.txt")?.as_ptr() as *const i8,
-                CString::from_str("./data/model/tts/matcha-zh/dict")?.as_ptr() as *const i8,
-                CString::from_str("./data/model/tts/matcha-zh/date.fst,./data/model/tts/matcha-zh/number.fst,./data/model/tts/matcha-zh/phone.fst")?.as_ptr() as *const i8,
+                model_path(folder, "model-steps-3.onnx")?.as_ptr() as *const i8,
+                model_path(folder, "hifigan_v3.onnx")?.as_ptr() as *const i8,
+                model_path(folder, "lexicon.txt")?.as_ptr() as *const i8,
+                model_path(folder, "tokens.txt")?.as_ptr() as *const i8,
+                model_path(folder, "dict")?.as_ptr() as *const i8,
+                CString::from_str(&rule_fsts)?.as_ptr() as *const i8,
                 CString::from_str("cpu")?.as_ptr() as *const i8,
                 16,
                 0,
             )
         };
         if engine.is_null() {
-            return Err(anyhow::anyhow!("Failed to initialize TTS engine"));
+            return Err(anyhow::anyhow!(
+                "Failed to initialize TTS engine from {}",
+                folder.display()
+            ));
         }
         info!("TTS engine initialized successfully");
         Ok(Self { engine })
     }
 
-    fn new_en() -> anyhow::Result<Self> {
+    fn new_en(folder: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let folder = folder.as_ref();
         let engine = unsafe {
             init_tts_engine_en(
-                CString::from_str("./data/model/tts/matcha-en/model-steps-3.onnx")?.as_ptr()
-                    as *const i8,
-                CString::from_str("./data/model/tts/matcha-en/vocos-22khz-univ.onnx")?.as_ptr()
-                    as *const i8,
-                CString::from_str("./data/model/tts/matcha-en/tokens.txt")?.as_ptr() as *const i8,
-                CString::from_str("./data/model/tts/matcha-en/espeak-ng-data")?.as_ptr()
-                    as *const i8,
+                model_path(folder, "model-steps-3.onnx")?.as_ptr() as *const i8,
+                model_path(folder, "vocos-22khz-univ.onnx")?.as_ptr() as *const i8,
+                model_path(folder, "tokens.txt")?.as_ptr() as *const i8,
+                model_path(folder, "espeak-ng-data")?.as_ptr() as *const i8,
                 CString::from_str("cpu")?.as_ptr() as *const i8,
                 16,
                 0,
             )
         };
         if engine.is_null() {
-            return Err(anyhow::anyhow!("Failed to initialize TTS engine"));
+            return Err(anyhow::anyhow!(
+                "Failed to initialize TTS engine from {}",
+                folder.display()
+            ));
         }
         info!("TTS engine initialized successfully");
         Ok(Self { engine })
     }
+}
 
-    fn generate<'a, T: Into<&'a str>>(&self, source: T) -> anyhow::Result<Vec<u8>> {
-        let source = source.into();
+impl SpeechSynthesizer for TTSEngine {
+    /// Runs the engine and copies its samples out of the native buffer.
+    fn speak(&self, source: &str) -> anyhow::Result<Audio> {
         if source.is_empty() {
             return Err(anyhow::anyhow!("Empty source string"));
         }
 
-        let (back, sample_rate) = unsafe {
-            let back = generate_tts_audio(
-                self.engine,
-                CString::from_str(source)?.as_ptr() as *const i8,
-                0,
-                1.0,
-            );
-            (back, (*back).sample_rate as u32)
-        };
-
-        let spec = hound::WavSpec {
-            channels: 1,
-            sample_rate,
-            bits_per_sample: 32,
-            sample_format: hound::SampleFormat::Float,
-        };
-
-        let mut buf = Vec::new();
-        let writer = BufWriter::new(Cursor::new(&mut buf));
-        let mut writer = hound::WavWriter::new(writer, spec)?;
-
+        let source = CString::from_str(source)?;
         unsafe {
-            for i in 0..(*back).n {
-                let sample = *(*back).samples.offset(i as isize);
-                writer.write_sample(sample)?;
+            let back = generate_tts_audio(self.engine, source.as_ptr() as *const i8, 0, 1.0);
+            if back.is_null() {
+                return Err(anyhow::anyhow!("Failed to generate TTS audio"));
             }
-            writer.finalize()?;
+
+            let samples = std::slice::from_raw_parts((*back).samples, (*back).n as usize).to_vec();
+            let sample_rate = (*back).sample_rate as u32;
             free_tts_audio(back);
-        }
 
-        Ok(buf)
+            Ok(Audio::new(samples, sample_rate))
+        }
     }
 }
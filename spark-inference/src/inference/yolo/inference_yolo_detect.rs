@@ -1,26 +1,51 @@
 use crate::engine::inference_engine::{ExecutionProvider, OnnxSession};
-use crate::{INFERENCE_YOLO, RUNNING_YOLO_DEVICE};
-use anyhow::Result;
-use cudarc::driver::{DevicePtr, LaunchConfig, PushKernelArg};
+use crate::engine::session_pool::{PoolOptions, PoolStats, SessionPool};
+use crate::inference::yolo::labels::YoloLabels;
+#[cfg(feature = "cuda")]
+use crate::inference::yolo::normalise_on_device;
+use crate::inference::yolo::{load_yolo_sessions, yolo_default_spec, YOLO_DETECT_MODEL};
+use crate::utils::graph;
+use crate::utils::letterbox::Letterbox;
+use crate::utils::tensor::normalise_pixel_batch;
+use anyhow::{ensure, Result};
 use log::{debug, info};
-use ndarray::{s, Axis, Ix2};
-use ort::memory::{AllocationDevice, AllocatorType, MemoryInfo, MemoryType};
-use ort::tensor::Shape;
-use ort::value::TensorRefMut;
-use parking_lot::Mutex;
+use ndarray::{s, ArrayView2, ArrayView3, Axis, Ix3};
+use ort::inputs;
+use ort::value::Tensor;
 use rayon::prelude::*;
-use spark_media::filter::filter::AVFilter;
+use serde::Serialize;
 use spark_media::Image;
 use std::ops::Deref;
 use std::path::Path;
 
 pub trait YoloDetectInference {
     fn inference_yolo(&self, tensor: Image, confidence: f32) -> Result<Vec<YoloDetectResult>>;
+
+    /// The detections of each image, in the order of `images`.
+    /// Backends that cannot batch run the images one by one.
+    fn inference_yolo_batch(
+        &self,
+        images: Vec<Image>,
+        confidence: f32,
+    ) -> Result<Vec<Vec<YoloDetectResult>>> {
+        images
+            .into_iter()
+            .map(|image| self.inference_yolo(image, confidence))
+            .collect()
+    }
+
+    /// Usage of the sessions behind the model, empty for backends without any.
+    fn pool_stats(&self) -> Vec<PoolStats> {
+        Vec::new()
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct YoloDetectResult {
     pub score: Vec<f32>,
+    /// Index of the highest score, the class the box most likely belongs to.
+    pub class_id: usize,
+    pub label: String,
 
     pub x: f32,
     pub y: f32,
@@ -28,147 +53,271 @@ pub struct YoloDetectResult {
     pub height: f32,
 }
 
-pub struct YoloDetectSession(Mutex<OnnxSession>);
+pub struct YoloDetectSession {
+    sessions: SessionPool<OnnxSession>,
+    labels: YoloLabels,
+    /// `(width, height)` frames are letterboxed to.
+    input_size: (usize, usize),
+    /// Most frames one run takes, set by graphs exported with a fixed batch size.
+    max_batch: usize,
+}
 
 impl YoloDetectSession {
+    /// Runs on [`ExecutionProvider::default`].
     pub fn new(folder_path: impl AsRef<Path>) -> Result<Self> {
-        let yolo_detect_session = Self(Mutex::new(OnnxSession::new(
-            folder_path.as_ref().join("yolo_detect.onnx"),
-            ExecutionProvider::CUDA(RUNNING_YOLO_DEVICE),
-        )?));
+        Self::with_provider(folder_path, ExecutionProvider::default())
+    }
+
+    /// A single session on `provider`.
+    pub fn with_provider(
+        folder_path: impl AsRef<Path>,
+        provider: ExecutionProvider,
+    ) -> Result<Self> {
+        Self::with_pool(folder_path, provider, PoolOptions::default())
+    }
+
+    /// Loads `pool.size` sessions of `yolo_detect.onnx`, or of the `yolo_detect` model of the
+    /// folder's manifest, from `folder_path`.
+    pub fn with_pool(
+        folder_path: impl AsRef<Path>,
+        provider: ExecutionProvider,
+        pool: PoolOptions,
+    ) -> Result<Self> {
+        let (sessions, input_size, max_batch) = load_yolo_sessions(
+            &folder_path,
+            YOLO_DETECT_MODEL,
+            yolo_default_spec("yolo_detect.onnx", &["boxes"]),
+            provider,
+            pool,
+        )?;
+        let labels = YoloLabels::load(folder_path, &sessions.checkout()?)?;
+        let yolo_detect_session = Self {
+            sessions,
+            labels,
+            input_size,
+            max_batch,
+        };
         info!("Yolo Inference Session created");
 
         Ok(yolo_detect_session)
     }
+
+    pub fn labels(&self) -> &YoloLabels {
+        &self.labels
+    }
 }
 
 impl Deref for YoloDetectSession {
-    type Target = Mutex<OnnxSession>;
+    type Target = SessionPool<OnnxSession>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.sessions
     }
 }
 
 impl YoloDetectInference for YoloDetectSession {
-    fn inference_yolo(&self, mut image: Image, confidence: f32) -> Result<Vec<YoloDetectResult>> {
-        let filter = AVFilter::builder(image.pixel_format()?, image.get_size())?
-            .add_context("scale", "640:640:force_original_aspect_ratio=decrease")?
-            .add_context("pad", "640:640:(ow-iw)/2:(oh-ih)/2:#727272")?
-            .add_context("format", "rgb24")?
-            .build()?;
-
-        let (image_width, image_height) = image.get_size();
-        let (image_width, image_height) = (image_width as f32, image_height as f32);
-
-        image.apply_filter(&filter)?;
-
-        let stream = INFERENCE_YOLO.new_stream()?;
-        let tensor = {
-            let buffer = stream.memcpy_stod(image.raw_data()?.as_slice())?;
-            let cfg = LaunchConfig::for_num_elems((buffer.len() / 3) as u32);
-
-            let tensor: TensorRefMut<'_, f32> = unsafe {
-                let image_size = buffer.len();
-                let mut tensor = stream.alloc::<f32>(image_size)?;
-
-                let mut builder = stream.launch_builder(INFERENCE_YOLO.normalise_pixel_div());
-                builder.arg(&mut tensor);
-                builder.arg(&buffer);
-                builder.arg(&image_size);
-                builder.launch(cfg)?;
-
-                let back = TensorRefMut::from_raw(
-                    MemoryInfo::new(
-                        AllocationDevice::CUDA,
-                        RUNNING_YOLO_DEVICE,
-                        AllocatorType::Device,
-                        MemoryType::Default,
-                    )?,
-                    (tensor.device_ptr(&stream).0 as usize as *mut ()).cast(),
-                    Shape::new([1, 3, 640, 640]),
-                )?;
-
-                back
-            };
+    fn inference_yolo(&self, image: Image, confidence: f32) -> Result<Vec<YoloDetectResult>> {
+        let mut results = self.inference_batch(vec![image], confidence)?;
+        Ok(results.pop().unwrap_or_default())
+    }
 
-            tensor
-        };
+    /// Runs the images in as few runs as the graph allows, all of them at once unless it was
+    /// exported with a fixed batch size.
+    fn inference_yolo_batch(
+        &self,
+        images: Vec<Image>,
+        confidence: f32,
+    ) -> Result<Vec<Vec<YoloDetectResult>>> {
+        let mut results = Vec::with_capacity(images.len());
+        for batch in batches(images, self.max_batch) {
+            results.extend(self.inference_batch(batch, confidence)?);
+        }
+
+        Ok(results)
+    }
+
+    fn pool_stats(&self) -> Vec<PoolStats> {
+        vec![self.stats()]
+    }
+}
+
+impl YoloDetectSession {
+    /// Letterboxes the images into one `[N, 3, H, W]` tensor, runs it on a single session
+    /// and splits the `[N, 4 + classes, boxes]` output back up per image.
+    fn inference_batch(
+        &self,
+        images: Vec<Image>,
+        confidence: f32,
+    ) -> Result<Vec<Vec<YoloDetectResult>>> {
+        if images.is_empty() {
+            return Ok(Vec::new());
+        }
 
-        let output_first = {
-            debug!("Finish copying tensor to device");
-            let mut guard = self.lock();
-            let outputs = guard.run([tensor.into()])?;
-            debug!("Finish running model");
+        let (width, height) = self.input_size;
+        let mut rgb = Vec::with_capacity(images.len() * width * height * 3);
+        let mut letterboxes = Vec::with_capacity(images.len());
+        for mut image in images {
+            letterboxes.push(Letterbox::apply(&mut image, self.input_size)?);
+            rgb.extend_from_slice(image.raw_data()?.as_slice());
+        }
 
-            let output_first = outputs["output0"]
+        let output = {
+            let mut guard = self.checkout()?;
+            let executor = guard.executor;
+            let (mean, std) = guard.spec.normalisation();
+            let boxes = guard.spec.output("boxes")?.to_string();
+            let outputs = match executor {
+                #[cfg(feature = "cuda")]
+                ExecutionProvider::CUDA(_) | ExecutionProvider::TensorRT(_) => {
+                    let (_buffer, tensor) = normalise_on_device(&rgb, self.input_size)?;
+                    debug!("Finish copying tensor to device");
+                    guard.run([tensor.into()])?
+                }
+                _ => {
+                    let tensor = normalise_pixel_batch(&rgb, width, height, mean, std)?;
+                    guard.run(inputs![Tensor::from_array(tensor)?])?
+                }
+            };
+            debug!("Finish running model on {} images", letterboxes.len());
+
+            outputs[boxes.as_str()]
                 .try_extract_array::<f32>()?
-                .t()
-                .into_owned();
-            output_first.squeeze().into_dimensionality::<Ix2>()?
+                .into_owned()
+                .into_dimensionality::<Ix3>()?
         };
 
-        let result = output_first
-            .axis_iter(Axis(0))
-            .into_par_iter()
-            .filter(|box_output| {
-                box_output
-                    .slice(s![4..box_output.len()])
-                    .iter()
-                    .any(|&score| score > confidence)
-            })
-            .map(|box_output| {
-                let score = box_output.slice(s![4..box_output.len()]).to_vec();
-                let (x, y, width, height) = yolo_to_image_coords(
-                    box_output[0],
-                    box_output[1],
-                    box_output[2],
-                    box_output[3],
-                    image_width,
-                    image_height,
-                    640.0,
-                    640.0,
-                );
-                YoloDetectResult {
-                    score,
-                    x,
-                    y,
-                    width,
-                    height,
-                }
-            })
-            .collect::<Vec<_>>();
+        split_detections(output.view(), &letterboxes, &self.labels, confidence)
+    }
+}
 
-        Ok(result)
+/// Cuts `items` into runs of at most `max_batch` items, keeping their order.
+fn batches<T>(items: Vec<T>, max_batch: usize) -> Vec<Vec<T>> {
+    let mut items = items.into_iter().peekable();
+    let mut batches = Vec::new();
+    while items.peek().is_some() {
+        batches.push(items.by_ref().take(max_batch.max(1)).collect());
     }
+
+    batches
+}
+
+/// Splits the `[N, 4 + classes, boxes]` output of a batch into the boxes of each image,
+/// mapped back to it through its letterbox.
+fn split_detections(
+    output: ArrayView3<f32>,
+    letterboxes: &[Letterbox],
+    labels: &YoloLabels,
+    confidence: f32,
+) -> Result<Vec<Vec<YoloDetectResult>>> {
+    ensure!(
+        output.len_of(Axis(0)) == letterboxes.len(),
+        "Yolo model returned {} results for {} images",
+        output.len_of(Axis(0)),
+        letterboxes.len()
+    );
+
+    Ok(output
+        .axis_iter(Axis(0))
+        .zip(letterboxes)
+        .map(|(output, letterbox)| detections(output.t(), letterbox, labels, confidence))
+        .collect())
+}
+
+/// The boxes of one image, `output` holding a row of `4 + classes` values per box.
+fn detections(
+    output: ArrayView2<f32>,
+    letterbox: &Letterbox,
+    labels: &YoloLabels,
+    confidence: f32,
+) -> Vec<YoloDetectResult> {
+    output
+        .axis_iter(Axis(0))
+        .into_par_iter()
+        .filter(|box_output| {
+            box_output
+                .slice(s![4..box_output.len()])
+                .iter()
+                .any(|&score| score > confidence)
+        })
+        .map(|box_output| {
+            let score = box_output.slice(s![4..box_output.len()]).to_vec();
+            let class_id = score
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map_or(0, |(index, _)| index);
+            let boxes = letterbox.box_to_source(graph::Box {
+                x: box_output[0],
+                y: box_output[1],
+                width: box_output[2],
+                height: box_output[3],
+            });
+            YoloDetectResult {
+                score,
+                class_id,
+                label: labels.label(class_id),
+                x: boxes.x,
+                y: boxes.y,
+                width: boxes.width,
+                height: boxes.height,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_batches_keep_order() {
+    assert_eq!(
+        batches((0..5).collect(), 2),
+        vec![vec![0, 1], vec![2, 3], vec![4]]
+    );
+    assert_eq!(batches((0..3).collect(), 8), vec![vec![0, 1, 2]]);
+    assert!(batches(Vec::<i32>::new(), 2).is_empty());
 }
 
-fn yolo_to_image_coords(
-    x_center: f32,
-    y_center: f32,
-    width: f32,
-    height: f32,
-    img_width: f32,
-    img_height: f32,
-    input_width: f32,
-    input_height: f32,
-) -> (f32, f32, f32, f32) {
-    // 计算缩放比例
-    let scale = f32::max(img_width / input_width, img_height / input_height);
-
-    // 计算原图在等比例缩放后的尺寸
-    let scaled_img_width = img_width / scale;
-    let scaled_img_height = img_height / scale;
-
-    // 计算平移的量
-    let x_move = (scaled_img_width - input_width).abs() / 2.0;
-    let y_move = (scaled_img_height - input_height).abs() / 2.0;
-
-    // 映射回原图的坐标系
-    let ret_x_center = (x_center - x_move) * scale;
-    let ret_y_center = (y_center - y_move) * scale;
-    let ret_width = width * scale;
-    let ret_height = height * scale;
-
-    (ret_x_center, ret_y_center, ret_width, ret_height)
+#[test]
+fn test_split_detections_per_image() {
+    use ndarray::Array3;
+
+    let labels = YoloLabels::from_ultralytics_names("{0: 'highway', 1: 'sidewalk'}").unwrap();
+    // Two images with three boxes each, a row of x, y, width, height and two class scores per box
+    let mut output = Array3::<f32>::zeros((2, 6, 3));
+    let mut set_box = |image: usize, index: usize, values: [f32; 6]| {
+        for (row, value) in values.into_iter().enumerate() {
+            output[[image, row, index]] = value;
+        }
+    };
+    set_box(0, 0, [320.0, 140.0, 64.0, 32.0, 0.9, 0.1]);
+    set_box(0, 1, [100.0, 100.0, 10.0, 10.0, 0.1, 0.2]);
+    set_box(0, 2, [320.0, 320.0, 10.0, 10.0, 0.0, 0.6]);
+    set_box(1, 1, [320.0, 320.0, 64.0, 64.0, 0.3, 0.8]);
+
+    // Padded above and below, then left and right
+    let letterboxes = [
+        Letterbox::new((1280, 720), (640, 640)),
+        Letterbox::new((480, 640), (640, 640)),
+    ];
+    let results = split_detections(output.view(), &letterboxes, &labels, 0.25).unwrap();
+    let boxes = |image: usize| {
+        results[image]
+            .iter()
+            .map(|result| {
+                let (x, y, width, height) = (result.x, result.y, result.width, result.height);
+                (result.label.as_str(), [x, y, width, height])
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(
+        boxes(0),
+        vec![
+            ("highway", [640.0, 0.0, 128.0, 64.0]),
+            ("sidewalk", [640.0, 360.0, 20.0, 20.0]),
+        ]
+    );
+    assert_eq!(boxes(1), vec![("sidewalk", [240.0, 320.0, 64.0, 64.0])]);
+    assert_eq!(results[1][0].score, vec![0.3, 0.8]);
+
+    assert!(split_detections(output.view(), &letterboxes[..1], &labels, 0.25).is_err());
 }
@@ -1,181 +1,392 @@
-use crate::engine::entity::box_point::Box;
-use crate::engine::inference_engine::OnnxSession;
-use crate::utils::tensor::{linear_interpolate, sigmoid};
-use crate::{INFERENCE_YOLO, RUNNING_YOLO_DEVICE};
-use anyhow::Result;
+use crate::engine::inference_engine::{ExecutionProvider, OnnxSession};
+use crate::engine::session_pool::{PoolOptions, PoolStats, SessionPool};
+use crate::inference::yolo::inference_yolo_detect::YoloDetectResult;
+use crate::inference::yolo::labels::YoloLabels;
+#[cfg(feature = "cuda")]
+use crate::inference::yolo::normalise_on_device;
+use crate::inference::yolo::{
+    load_yolo_sessions, yolo_default_spec, NMSImplement, NmsOptions, NmsThresholds,
+    YOLO_SEGMENT_MODEL,
+};
+use crate::utils::graph;
+use crate::utils::letterbox::Letterbox;
+use crate::utils::tensor::{normalise_pixel_mean, sigmoid};
+use anyhow::{ensure, Result};
 use bitvec::prelude::*;
-use cudarc::driver::{DevicePtr, LaunchConfig, PushKernelArg};
-use log::debug;
-use ndarray::{s, Axis, Ix2, Ix3};
-use ort::memory::{AllocationDevice, AllocatorType, MemoryInfo, MemoryType};
-use ort::tensor::Shape;
-use ort::value::TensorRefMut;
-use parking_lot::Mutex;
+use log::{debug, info};
+use ndarray::{s, Array2, ArrayD, Axis, Ix2, Ix3};
+use ort::inputs;
+use ort::value::Tensor;
 use rayon::prelude::*;
-use spark_media::filter::filter::AVFilter;
 use spark_media::Image;
-use std::cmp::Ordering;
 use std::ops::Deref;
+use std::path::Path;
 
-pub struct YoloSegmentSession(Mutex<OnnxSession>);
+pub struct YoloSegmentSession {
+    sessions: SessionPool<OnnxSession>,
+    labels: YoloLabels,
+    /// `(width, height)` frames are letterboxed to.
+    input_size: (usize, usize),
+}
+
+impl YoloSegmentSession {
+    /// Runs on [`ExecutionProvider::default`].
+    pub fn new(folder_path: impl AsRef<Path>) -> Result<Self> {
+        Self::with_provider(folder_path, ExecutionProvider::default())
+    }
+
+    /// A single session on `provider`.
+    pub fn with_provider(
+        folder_path: impl AsRef<Path>,
+        provider: ExecutionProvider,
+    ) -> Result<Self> {
+        Self::with_pool(folder_path, provider, PoolOptions::default())
+    }
+
+    /// Loads `pool.size` sessions of `yolo_seg.onnx`, or of the `yolo_seg` model of the
+    /// folder's manifest, from `folder_path`. Labels are looked up like for the detect model.
+    pub fn with_pool(
+        folder_path: impl AsRef<Path>,
+        provider: ExecutionProvider,
+        pool: PoolOptions,
+    ) -> Result<Self> {
+        let (sessions, input_size, _) = load_yolo_sessions(
+            &folder_path,
+            YOLO_SEGMENT_MODEL,
+            yolo_default_spec("yolo_seg.onnx", &["boxes", "prototypes"]),
+            provider,
+            pool,
+        )?;
+        let labels = YoloLabels::load(folder_path, &sessions.checkout()?)?;
+        let yolo_segment_session = Self {
+            sessions,
+            labels,
+            input_size,
+        };
+        info!("Yolo Segment Session created");
+
+        Ok(yolo_segment_session)
+    }
+
+    pub fn labels(&self) -> &YoloLabels {
+        &self.labels
+    }
+}
 
 impl Deref for YoloSegmentSession {
-    type Target = Mutex<OnnxSession>;
+    type Target = SessionPool<OnnxSession>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.sessions
     }
 }
 
 pub trait YoloSegmentInference {
+    /// Detects, suppresses and segments in one run. Boxes are in image coordinates like the ones
+    /// of the detect model. Each mask covers the whole image row by row, stretched to
+    /// `out_put_size` when given like the SAM masks, and is limited to its box.
     fn inference_yolo(
         &self,
-        tensor: Image,
-        confidence: f32,
+        image: Image,
+        thresholds: &[NmsThresholds],
+        options: NmsOptions,
         probability_mask: f32,
+        out_put_size: Option<(i32, i32)>,
     ) -> Result<Vec<YoloInferenceResult>>;
+
+    /// Usage of the sessions behind the model, empty for backends without any.
+    fn pool_stats(&self) -> Vec<PoolStats> {
+        Vec::new()
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct YoloInferenceResult {
-    pub boxed: Box,
-    pub classify: usize,
+    pub detection: YoloDetectResult,
     pub mask: BitVec,
-    pub score: f32,
+}
+
+/// A box before NMS, with the row of its mask coefficients in the model output.
+struct Candidate {
+    detection: YoloDetectResult,
+    row: usize,
+}
+
+impl AsRef<YoloDetectResult> for Candidate {
+    fn as_ref(&self) -> &YoloDetectResult {
+        &self.detection
+    }
+}
+
+impl AsMut<YoloDetectResult> for Candidate {
+    fn as_mut(&mut self) -> &mut YoloDetectResult {
+        &mut self.detection
+    }
 }
 
 impl YoloSegmentInference for YoloSegmentSession {
     fn inference_yolo(
         &self,
         mut image: Image,
-        conf_thres: f32,
-        iou_thres: f32,
+        thresholds: &[NmsThresholds],
+        options: NmsOptions,
+        probability_mask: f32,
+        out_put_size: Option<(i32, i32)>,
     ) -> Result<Vec<YoloInferenceResult>> {
-        let filter = AVFilter::builder(image.pixel_format()?, image.get_size())?
-            .add_context("scale", "640:640:force_original_aspect_ratio=decrease")?
-            .add_context("pad", "640:640:(ow-iw)/2:(oh-ih)/2:#727272")?
-            .add_context("format", "rgb24")?
-            .build()?;
-        image.apply_filter(&filter)?;
-
-        let stream = INFERENCE_YOLO.new_stream()?;
-        let tensor = {
-            let buffer = stream.memcpy_stod(image.raw_data()?.as_slice())?;
-            let cfg = LaunchConfig::for_num_elems((buffer.len() / 3) as u32);
-
-            let tensor: TensorRefMut<'_, f32> = unsafe {
-                let image_size = buffer.len();
-                let mut tensor = stream.alloc::<f32>(image_size)?;
-
-                let mut builder = stream.launch_builder(INFERENCE_YOLO.normalise_pixel_div());
-                builder.arg(&mut tensor);
-                builder.arg(&buffer);
-                builder.arg(&image_size);
-                builder.launch(cfg)?;
-
-                let back = TensorRefMut::from_raw(
-                    MemoryInfo::new(
-                        AllocationDevice::CUDA,
-                        RUNNING_YOLO_DEVICE,
-                        AllocatorType::Device,
-                        MemoryType::Default,
-                    )?,
-                    (tensor.device_ptr(&stream).0 as usize as *mut ()).cast(),
-                    Shape::new([1, 3, 640, 640]),
-                )?;
-
-                back
-            };
+        let (width, height) = self.input_size;
+        let (image_width, image_height) = image.get_size();
+        let out_size = out_put_size.unwrap_or((image_width, image_height));
+        let out_size = (out_size.0 as usize, out_size.1 as usize);
 
-            tensor
-        };
+        let letterbox = Letterbox::apply(&mut image, self.input_size)?;
 
         let (output_first, output_second) = {
-            debug!("Finish copying tensor to device");
-            let mut guard = self.lock();
-            let outputs = guard.run([tensor.into()])?;
+            let mut guard = self.checkout()?;
+            let executor = guard.executor;
+            let (mean, std) = guard.spec.normalisation();
+            let boxes = guard.spec.output("boxes")?.to_string();
+            let prototypes = guard.spec.output("prototypes")?.to_string();
+            let outputs = match executor {
+                #[cfg(feature = "cuda")]
+                ExecutionProvider::CUDA(_) | ExecutionProvider::TensorRT(_) => {
+                    let (_buffer, tensor) =
+                        normalise_on_device(image.raw_data()?.as_slice(), self.input_size)?;
+                    debug!("Finish copying tensor to device");
+                    guard.run([tensor.into()])?
+                }
+                _ => {
+                    let tensor = normalise_pixel_mean(
+                        image.raw_data()?.as_slice(),
+                        width,
+                        height,
+                        mean,
+                        std,
+                    )?;
+                    guard.run(inputs![Tensor::from_array(tensor)?])?
+                }
+            };
             debug!("Finish running model");
 
-            let output_first = outputs["output0"]
-                .try_extract_array::<f32>()?
-                .t()
-                .into_owned();
             (
-                output_first.squeeze().into_dimensionality::<Ix2>()?,
-                outputs["output1"].try_extract_array::<f32>()?.into_owned(),
+                outputs[boxes.as_str()]
+                    .try_extract_array::<f32>()?
+                    .into_owned(),
+                outputs[prototypes.as_str()]
+                    .try_extract_array::<f32>()?
+                    .into_owned(),
             )
         };
-        let output_second = output_second.squeeze().into_dimensionality::<Ix3>()?;
-        let output_second = output_second.to_shape((32, 25600))?;
+        let output = SegmentOutput::new(output_first, output_second)?;
+
+        let candidates = output
+            .candidates(&letterbox, &self.labels, thresholds)
+            .non_maximum_suppression(thresholds, options);
+        debug!("Segmenting {} boxes", candidates.len());
+
+        output.segment(candidates, &letterbox, probability_mask, out_size)
+    }
+
+    fn pool_stats(&self) -> Vec<PoolStats> {
+        vec![self.stats()]
+    }
+}
 
-        debug!("output_first: {:?}", output_first.shape());
-        debug!("output_second: {:?}", output_second.shape());
+/// The two outputs of one run, per box its centre and size, class scores and mask coefficients,
+/// and the mask prototypes the coefficients weigh.
+struct SegmentOutput {
+    /// `[boxes, 4 + classes + masks]`.
+    boxes: Array2<f32>,
+    /// `[masks, height * width]`.
+    prototypes: Array2<f32>,
+    /// `(width, height)` of the prototypes, a fraction of the input size.
+    prototype_size: (usize, usize),
+    class_count: usize,
+}
+
+impl SegmentOutput {
+    /// Takes the `[1, 4 + classes + masks, boxes]` and `[1, masks, height, width]` outputs,
+    /// the number of classes and masks being read from their shapes.
+    fn new(boxes: ArrayD<f32>, prototypes: ArrayD<f32>) -> Result<Self> {
+        let boxes = boxes
+            .reversed_axes()
+            .squeeze()
+            .into_dimensionality::<Ix2>()?;
+        let prototypes = prototypes.squeeze().into_dimensionality::<Ix3>()?;
+        let (mask_channels, proto_height, proto_width) = prototypes.dim();
+        let prototypes = prototypes
+            .as_standard_layout()
+            .into_owned()
+            .into_shape_with_order((mask_channels, proto_height * proto_width))?;
+        ensure!(
+            boxes.ncols() > 4 + mask_channels,
+            "Yolo segment output has {} values per box, expected more than {}",
+            boxes.ncols(),
+            4 + mask_channels
+        );
+        debug!("boxes: {:?}", boxes.shape());
+        debug!("prototypes: {:?}", prototypes.shape());
 
-        let mask = output_first
+        Ok(Self {
+            class_count: boxes.ncols() - 4 - mask_channels,
+            boxes,
+            prototypes,
+            prototype_size: (proto_width, proto_height),
+        })
+    }
+
+    /// The boxes scoring at least the lowest of `thresholds`, in image coordinates.
+    fn candidates(
+        &self,
+        letterbox: &Letterbox,
+        labels: &YoloLabels,
+        thresholds: &[NmsThresholds],
+    ) -> Vec<Candidate> {
+        let min_score = thresholds
+            .iter()
+            .map(|thresholds| thresholds.score)
+            .fold(f32::INFINITY, f32::min);
+
+        self.boxes
             .axis_iter(Axis(0))
-            .into_par_iter()
-            .filter(|box_output| {
-                box_output
-                    .slice(s![4..box_output.len() - 32])
-                    .iter()
-                    .any(|&score| score > conf_thres)
-            })
-            .map(|box_output| {
-                let score = box_output.slice(s![4..box_output.len() - 32]);
-                let score = score
+            .enumerate()
+            .filter_map(|(row, box_output)| {
+                let score = box_output.slice(s![4..4 + self.class_count]).to_vec();
+                let (class_id, &class_score) = score
                     .iter()
-                    .max_by(|&a, &b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
-                    .unwrap_or(&0.0);
-                (box_output, *score)
-            })
-            .map(|(box_output, score)| {
-                let (x, y, w, h) = (box_output[0], box_output[1], box_output[2], box_output[3]);
-                let x1 = x - w / 2.;
-                let y1 = y - h / 2.;
-                let x2 = x + w / 2.;
-                let y2 = y + h / 2.;
-
-                let feature_mask = box_output.slice(s![box_output.len() - 32..]);
-                let feature_mask = feature_mask.to_shape((1, 32)).unwrap();
-
-                let mask = {
-                    let mask = feature_mask.dot(&output_second);
-                    let mask = mask.into_shape_with_order((160, 160)).unwrap();
-                    let mask = linear_interpolate(mask.into_owned(), (640, 640));
-                    let mask = sigmoid(mask);
-                    let x1 = x1 as usize;
-                    let y1 = y1 as usize;
-                    let x2 = x2 as usize;
-                    let y2 = y2 as usize;
-
-                    mask.iter()
-                        .enumerate()
-                        .map(|(index, value)| {
-                            let y = index / 640;
-                            let x = index % 640;
-                            (y1..y2).contains(&y) && (x1..x2).contains(&x) && *value > iou_thres
-                        })
-                        .collect::<BitVec>()
+                    .enumerate()
+                    .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+                if class_score < min_score {
+                    return None;
+                }
+
+                let boxes = letterbox.box_to_source(graph::Box {
+                    x: box_output[0],
+                    y: box_output[1],
+                    width: box_output[2],
+                    height: box_output[3],
+                });
+                let detection = YoloDetectResult {
+                    label: labels.label(class_id),
+                    score,
+                    class_id,
+                    x: boxes.x,
+                    y: boxes.y,
+                    width: boxes.width,
+                    height: boxes.height,
                 };
+                Some(Candidate { detection, row })
+            })
+            .collect()
+    }
+
+    /// The mask of each candidate over `out_size` (`(width, height)`), which stretches the
+    /// image, limited to the candidate's box.
+    fn segment(
+        &self,
+        candidates: Vec<Candidate>,
+        letterbox: &Letterbox,
+        probability_mask: f32,
+        out_size: (usize, usize),
+    ) -> Result<Vec<YoloInferenceResult>> {
+        let (out_width, out_height) = out_size;
+        let (proto_width, proto_height) = self.prototype_size;
+        let scale_x = out_width as f32 / letterbox.source_size.0 as f32;
+        let scale_y = out_height as f32 / letterbox.source_size.1 as f32;
+
+        candidates
+            .into_par_iter()
+            .map(|Candidate { detection, row }| {
+                let coefficients = self.boxes.slice(s![row, 4 + self.class_count..]);
+                let mask = coefficients
+                    .dot(&self.prototypes)
+                    .into_shape_with_order((proto_height, proto_width))?;
+                // Only the part of the prototypes behind the image, the letterbox padding is cut off
+                let mask = sigmoid(letterbox.mask_to_source(mask.view(), out_size));
 
-                let boxed = Box { x1, y1, x2, y2 };
-                let classify = box_output
-                    .slice(s![4..box_output.len() - 32])
+                let [x1, y1, x2, y2] = detection.corners();
+                let (x1, x2) = (x1 * scale_x, x2 * scale_x);
+                let (y1, y2) = (y1 * scale_y, y2 * scale_y);
+                let mask = mask
                     .iter()
                     .enumerate()
-                    .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-                    .map(|(index, _)| index)
-                    .unwrap();
-
-                YoloInferenceResult {
-                    boxed,
-                    classify,
-                    mask,
-                    score,
-                }
-            })
-            .collect::<Vec<_>>();
+                    .map(|(index, &value)| {
+                        let y = (index / out_width) as f32;
+                        let x = (index % out_width) as f32;
+                        x >= x1 && x < x2 && y >= y1 && y < y2 && value > probability_mask
+                    })
+                    .collect::<BitVec>();
 
-        Ok(mask)
+                Ok(YoloInferenceResult { detection, mask })
+            })
+            .collect()
     }
 }
+
+#[test]
+fn test_segment_output_on_letterbox() {
+    use ndarray::{Array3, Array4};
+
+    let labels = YoloLabels::from_ultralytics_names("{0: 'highway', 1: 'sidewalk'}").unwrap();
+    // Four boxes, each x, y, width, height, two class scores and two mask coefficients
+    let mut boxes = Array3::<f32>::zeros((1, 8, 4));
+    let mut set_box = |index: usize, values: [f32; 8]| {
+        for (row, value) in values.into_iter().enumerate() {
+            boxes[[0, row, index]] = value;
+        }
+    };
+    // The whole image, its left half, a box below the score threshold and the right half
+    set_box(0, [320.0, 320.0, 640.0, 360.0, 0.9, 0.1, 1.0, 0.0]);
+    set_box(1, [160.0, 320.0, 320.0, 360.0, 0.1, 0.8, 1.0, 0.0]);
+    set_box(2, [320.0, 320.0, 64.0, 64.0, 0.1, 0.1, 1.0, 0.0]);
+    set_box(3, [480.0, 320.0, 320.0, 360.0, 0.1, 0.7, -1.0, 0.0]);
+
+    // 1280x720 fills rows 140 to 500 of the 640x640 input, rows 3 to 12 of 16 prototype rows.
+    // The first prototype is set over the image only, taller than wide to catch swapped sides
+    let mut prototypes = Array4::<f32>::zeros((1, 2, 16, 8));
+    prototypes.slice_mut(s![0, 0, .., ..]).fill(-10.0);
+    prototypes.slice_mut(s![0, 0, 3..13, ..]).fill(10.0);
+
+    let letterbox = Letterbox::new((1280, 720), (640, 640));
+    let output = SegmentOutput::new(boxes.clone().into_dyn(), prototypes.into_dyn()).unwrap();
+    assert_eq!(output.class_count, 2);
+    assert_eq!(output.prototype_size, (8, 16));
+
+    let thresholds = [NmsThresholds {
+        iou: 0.5,
+        score: 0.25,
+    }; 2];
+    let candidates = output
+        .candidates(&letterbox, &labels, &thresholds)
+        .non_maximum_suppression(&thresholds, NmsOptions::default());
+    let results = output
+        .segment(candidates, &letterbox, 0.5, (32, 18))
+        .unwrap();
+
+    let summary = results
+        .iter()
+        .map(|result| {
+            let detection = &result.detection;
+            let corners = [detection.x, detection.y, detection.width, detection.height];
+            (detection.label.as_str(), corners, result.mask.count_ones())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        vec![
+            // Padding cut off, so the image is covered from the first row to the last
+            ("highway", [640.0, 360.0, 1280.0, 720.0], 32 * 18),
+            ("sidewalk", [320.0, 360.0, 640.0, 720.0], 16 * 18),
+            ("sidewalk", [960.0, 360.0, 640.0, 720.0], 0),
+        ]
+    );
+    // Clipped to its box in output pixels, the left 16 columns
+    assert!(results[1]
+        .mask
+        .iter()
+        .enumerate()
+        .all(|(index, set)| *set == (index % 32 < 16)));
+
+    // More mask channels than values per box
+    let prototypes = Array4::<f32>::zeros((1, 5, 16, 8));
+    assert!(SegmentOutput::new(boxes.into_dyn(), prototypes.into_dyn()).is_err());
+}
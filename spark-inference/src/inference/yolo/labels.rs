@@ -0,0 +1,159 @@
+use crate::engine::inference_engine::OnnxSession;
+use anyhow::{anyhow, bail, Context, Result};
+use log::{info, warn};
+use std::path::Path;
+
+/// Sidecar file next to the model, one class name per line in class id order.
+pub const LABELS_FILE_NAME: &str = "labels.txt";
+/// Metadata key Ultralytics exports the class names under.
+const METADATA_NAMES_KEY: &str = "names";
+
+/// Class names of a YOLO model, indexed by class id.
+#[derive(Debug, Clone, Default)]
+pub struct YoloLabels(Vec<String>);
+
+impl YoloLabels {
+    /// Takes the classes of the model manifest first, then looks for a sidecar `labels.txt`
+    /// in `folder_path`, then for the `names` entry of the model metadata.
+    /// Models without any of them get generic `class N` labels.
+    pub fn load(folder_path: impl AsRef<Path>, session: &OnnxSession) -> Result<Self> {
+        let classes = &session.spec().classes;
+        if !classes.is_empty() {
+            info!(
+                "Loaded {} yolo labels from the model manifest",
+                classes.len()
+            );
+            return Ok(Self(classes.clone()));
+        }
+
+        let sidecar = folder_path.as_ref().join(LABELS_FILE_NAME);
+        if sidecar.is_file() {
+            let labels = Self::from_file(&sidecar)?;
+            info!(
+                "Loaded {} yolo labels from {}",
+                labels.len(),
+                sidecar.display()
+            );
+            return Ok(labels);
+        }
+
+        match session.metadata()?.custom(METADATA_NAMES_KEY)? {
+            Some(names) => {
+                let labels = Self::from_ultralytics_names(&names)?;
+                info!("Loaded {} yolo labels from model metadata", labels.len());
+                Ok(labels)
+            }
+            None => {
+                warn!("Yolo model has no class names, detections will be labelled by class id");
+                Ok(Self::default())
+            }
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read labels file {}", path.display()))?;
+
+        let labels = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        if labels.is_empty() {
+            bail!("Labels file {} is empty", path.display());
+        }
+
+        Ok(Self(labels))
+    }
+
+    /// Parses the Python dict repr Ultralytics writes into the ONNX metadata,
+    /// e.g. `{0: 'person', 1: 'bicycle', 2: "people's car"}`.
+    pub fn from_ultralytics_names(names: &str) -> Result<Self> {
+        let body = names
+            .trim()
+            .strip_prefix('{')
+            .and_then(|rest| rest.strip_suffix('}'))
+            .ok_or_else(|| anyhow!("Class names are not a dict: {}", names))?;
+
+        let mut entries = Vec::new();
+        let mut chars = body.chars().peekable();
+        loop {
+            while chars.next_if(|c| c.is_whitespace() || *c == ',').is_some() {}
+            if chars.peek().is_none() {
+                break;
+            }
+
+            let mut id = String::new();
+            while let Some(c) = chars.next_if(char::is_ascii_digit) {
+                id.push(c);
+            }
+            let id = id
+                .parse::<usize>()
+                .with_context(|| format!("Invalid class id in names: {}", names))?;
+
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            if chars.next() != Some(':') {
+                bail!("Expected ':' after class id {} in names: {}", id, names);
+            }
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+            let quote = chars
+                .next()
+                .filter(|c| *c == '\'' || *c == '"')
+                .ok_or_else(|| anyhow!("Expected a quoted name for class {}", id))?;
+            let mut name = String::new();
+            loop {
+                match chars.next() {
+                    Some('\\') => name.extend(chars.next()),
+                    Some(c) if c == quote => break,
+                    Some(c) => name.push(c),
+                    None => bail!("Unterminated name for class {}", id),
+                }
+            }
+
+            entries.push((id, name));
+        }
+
+        let mut labels =
+            vec![String::new(); entries.iter().map(|(id, _)| id + 1).max().unwrap_or(0)];
+        for (id, name) in entries {
+            labels[id] = name;
+        }
+
+        Ok(Self(labels))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Name of the class, or `class N` when the model did not name it.
+    pub fn label(&self, class_id: usize) -> String {
+        match self.0.get(class_id) {
+            Some(name) if !name.is_empty() => name.clone(),
+            _ => format!("class {}", class_id),
+        }
+    }
+}
+
+#[test]
+fn test_parse_ultralytics_names() {
+    let labels = YoloLabels::from_ultralytics_names(
+        "{0: 'person', 1: 'traffic light', 3: \"people's car\"}",
+    )
+    .unwrap();
+    assert_eq!(labels.len(), 4);
+    assert_eq!(labels.label(0), "person");
+    assert_eq!(labels.label(1), "traffic light");
+    assert_eq!(labels.label(2), "class 2");
+    assert_eq!(labels.label(3), "people's car");
+    assert_eq!(labels.label(7), "class 7");
+
+    assert!(YoloLabels::from_ultralytics_names("['person']").is_err());
+}
@@ -1,66 +1,495 @@
+use crate::engine::inference_engine::{ExecutionProvider, OnnxSession};
+use crate::engine::manifest::{ModelManifest, ModelSpec, TensorSpec};
+use crate::engine::session_pool::{PoolOptions, SessionPool};
+#[cfg(feature = "cuda")]
+use crate::{inference_yolo, RUNNING_YOLO_DEVICE};
+use anyhow::{ensure, Result};
+#[cfg(feature = "cuda")]
+use cudarc::driver::{CudaSlice, DevicePtr, LaunchConfig, PushKernelArg};
 use inference_yolo_detect::YoloDetectResult;
+#[cfg(feature = "cuda")]
+use ort::memory::{AllocationDevice, AllocatorType, MemoryInfo, MemoryType};
+#[cfg(feature = "cuda")]
+use ort::tensor::Shape;
+#[cfg(feature = "cuda")]
+use ort::value::TensorRefMut;
+use serde::{Deserialize, Serialize};
 
 pub mod inference_yolo_detect;
 pub mod inference_yolo_seg;
+pub mod labels;
+
+/// Side length of the square frames the YOLO models take, unless their manifest says otherwise.
+pub(crate) const YOLO_INPUT_SIZE: usize = 640;
+/// Largest downsampling of the YOLO backbones, input sides have to be a multiple of it.
+const YOLO_STRIDE: usize = 32;
+
+/// Manifest keys of the YOLO models.
+pub const YOLO_DETECT_MODEL: &str = "yolo_detect";
+pub const YOLO_SEGMENT_MODEL: &str = "yolo_seg";
+
+/// An Ultralytics export in `file`, whose outputs are called `output0`, `output1`, ...
+/// in the order of `output_roles`.
+pub(crate) fn yolo_default_spec(file: &str, output_roles: &[&str]) -> ModelSpec {
+    ModelSpec {
+        file: file.to_string(),
+        input_size: Some([YOLO_INPUT_SIZE; 2]),
+        outputs: output_roles
+            .iter()
+            .enumerate()
+            .map(|(index, role)| {
+                let name = format!("output{}", index);
+                (role.to_string(), TensorSpec::new(&name, None))
+            })
+            .collect(),
+        ..ModelSpec::default()
+    }
+}
+
+/// Loads the model `key` of the manifest in `folder_path` and checks what the sessions rely on
+/// beyond the declared tensors: an input size the backbone can downsample, e.g. 320, 640 or
+/// 1280, plain `[0, 1]` pixels on the GPU, where the kernel does not normalise, and
+/// `4 + classes + masks` values per box when the graph fixes that number.
+/// Returns the pool, the `(width, height)` frames are letterboxed to and how many frames
+/// one run takes at most, which is only limited for graphs exported with a fixed batch size.
+pub(crate) fn load_yolo_sessions(
+    folder_path: impl AsRef<std::path::Path>,
+    key: &str,
+    defaults: ModelSpec,
+    provider: ExecutionProvider,
+    pool: PoolOptions,
+) -> Result<(SessionPool<OnnxSession>, (usize, usize), usize)> {
+    let spec = ModelManifest::load(&folder_path)?.spec(key, defaults);
+    let sessions = OnnxSession::pool(folder_path, spec, provider, pool)?;
+    let session = sessions.checkout()?;
+    let spec = session.spec();
+
+    let (width, height) = spec.input_size()?;
+    ensure!(
+        width % YOLO_STRIDE == 0 && height % YOLO_STRIDE == 0,
+        "Yolo model {} takes {}x{} frames, both sides must be multiples of {}",
+        spec.file,
+        width,
+        height,
+        YOLO_STRIDE
+    );
+    if cfg!(feature = "cuda") && !matches!(provider, ExecutionProvider::CPU) {
+        ensure!(
+            spec.normalisation() == ([0.0; 3], [1.0; 3]),
+            "Yolo model {} sets mean and std, which the GPU path does not apply",
+            spec.file
+        );
+    }
+
+    let channels = |role: &str| {
+        session
+            .output_shape(role)
+            .and_then(|shape| shape.get(1).copied())
+            .filter(|&channels| channels > 0)
+    };
+    if let Some(values) = channels("boxes") {
+        let masks = channels("prototypes").unwrap_or(0);
+        let classes = spec.classes.len() as i64;
+        ensure!(
+            classes == 0 || values == 4 + classes + masks,
+            "Yolo model {} outputs {} values per box, {} classes need {}",
+            spec.file,
+            values,
+            classes,
+            4 + classes + masks
+        );
+    }
+
+    let max_batch = session
+        .input_shape(0)
+        .and_then(|shape| shape.first().copied())
+        .filter(|&batch| batch > 0)
+        .map_or(usize::MAX, |batch| batch as usize);
+
+    drop(session);
+    Ok((sessions, (width, height), max_batch))
+}
+
+/// Uploads packed rgb24 frames of `(width, height)`, stored back to back, and scales them to
+/// `[0, 1]` with the CUDA kernel, one launch per frame, into an `Nx3xHxW` tensor.
+/// The returned slice owns the memory the tensor points into, keep it alive until the model ran.
+#[cfg(feature = "cuda")]
+pub(crate) fn normalise_on_device(
+    rgb: &[u8],
+    (width, height): (usize, usize),
+) -> anyhow::Result<(CudaSlice<f32>, TensorRefMut<'static, f32>)> {
+    let frame_size = width * height * 3;
+    ensure!(
+        frame_size > 0 && !rgb.is_empty() && rgb.len().is_multiple_of(frame_size),
+        "Expected a multiple of {} bytes of rgb24 for {}x{} frames, got {}",
+        frame_size,
+        width,
+        height,
+        rgb.len()
+    );
+    let batch = rgb.len() / frame_size;
+
+    let cuda = inference_yolo()?;
+    let stream = cuda.new_stream()?;
+    let buffer = stream.memcpy_stod(rgb)?;
+    let cfg = LaunchConfig::for_num_elems((width * height) as u32);
+
+    unsafe {
+        let mut tensor = stream.alloc::<f32>(rgb.len())?;
+        for frame in 0..batch {
+            let range = frame * frame_size..(frame + 1) * frame_size;
+            let input = buffer.slice(range.clone());
+            let mut output = tensor.slice_mut(range);
+
+            let mut builder = stream.launch_builder(cuda.normalise_pixel_div());
+            builder.arg(&mut output);
+            builder.arg(&input);
+            builder.arg(&frame_size);
+            builder.launch(cfg)?;
+        }
+
+        let back = TensorRefMut::from_raw(
+            MemoryInfo::new(
+                AllocationDevice::CUDA,
+                RUNNING_YOLO_DEVICE,
+                AllocatorType::Device,
+                MemoryType::Default,
+            )?,
+            (tensor.device_ptr(&stream).0 as usize as *mut ()).cast(),
+            Shape::new([batch as i64, 3, height as i64, width as i64]),
+        )?;
+
+        Ok((tensor, back))
+    }
+}
+
+/// How the `x` and `y` of a box are to be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoxFormat {
+    /// `x`/`y` is the centre of the box, what YOLO outputs.
+    Center,
+    /// `x`/`y` is the top left corner.
+    TopLeft,
+}
+
+impl BoxFormat {
+    /// `[x1, y1, x2, y2]` of a box given in this format.
+    pub fn corners(self, x: f32, y: f32, width: f32, height: f32) -> [f32; 4] {
+        match self {
+            BoxFormat::Center => [
+                x - width / 2.0,
+                y - height / 2.0,
+                x + width / 2.0,
+                y + height / 2.0,
+            ],
+            BoxFormat::TopLeft => [x, y, x + width, y + height],
+        }
+    }
+}
+
+/// Intersection over union of two boxes given as `[x1, y1, x2, y2]`, 0 when both are empty.
+pub fn box_iou(first: [f32; 4], second: [f32; 4]) -> f32 {
+    let intersection_width = (first[2].min(second[2]) - first[0].max(second[0])).max(0.0);
+    let intersection_height = (first[3].min(second[3]) - first[1].max(second[1])).max(0.0);
+    let intersection_area = intersection_width * intersection_height;
+
+    let first_area = (first[2] - first[0]) * (first[3] - first[1]);
+    let second_area = (second[2] - second[0]) * (second[3] - second[1]);
+    let union_area = first_area + second_area - intersection_area;
+
+    if union_area <= 0.0 {
+        0.0
+    } else {
+        intersection_area / union_area
+    }
+}
+
+/// Distance IoU of two boxes given as `[x1, y1, x2, y2]`: the IoU minus the squared distance
+/// of the centres over the squared diagonal of the smallest box enclosing both.
+pub fn box_diou(first: [f32; 4], second: [f32; 4]) -> f32 {
+    let center_x = (first[0] + first[2] - second[0] - second[2]) / 2.0;
+    let center_y = (first[1] + first[3] - second[1] - second[3]) / 2.0;
+    let enclosing_width = first[2].max(second[2]) - first[0].min(second[0]);
+    let enclosing_height = first[3].max(second[3]) - first[1].min(second[1]);
+    let diagonal = enclosing_width * enclosing_width + enclosing_height * enclosing_height;
+
+    if diagonal <= 0.0 {
+        return box_iou(first, second);
+    }
+    box_iou(first, second) - (center_x * center_x + center_y * center_y) / diagonal
+}
 
 impl YoloDetectResult {
+    /// `[x1, y1, x2, y2]` of the box, `x`/`y` being its centre.
+    pub fn corners(&self) -> [f32; 4] {
+        BoxFormat::Center.corners(self.x, self.y, self.width, self.height)
+    }
+
+    /// Score of the class the box belongs to.
+    pub fn class_score(&self) -> f32 {
+        self.score.get(self.class_id).copied().unwrap_or(0.0)
+    }
+
     // 计算两个检测框的IoU
     pub fn iou(&self, other: &YoloDetectResult) -> f32 {
-        let x1 = self.x.max(other.x);
-        let y1 = self.y.max(other.y);
-        let x2 = (self.x + self.width).min(other.x + other.width);
-        let y2 = (self.y + self.height).min(other.y + other.height);
+        box_iou(self.corners(), other.corners())
+    }
+
+    pub fn diou(&self, other: &YoloDetectResult) -> f32 {
+        box_diou(self.corners(), other.corners())
+    }
+}
 
-        let intersection_width = (x2 - x1).max(0.0);
-        let intersection_height = (y2 - y1).max(0.0);
-        let intersection_area = intersection_width * intersection_height;
+/// Thresholds of one class: boxes below `score` are dropped,
+/// boxes overlapping a better one of the same class by `iou` or more are suppressed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NmsThresholds {
+    pub iou: f32,
+    pub score: f32,
+}
 
-        let self_area = self.width * self.height;
-        let other_area = other.width * other.height;
+/// How overlapping boxes of the same class are dealt with.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "strategy", rename_all = "lowercase", deny_unknown_fields)]
+pub enum NmsStrategy {
+    /// Drops every box whose IoU with a kept box reaches the threshold.
+    #[default]
+    Hard,
+    /// Gaussian Soft-NMS: instead of being dropped, the score of an overlapping box decays by
+    /// `exp(-iou² / sigma)`, and the box only goes once it falls below the score threshold.
+    /// The IoU threshold is not used.
+    Soft { sigma: f32 },
+    /// Like `Hard`, but compares the distance IoU, so overlapping boxes whose centres are
+    /// far apart, e.g. two people side by side, are both kept.
+    DIoU,
+}
 
-        intersection_area / (self_area + other_area - intersection_area)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NmsOptions {
+    pub strategy: NmsStrategy,
+    /// Keeps at most this many boxes over all classes, the best ones.
+    pub max_detections: Option<usize>,
+}
+
+impl AsRef<YoloDetectResult> for YoloDetectResult {
+    fn as_ref(&self) -> &YoloDetectResult {
+        self
+    }
+}
+
+impl AsMut<YoloDetectResult> for YoloDetectResult {
+    fn as_mut(&mut self) -> &mut YoloDetectResult {
+        self
     }
 }
 
 pub trait NMSImplement {
-    fn non_maximum_suppression(
-        self,
-        iou_threshold: f32,
-        score_threshold: f32,
-        class_index: usize,
-    ) -> Vec<YoloDetectResult>;
-}
-
-impl NMSImplement for Vec<YoloDetectResult> {
-    fn non_maximum_suppression(
-        self,
-        iou_threshold: f32,
-        score_threshold: f32,
-        class_index: usize, // 指定分类的索引
-    ) -> Vec<YoloDetectResult> {
-        let mut filtered_detections: Vec<YoloDetectResult> = self
+    /// Class aware NMS over all classes at once. A box belongs to its `class_id` and is judged
+    /// by the thresholds at that index, boxes of classes without thresholds are dropped.
+    /// Boxes only suppress boxes of their own class. The result is sorted by score, best first.
+    fn non_maximum_suppression(self, thresholds: &[NmsThresholds], options: NmsOptions) -> Self;
+}
+
+/// Works on anything carrying a detection, so callers can keep data next to each box.
+impl<T> NMSImplement for Vec<T>
+where
+    T: AsRef<YoloDetectResult> + AsMut<YoloDetectResult>,
+{
+    fn non_maximum_suppression(self, thresholds: &[NmsThresholds], options: NmsOptions) -> Self {
+        let max_detections = options.max_detections.unwrap_or(usize::MAX);
+        let above_threshold = |detection: &YoloDetectResult| {
+            thresholds
+                .get(detection.class_id)
+                .is_some_and(|thresholds| detection.class_score() >= thresholds.score)
+        };
+        let mut candidates = self
             .into_iter()
-            .filter(|d| d.score[class_index] >= score_threshold)
-            .collect();
+            .filter(|candidate| above_threshold(candidate.as_ref()))
+            .collect::<Vec<_>>();
+        let mut result: Vec<T> = Vec::new();
 
-        // 根据分类得分排序（降序）
-        filtered_detections.sort_by(|a, b| {
-            b.score[class_index]
-                .partial_cmp(&a.score[class_index])
-                .unwrap_or(std::cmp::Ordering::Equal)
-        });
+        if let NmsStrategy::Soft { sigma } = options.strategy {
+            // Scores change after every pick, so the best box is searched again each round
+            while result.len() < max_detections {
+                let best = candidates
+                    .iter()
+                    .enumerate()
+                    .max_by(|(_, a), (_, b)| {
+                        a.as_ref()
+                            .class_score()
+                            .total_cmp(&b.as_ref().class_score())
+                    })
+                    .map(|(index, _)| index);
+                let Some(best) = best.map(|index| candidates.swap_remove(index)) else {
+                    break;
+                };
+
+                let kept = best.as_ref();
+                for detection in candidates
+                    .iter_mut()
+                    .map(|candidate| candidate.as_mut())
+                    .filter(|detection| detection.class_id == kept.class_id)
+                {
+                    let iou = kept.iou(detection);
+                    if let Some(score) = detection.score.get_mut(kept.class_id) {
+                        *score *= (-iou * iou / sigma).exp();
+                    }
+                }
+                candidates.retain(|candidate| above_threshold(candidate.as_ref()));
+                result.push(best);
+            }
 
-        let mut result: Vec<YoloDetectResult> = Vec::new();
+            return result;
+        }
 
-        while !filtered_detections.is_empty() {
-            // 选择得分最高的框并移除
-            let best = filtered_detections.remove(0);
-            result.push(best.clone());
+        // 根据分类得分排序（降序）
+        candidates.sort_by(|a, b| {
+            b.as_ref()
+                .class_score()
+                .total_cmp(&a.as_ref().class_score())
+        });
+        for candidate in candidates {
+            if result.len() >= max_detections {
+                break;
+            }
 
-            // 过滤掉与当前框的IoU超过阈值的框
-            filtered_detections.retain(|d| best.iou(d) < iou_threshold);
+            let detection = candidate.as_ref();
+            let iou_threshold = thresholds[detection.class_id].iou;
+            let suppressed = result
+                .iter()
+                .map(|kept| kept.as_ref())
+                .filter(|kept| kept.class_id == detection.class_id)
+                .any(|kept| {
+                    let overlap = match options.strategy {
+                        NmsStrategy::DIoU => kept.diou(detection),
+                        _ => kept.iou(detection),
+                    };
+                    overlap >= iou_threshold
+                });
+            if !suppressed {
+                result.push(candidate);
+            }
         }
 
         result
     }
 }
+
+#[cfg(test)]
+fn detection(
+    class_id: usize,
+    score: f32,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+) -> YoloDetectResult {
+    let mut scores = vec![0.0; 2];
+    scores[class_id] = score;
+    YoloDetectResult {
+        score: scores,
+        class_id,
+        label: class_id.to_string(),
+        x,
+        y,
+        width,
+        height,
+    }
+}
+
+#[test]
+fn test_iou_reads_centre_boxes() {
+    // (0, 0)-(10, 10) around (0, 0)-(4, 4): 16 / 100
+    let outer = detection(0, 0.9, 5.0, 5.0, 10.0, 10.0);
+    let inner = detection(0, 0.9, 2.0, 2.0, 4.0, 4.0);
+    assert!((outer.iou(&inner) - 0.16).abs() < 1e-6);
+
+    let top_left = BoxFormat::TopLeft.corners(0.0, 0.0, 4.0, 4.0);
+    assert_eq!(top_left, inner.corners());
+    assert_eq!(box_iou(top_left, [10.0, 10.0, 12.0, 12.0]), 0.0);
+    assert_eq!(box_iou([1.0, 1.0, 1.0, 1.0], [1.0, 1.0, 1.0, 1.0]), 0.0);
+
+    // Half shifted: IoU 50 / 150, centres 5 apart, enclosing box 15 x 10, so 1/3 - 25/325
+    let shifted = detection(0, 0.9, 10.0, 5.0, 10.0, 10.0);
+    assert!((outer.iou(&shifted) - 1.0 / 3.0).abs() < 1e-6);
+    assert!((outer.diou(&shifted) - (1.0 / 3.0 - 25.0 / 325.0)).abs() < 1e-6);
+}
+
+#[test]
+fn test_nms_is_class_aware() {
+    let thresholds = [NmsThresholds {
+        iou: 0.5,
+        score: 0.3,
+    }; 2];
+    // The second box overlaps the first by 90 / 110, the third is the same box in the other class
+    let detections = vec![
+        detection(0, 0.8, 6.0, 5.0, 10.0, 10.0),
+        detection(0, 0.9, 5.0, 5.0, 10.0, 10.0),
+        detection(1, 0.7, 6.0, 5.0, 10.0, 10.0),
+        detection(0, 0.6, 50.0, 50.0, 10.0, 10.0),
+        detection(0, 0.2, 80.0, 80.0, 10.0, 10.0),
+    ];
+
+    let kept = detections
+        .clone()
+        .non_maximum_suppression(&thresholds, NmsOptions::default());
+    let kept = kept
+        .iter()
+        .map(|detection| (detection.class_id, detection.class_score()))
+        .collect::<Vec<_>>();
+    assert_eq!(kept, vec![(0, 0.9), (1, 0.7), (0, 0.6)]);
+
+    let capped = detections.clone().non_maximum_suppression(
+        &thresholds,
+        NmsOptions {
+            max_detections: Some(2),
+            ..Default::default()
+        },
+    );
+    assert_eq!(capped.len(), 2);
+
+    let only_highway = detections.non_maximum_suppression(&thresholds[..1], NmsOptions::default());
+    assert!(only_highway.iter().all(|detection| detection.class_id == 0));
+}
+
+#[test]
+fn test_soft_and_distance_nms() {
+    let thresholds = [NmsThresholds {
+        iou: 0.3,
+        score: 0.1,
+    }; 2];
+    let detections = vec![
+        detection(0, 0.9, 5.0, 5.0, 10.0, 10.0),
+        detection(0, 0.8, 6.0, 5.0, 10.0, 10.0),
+    ];
+
+    // 0.8 * exp(-(90 / 110)² / 0.5)
+    let soft = detections.clone().non_maximum_suppression(
+        &thresholds,
+        NmsOptions {
+            strategy: NmsStrategy::Soft { sigma: 0.5 },
+            max_detections: None,
+        },
+    );
+    assert_eq!(soft.len(), 2);
+    assert!((soft[1].class_score() - 0.2097).abs() < 1e-4);
+
+    // IoU 1/3 is over the threshold, DIoU 0.256 is not
+    let side_by_side = vec![
+        detection(0, 0.9, 5.0, 5.0, 10.0, 10.0),
+        detection(0, 0.8, 10.0, 5.0, 10.0, 10.0),
+    ];
+    let hard = side_by_side
+        .clone()
+        .non_maximum_suppression(&thresholds, NmsOptions::default());
+    assert_eq!(hard.len(), 1);
+    let distance = side_by_side.non_maximum_suppression(
+        &thresholds,
+        NmsOptions {
+            strategy: NmsStrategy::DIoU,
+            max_detections: None,
+        },
+    );
+    assert_eq!(distance.len(), 2);
+}
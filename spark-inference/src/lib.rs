@@ -2,21 +2,44 @@
 #![allow(dead_code)]
 #![cfg_attr(debug_assertions, allow(warnings))]
 
+#[cfg(feature = "cuda")]
 pub(crate) mod cuda;
 pub mod engine;
 pub mod inference;
 pub mod utils;
 
+#[cfg(feature = "cuda")]
 use crate::cuda::cuda_sam::CudaSam;
+#[cfg(feature = "cuda")]
 use crate::cuda::cuda_yolo::CudaYolo;
+#[cfg(feature = "cuda")]
 use std::sync::LazyLock;
 
 const RUNNING_SAM_DEVICE: i32 = 0;
 const RUNNING_YOLO_DEVICE: i32 = 0;
 
-pub(crate) static INFERENCE_SAM: LazyLock<CudaSam> =
-    LazyLock::new(|| CudaSam::new(RUNNING_SAM_DEVICE as usize).unwrap());
-pub(crate) static INFERENCE_YOLO: LazyLock<CudaYolo> =
-    LazyLock::new(|| CudaYolo::new(RUNNING_YOLO_DEVICE as usize).unwrap());
+/// CUDA kernels, compiled on first use. The error is kept as text so every later call can report it.
+#[cfg(feature = "cuda")]
+static INFERENCE_SAM: LazyLock<Result<CudaSam, String>> =
+    LazyLock::new(|| CudaSam::new(RUNNING_SAM_DEVICE as usize).map_err(|e| format!("{:?}", e)));
+#[cfg(feature = "cuda")]
+static INFERENCE_YOLO: LazyLock<Result<CudaYolo, String>> =
+    LazyLock::new(|| CudaYolo::new(RUNNING_YOLO_DEVICE as usize).map_err(|e| format!("{:?}", e)));
+
+/// The SAM kernels, or why the CUDA context could not be created.
+#[cfg(feature = "cuda")]
+pub(crate) fn inference_sam() -> anyhow::Result<&'static CudaSam> {
+    INFERENCE_SAM
+        .as_ref()
+        .map_err(|e| anyhow::anyhow!("CUDA is not available for SAM: {}", e))
+}
+
+/// The YOLO kernels, or why the CUDA context could not be created.
+#[cfg(feature = "cuda")]
+pub(crate) fn inference_yolo() -> anyhow::Result<&'static CudaYolo> {
+    INFERENCE_YOLO
+        .as_ref()
+        .map_err(|e| anyhow::anyhow!("CUDA is not available for YOLO: {}", e))
+}
 
 pub use spark_ffmpeg::disable_ffmpeg_logging;
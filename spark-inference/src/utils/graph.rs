@@ -14,10 +14,63 @@ pub struct Box<T: Num> {
     pub height: T,
 }
 
-#[derive(Clone)]
-pub enum SamPrompt<T: Num> {
-    Box(Box<T>),
-    Point(Point<T>),
-    Points(Vec<Point<T>>),
-    Both(Point<T>, Box<T>),
+/// What SAM is asked to segment: any mix of points on the object, points to leave out and
+/// a box around it, `x`/`y` of the box being its centre. At least one positive point or
+/// box is needed, negative points alone do not tell the decoder what to look for.
+/// The decoder takes a single box, several objects need a prompt each.
+#[derive(Clone, Debug)]
+pub struct SamPrompt<T: Num> {
+    pub positive: Vec<Point<T>>,
+    pub negative: Vec<Point<T>>,
+    pub boxes: Option<Box<T>>,
+}
+
+impl<T: Num> Default for SamPrompt<T> {
+    fn default() -> Self {
+        Self {
+            positive: Vec::new(),
+            negative: Vec::new(),
+            boxes: None,
+        }
+    }
+}
+
+impl<T: Num> SamPrompt<T> {
+    pub fn from_box(boxes: Box<T>) -> Self {
+        Self::default().with_box(boxes)
+    }
+
+    pub fn from_point(point: Point<T>) -> Self {
+        Self::default().positive(point)
+    }
+
+    pub fn from_points(points: impl IntoIterator<Item = Point<T>>) -> Self {
+        Self {
+            positive: points.into_iter().collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Adds a point on the object.
+    pub fn positive(mut self, point: Point<T>) -> Self {
+        self.positive.push(point);
+        self
+    }
+
+    /// Adds a point the mask should not cover.
+    pub fn negative(mut self, point: Point<T>) -> Self {
+        self.negative.push(point);
+        self
+    }
+
+    /// Sets the box around the object, replacing any earlier one.
+    pub fn with_box(mut self, boxes: Box<T>) -> Self {
+        self.boxes = Some(boxes);
+        self
+    }
+
+    /// Whether the prompt says what to segment, i.e. has a positive point or a box.
+    pub fn has_target(&self) -> bool {
+        !self.positive.is_empty() || self.boxes.is_some()
+    }
 }
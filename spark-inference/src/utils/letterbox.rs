@@ -0,0 +1,206 @@
+use crate::utils::graph::{Box, Point};
+use crate::utils::tensor::linear_interpolate;
+use anyhow::{ensure, Result};
+use ndarray::{s, Array2, ArrayView2};
+use spark_media::filter::filter::AVFilter;
+use spark_media::Image;
+use std::ops::Range;
+
+/// Grey the padding is filled with, the one Ultralytics trains with.
+pub const LETTERBOX_PAD_COLOR: &str = "#727272";
+
+/// How an image was scaled to fit a model input without changing its aspect ratio, and padded
+/// equally on both sides to fill the rest. Whatever the model outputs in input coordinates maps
+/// back to the source image through it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Letterbox {
+    /// `(width, height)` of the source image.
+    pub source_size: (usize, usize),
+    /// `(width, height)` of the model input.
+    pub input_size: (usize, usize),
+    /// `(width, height)` of the image inside the input.
+    pub scaled_size: (usize, usize),
+    /// `(left, top)` padding in input pixels.
+    pub padding: (usize, usize),
+}
+
+impl Letterbox {
+    /// The letterbox FFmpeg produces for an image of `source_size`: `scale` with
+    /// `force_original_aspect_ratio=decrease` rounds each side to the nearest pixel,
+    /// `pad` centres it rounding down.
+    pub fn new(source_size: (usize, usize), input_size: (usize, usize)) -> Self {
+        let (source_width, source_height) = (source_size.0.max(1), source_size.1.max(1));
+        let (input_width, input_height) = input_size;
+        let rounded_div = |numerator: usize, denominator: usize| {
+            (2 * numerator + denominator) / (2 * denominator)
+        };
+        let scaled_size = (
+            rounded_div(input_height * source_width, source_height).clamp(1, input_width),
+            rounded_div(input_width * source_height, source_width).clamp(1, input_height),
+        );
+
+        Self {
+            source_size,
+            input_size,
+            scaled_size,
+            padding: (
+                (input_width - scaled_size.0) / 2,
+                (input_height - scaled_size.1) / 2,
+            ),
+        }
+    }
+
+    /// Letterboxes `image` into packed rgb24 of `input_size`.
+    pub fn apply(image: &mut Image, input_size: (usize, usize)) -> Result<Self> {
+        let (width, height) = input_size;
+        let source_size = image.get_size();
+        let filter = AVFilter::builder(image.pixel_format()?, source_size)?
+            .add_context(
+                "scale",
+                &format!("{}:{}:force_original_aspect_ratio=decrease", width, height),
+            )?
+            .add_context(
+                "pad",
+                &format!(
+                    "{}:{}:(ow-iw)/2:(oh-ih)/2:{}",
+                    width, height, LETTERBOX_PAD_COLOR
+                ),
+            )?
+            .add_context("format", "rgb24")?
+            .build()?;
+        image.apply_filter(&filter)?;
+        ensure!(
+            image.get_size() == (width as i32, height as i32),
+            "Letterboxing {:?} to {}x{} produced {:?}",
+            source_size,
+            width,
+            height,
+            image.get_size()
+        );
+
+        Ok(Self::new(
+            (source_size.0 as usize, source_size.1 as usize),
+            input_size,
+        ))
+    }
+
+    /// Input pixels per source pixel along `x` and `y`.
+    pub fn scale(&self) -> (f32, f32) {
+        (
+            self.scaled_size.0 as f32 / self.source_size.0.max(1) as f32,
+            self.scaled_size.1 as f32 / self.source_size.1.max(1) as f32,
+        )
+    }
+
+    pub fn point_to_source(&self, point: Point<f32>) -> Point<f32> {
+        let (scale_x, scale_y) = self.scale();
+        Point {
+            x: (point.x - self.padding.0 as f32) / scale_x,
+            y: (point.y - self.padding.1 as f32) / scale_y,
+        }
+    }
+
+    pub fn point_to_input(&self, point: Point<f32>) -> Point<f32> {
+        let (scale_x, scale_y) = self.scale();
+        Point {
+            x: point.x * scale_x + self.padding.0 as f32,
+            y: point.y * scale_y + self.padding.1 as f32,
+        }
+    }
+
+    /// Maps a box given by its centre, like YOLO outputs them.
+    pub fn box_to_source(&self, boxes: Box<f32>) -> Box<f32> {
+        let (scale_x, scale_y) = self.scale();
+        let centre = self.point_to_source(Point {
+            x: boxes.x,
+            y: boxes.y,
+        });
+        Box {
+            x: centre.x,
+            y: centre.y,
+            width: boxes.width / scale_x,
+            height: boxes.height / scale_y,
+        }
+    }
+
+    /// Rows and columns of a `mask_size` (`(width, height)`) mask over the whole input that
+    /// show the image, the padding left out. Partly covered cells are kept.
+    pub fn mask_region(&self, mask_size: (usize, usize)) -> (Range<usize>, Range<usize>) {
+        let span = |padding: usize, scaled: usize, input: usize, mask: usize| {
+            let ratio = mask as f32 / input as f32;
+            let start = ((padding as f32 * ratio).floor() as usize).min(mask - 1);
+            let end = (((padding + scaled) as f32 * ratio).ceil() as usize).clamp(start + 1, mask);
+            start..end
+        };
+
+        (
+            span(
+                self.padding.1,
+                self.scaled_size.1,
+                self.input_size.1,
+                mask_size.1,
+            ),
+            span(
+                self.padding.0,
+                self.scaled_size.0,
+                self.input_size.0,
+                mask_size.0,
+            ),
+        )
+    }
+
+    /// Cuts the padding off a mask over the whole input, at any resolution, and stretches
+    /// what is left to `out_size` (`(width, height)`), usually the source size.
+    pub fn mask_to_source(&self, mask: ArrayView2<f32>, out_size: (usize, usize)) -> Array2<f32> {
+        let (height, width) = mask.dim();
+        let (rows, columns) = self.mask_region((width, height));
+        linear_interpolate(
+            mask.slice(s![rows, columns]).to_owned(),
+            (out_size.1, out_size.0),
+        )
+    }
+}
+
+#[test]
+fn test_letterbox_round_trip() {
+    // 1280x720 becomes 640x360 with 140 rows of padding above and below, a quarter of that in prototypes
+    let letterbox = Letterbox::new((1280, 720), (640, 640));
+    assert_eq!(letterbox.scaled_size, (640, 360));
+    assert_eq!(letterbox.padding, (0, 140));
+    assert_eq!(letterbox.mask_region((160, 160)), (35..125, 0..160));
+
+    let point = letterbox.point_to_source(Point { x: 320.0, y: 320.0 });
+    assert_eq!((point.x, point.y), (640.0, 360.0));
+    let back = letterbox.point_to_input(point);
+    assert_eq!((back.x, back.y), (320.0, 320.0));
+    let boxes = letterbox.box_to_source(Box {
+        x: 320.0,
+        y: 140.0,
+        width: 64.0,
+        height: 32.0,
+    });
+    assert_eq!(
+        (boxes.x, boxes.y, boxes.width, boxes.height),
+        (640.0, 0.0, 128.0, 64.0)
+    );
+
+    let letterbox = Letterbox::new((480, 640), (640, 640));
+    assert_eq!(letterbox.padding, (80, 0));
+    assert_eq!(letterbox.mask_region((160, 160)), (0..160, 20..140));
+
+    // Sides are rounded like FFmpeg, and inputs need not be square
+    let letterbox = Letterbox::new((1000, 333), (320, 320));
+    assert_eq!(letterbox.scaled_size, (320, 107));
+    assert_eq!(letterbox.padding, (0, 106));
+    let letterbox = Letterbox::new((1280, 720), (640, 384));
+    assert_eq!(letterbox.scaled_size, (640, 360));
+    assert_eq!(letterbox.padding, (0, 12));
+
+    // Only the image part of the mask is stretched over the output
+    let mut mask = Array2::<f32>::zeros((16, 16));
+    mask.slice_mut(s![4..12, ..]).fill(1.0);
+    let letterbox = Letterbox::new((32, 16), (16, 16));
+    let source = letterbox.mask_to_source(mask.view(), (32, 16));
+    assert_eq!(source.dim(), (16, 32));
+    assert!(source.iter().all(|&value| value == 1.0));
+}
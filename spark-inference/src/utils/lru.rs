@@ -0,0 +1,88 @@
+use anyhow::Result;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+
+/// Thread safe least recently used cache holding at most `capacity` values.
+/// Entries are kept in a list ordered by use, which is meant for a handful of large values
+/// such as image embeddings, not for thousands of small ones.
+pub struct LruCache<K, V> {
+    capacity: usize,
+    /// Most recently used first.
+    entries: Mutex<VecDeque<(K, V)>>,
+}
+
+impl<K: Eq, V: Clone> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Returns the value under `key` and marks it as used.
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut entries = self.entries.lock();
+        let index = entries.iter().position(|(entry, _)| entry == key)?;
+        let entry = entries.remove(index)?;
+        let value = entry.1.clone();
+        entries.push_front(entry);
+
+        Some(value)
+    }
+
+    /// Stores `value` under `key`, dropping the least recently used value when the cache is full.
+    pub fn insert(&self, key: K, value: V) {
+        let mut entries = self.entries.lock();
+        entries.retain(|(entry, _)| *entry != key);
+        entries.truncate(self.capacity - 1);
+        entries.push_front((key, value));
+    }
+
+    /// Returns the value under `key`, creating and storing it first when there is none.
+    /// The cache is not locked while `create` runs, so slow values do not block other keys.
+    pub fn get_or_insert_with(&self, key: K, create: impl FnOnce() -> Result<V>) -> Result<V> {
+        if let Some(value) = self.get(&key) {
+            return Ok(value);
+        }
+
+        let value = create()?;
+        self.insert(key, value.clone());
+        Ok(value)
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let mut entries = self.entries.lock();
+        let index = entries.iter().position(|(entry, _)| entry == key)?;
+        entries.remove(index).map(|(_, value)| value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().is_empty()
+    }
+}
+
+#[test]
+fn test_lru_drops_least_recently_used() {
+    let cache = LruCache::new(2);
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    assert_eq!(cache.get(&"a"), Some(1));
+
+    // "b" was used last longest ago
+    cache.insert("c", 3);
+    assert_eq!(cache.get(&"b"), None);
+    assert_eq!(cache.len(), 2);
+
+    let value = cache
+        .get_or_insert_with("a", || panic!("cached values are not created again"))
+        .unwrap();
+    assert_eq!(value, 1);
+    assert_eq!(cache.get_or_insert_with("d", || Ok(4)).unwrap(), 4);
+    assert_eq!(cache.remove(&"c"), None);
+    assert_eq!(cache.remove(&"a"), Some(1));
+}
@@ -0,0 +1,343 @@
+use crate::utils::graph::Point;
+use bitvec::prelude::*;
+
+/// Connected regions of a mask, 4-connected.
+#[derive(Debug, Clone)]
+pub struct Components {
+    /// Region of every pixel row by row, 0 for unset pixels and `n` for the `n`th region.
+    pub labels: Vec<u32>,
+    /// Pixel count of each region, the one labelled `n` at `n - 1`.
+    pub areas: Vec<usize>,
+}
+
+impl Components {
+    pub fn len(&self) -> usize {
+        self.areas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.areas.is_empty()
+    }
+
+    /// Label of the region with the most pixels.
+    pub fn largest(&self) -> Option<u32> {
+        (1..=self.areas.len() as u32).max_by_key(|&label| self.areas[label as usize - 1])
+    }
+
+    pub fn mask(&self, label: u32) -> BitVec {
+        self.labels.iter().map(|&pixel| pixel == label).collect()
+    }
+}
+
+/// Geometry of row by row masks `width` pixels wide, the height follows from their length.
+/// Structuring elements are squares of `2 * radius + 1` pixels, pixels outside of the mask
+/// are ignored, so regions touching the border are not eroded from there.
+pub trait MaskGeometry {
+    fn components(&self, width: usize) -> Components;
+
+    /// Only the largest region, an empty mask stays empty.
+    fn keep_largest_component(&self, width: usize) -> BitVec;
+
+    fn erode(&self, width: usize, radius: usize) -> BitVec;
+
+    fn dilate(&self, width: usize, radius: usize) -> BitVec;
+
+    /// Erodes then dilates, removing speckles and thin spurs.
+    fn open(&self, width: usize, radius: usize) -> BitVec {
+        self.erode(width, radius).dilate(width, radius)
+    }
+
+    /// Dilates then erodes, closing small gaps and notches.
+    fn close(&self, width: usize, radius: usize) -> BitVec {
+        self.dilate(width, radius).erode(width, radius)
+    }
+
+    /// Sets every unset pixel that is not connected to the border of the mask.
+    fn fill_holes(&self, width: usize) -> BitVec;
+
+    fn area(&self) -> usize;
+
+    /// `[left, top, right, bottom]` of the set pixels, right and bottom exclusive.
+    fn bounding_box(&self, width: usize) -> Option<[usize; 4]>;
+
+    /// Mean position of the set pixels.
+    fn centroid(&self, width: usize) -> Option<Point<f32>>;
+
+    fn intersection(&self, other: &BitSlice) -> BitVec;
+
+    fn union(&self, other: &BitSlice) -> BitVec;
+
+    /// 0 when both masks are empty.
+    fn iou(&self, other: &BitSlice) -> f32;
+
+    /// Scales the mask to `size`, each pixel taking the value of the one it lands on.
+    fn resize_nearest(&self, width: usize, size: (usize, usize)) -> BitVec;
+}
+
+impl MaskGeometry for BitSlice {
+    fn components(&self, width: usize) -> Components {
+        let height = height(self, width);
+        let mut labels = vec![0_u32; self.len()];
+        let mut areas = Vec::new();
+        let mut stack = Vec::new();
+
+        for start in self.iter_ones() {
+            if labels[start] != 0 {
+                continue;
+            }
+
+            let label = areas.len() as u32 + 1;
+            let mut area = 0;
+            labels[start] = label;
+            stack.push(start);
+            while let Some(index) = stack.pop() {
+                area += 1;
+                for neighbour in neighbours(index, width, height) {
+                    if self[neighbour] && labels[neighbour] == 0 {
+                        labels[neighbour] = label;
+                        stack.push(neighbour);
+                    }
+                }
+            }
+            areas.push(area);
+        }
+
+        Components { labels, areas }
+    }
+
+    fn keep_largest_component(&self, width: usize) -> BitVec {
+        let components = self.components(width);
+        match components.largest() {
+            Some(label) => components.mask(label),
+            None => self.to_bitvec(),
+        }
+    }
+
+    fn erode(&self, width: usize, radius: usize) -> BitVec {
+        let horizontal = window_filter(self, width, radius, true, true);
+        window_filter(&horizontal, width, radius, false, true)
+    }
+
+    fn dilate(&self, width: usize, radius: usize) -> BitVec {
+        let horizontal = window_filter(self, width, radius, true, false);
+        window_filter(&horizontal, width, radius, false, false)
+    }
+
+    fn fill_holes(&self, width: usize) -> BitVec {
+        let height = height(self, width);
+        let mut outside = BitVec::repeat(false, self.len());
+        let mut stack = (0..self.len())
+            .filter(|&index| {
+                let (x, y) = (index % width, index / width);
+                (x == 0 || y == 0 || x == width - 1 || y == height - 1) && !self[index]
+            })
+            .collect::<Vec<_>>();
+        for &index in &stack {
+            outside.set(index, true);
+        }
+
+        while let Some(index) = stack.pop() {
+            for neighbour in neighbours(index, width, height) {
+                if !self[neighbour] && !outside[neighbour] {
+                    outside.set(neighbour, true);
+                    stack.push(neighbour);
+                }
+            }
+        }
+
+        !outside
+    }
+
+    fn area(&self) -> usize {
+        self.count_ones()
+    }
+
+    fn bounding_box(&self, width: usize) -> Option<[usize; 4]> {
+        let first = self.first_one()?;
+        let last = self.last_one()?;
+        let (left, right) = self
+            .iter_ones()
+            .map(|index| index % width)
+            .fold((width, 0), |(left, right), x| (left.min(x), right.max(x)));
+
+        Some([left, first / width, right + 1, last / width + 1])
+    }
+
+    fn centroid(&self, width: usize) -> Option<Point<f32>> {
+        let (count, sum_x, sum_y) =
+            self.iter_ones()
+                .fold((0_usize, 0_f64, 0_f64), |(count, sum_x, sum_y), index| {
+                    let (x, y) = (index % width, index / width);
+                    (count + 1, sum_x + x as f64, sum_y + y as f64)
+                });
+        if count == 0 {
+            return None;
+        }
+
+        Some(Point {
+            x: (sum_x / count as f64) as f32,
+            y: (sum_y / count as f64) as f32,
+        })
+    }
+
+    fn intersection(&self, other: &BitSlice) -> BitVec {
+        self.to_bitvec() & other
+    }
+
+    fn union(&self, other: &BitSlice) -> BitVec {
+        self.to_bitvec() | other
+    }
+
+    fn iou(&self, other: &BitSlice) -> f32 {
+        let intersection = self.intersection(other).count_ones();
+        let union = self.count_ones() + other.count_ones() - intersection;
+        if union == 0 {
+            return 0.0;
+        }
+
+        intersection as f32 / union as f32
+    }
+
+    fn resize_nearest(&self, width: usize, size: (usize, usize)) -> BitVec {
+        let height = height(self, width);
+        let (new_width, new_height) = size;
+        (0..new_width * new_height)
+            .map(|index| {
+                let x = index % new_width * width / new_width;
+                let y = index / new_width * height / new_height;
+                self[y * width + x]
+            })
+            .collect()
+    }
+}
+
+fn height(mask: &BitSlice, width: usize) -> usize {
+    debug_assert!(
+        width > 0 && mask.len() % width == 0,
+        "A mask of {} pixels cannot be {} pixels wide",
+        mask.len(),
+        width
+    );
+    mask.len() / width
+}
+
+/// The up to four pixels sharing an edge with `index`.
+fn neighbours(index: usize, width: usize, height: usize) -> impl Iterator<Item = usize> {
+    let (x, y) = (index % width, index / width);
+    [
+        (x > 0).then(|| index - 1),
+        (x + 1 < width).then(|| index + 1),
+        (y > 0).then(|| index - width),
+        (y + 1 < height).then(|| index + width),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+/// One pass of a separable erosion or dilation, along the rows or along the columns.
+fn window_filter(
+    mask: &BitSlice,
+    width: usize,
+    radius: usize,
+    horizontal: bool,
+    erode: bool,
+) -> BitVec {
+    let height = height(mask, width);
+    if radius == 0 {
+        return mask.to_bitvec();
+    }
+
+    let (lines, length) = if horizontal {
+        (height, width)
+    } else {
+        (width, height)
+    };
+    let index = |line: usize, position: usize| {
+        if horizontal {
+            line * width + position
+        } else {
+            position * width + line
+        }
+    };
+
+    let mut filtered = BitVec::repeat(false, mask.len());
+    let mut prefix = vec![0_usize; length + 1];
+    for line in 0..lines {
+        for position in 0..length {
+            prefix[position + 1] = prefix[position] + mask[index(line, position)] as usize;
+        }
+        for position in 0..length {
+            let start = position.saturating_sub(radius);
+            let end = (position + radius + 1).min(length);
+            let count = prefix[end] - prefix[start];
+            let set = if erode {
+                count == end - start
+            } else {
+                count > 0
+            };
+            if set {
+                filtered.set(index(line, position), true);
+            }
+        }
+    }
+
+    filtered
+}
+
+#[test]
+fn test_components_and_holes() {
+    // # # # . .
+    // # . # . #
+    // # # # . .
+    let mask = bitvec![
+        1, 1, 1, 0, 0, //
+        1, 0, 1, 0, 1, //
+        1, 1, 1, 0, 0,
+    ];
+    let components = mask.components(5);
+    assert_eq!(components.areas, vec![8, 1]);
+    assert_eq!(components.largest(), Some(1));
+    assert_eq!(components.labels[9], 2);
+
+    let largest = mask.keep_largest_component(5);
+    assert_eq!(largest.area(), 8);
+    assert_eq!(largest.bounding_box(5), Some([0, 0, 3, 3]));
+    let filled = largest.fill_holes(5);
+    assert_eq!(filled.area(), 9);
+    let centroid = filled.centroid(5).unwrap();
+    assert_eq!((centroid.x, centroid.y), (1.0, 1.0));
+
+    assert_eq!(mask.iou(&filled), 8.0 / 10.0);
+    assert_eq!(mask.union(&filled).area(), 10);
+    assert_eq!(mask.intersection(&filled).area(), 8);
+    assert_eq!(BitVec::<usize>::repeat(false, 4).bounding_box(2), None);
+}
+
+#[test]
+fn test_morphology_and_resize() {
+    // A 4x4 square in an 8x8 mask with a speckle in the corner and a notch in its top edge
+    let mut mask = BitVec::repeat(false, 64);
+    for y in 2..6 {
+        for x in 2..6 {
+            mask.set(y * 8 + x, true);
+        }
+    }
+    mask.set(2 * 8 + 3, false);
+    mask.set(7 * 8 + 7, true);
+
+    let opened = mask.open(8, 1);
+    assert!(!opened[7 * 8 + 7]);
+    let closed = mask.close(8, 1);
+    assert!(closed[2 * 8 + 3]);
+    assert_eq!(mask.dilate(8, 1).bounding_box(8), Some([1, 1, 8, 8]));
+    assert_eq!(mask.erode(8, 1).area(), 2);
+    assert_eq!(mask.erode(8, 0), mask);
+
+    // Pixels along the border are not eroded from outside of the mask
+    let full = BitVec::<usize>::repeat(true, 16);
+    assert_eq!(full.erode(4, 1), full);
+
+    let resized = mask.resize_nearest(8, (4, 4));
+    assert_eq!(resized.bounding_box(4), Some([1, 1, 3, 3]));
+    assert_eq!(resized.resize_nearest(4, (8, 8)).area(), 4 * resized.area());
+}
@@ -5,6 +5,11 @@ use spark_ffmpeg::pixel::pixel_formater::RGB;
 use spark_ffmpeg::util::ptr_wrapper::SafePtr;
 use spark_media::Image;
 
+pub mod geometry;
+pub mod png;
+pub mod polygon;
+pub mod rle;
+
 pub trait ApplyMask {
     fn layering_mask(&mut self, mask: &BitVec, apply_color: RGB) -> Result<()>;
 }
@@ -0,0 +1,113 @@
+use anyhow::{ensure, Result};
+use bitvec::prelude::*;
+use spark_media::filter::filter::AVFilter;
+use spark_media::{AVCodecID, AVPixelFormat, Image};
+use std::path::Path;
+
+/// Pixel format of a mask image.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum MaskDepth {
+    /// One bit per pixel, the smallest files.
+    #[default]
+    Bit,
+    /// Set pixels are white and unset ones black, readable by any image tool.
+    Gray,
+}
+
+pub trait MaskImage: Sized {
+    /// An image of a row by row mask, ready to be saved as PNG.
+    fn from_mask(mask: &BitSlice, size: (i32, i32), depth: MaskDepth) -> Result<Self>;
+
+    /// The row by row mask of the pixels that are brighter than mid gray.
+    fn into_mask(self) -> Result<BitVec>;
+}
+
+impl MaskImage for Image {
+    fn from_mask(mask: &BitSlice, size: (i32, i32), depth: MaskDepth) -> Result<Self> {
+        let (width, height) = (size.0 as usize, size.1 as usize);
+        ensure!(
+            mask.len() == width * height,
+            "Mask has {} pixels, expected {}x{}",
+            mask.len(),
+            width,
+            height
+        );
+
+        let (pixel_format, data) = match depth {
+            // Rows of packed bits, the first pixel in the most significant one
+            MaskDepth::Bit => {
+                let row_size = width.div_ceil(8);
+                let mut data = vec![0_u8; row_size * height];
+                for index in mask.iter_ones() {
+                    let (x, y) = (index % width, index / width);
+                    data[y * row_size + x / 8] |= 0x80 >> (x % 8);
+                }
+                (AVPixelFormat::Monoblack, data)
+            }
+            MaskDepth::Gray => {
+                let data = mask.iter().map(|set| if *set { 255 } else { 0 }).collect();
+                (AVPixelFormat::Gray8, data)
+            }
+        };
+
+        let mut image = Image::new_with_empty(size, pixel_format, AVCodecID::Png)?;
+        image.fill_data(&data)?;
+        Ok(image)
+    }
+
+    fn into_mask(mut self) -> Result<BitVec> {
+        let (width, height) = (self.get_width() as usize, self.get_height() as usize);
+        if self.pixel_format()? != AVPixelFormat::Gray8 {
+            let filter = AVFilter::builder(self.pixel_format()?, self.get_size())?
+                .add_context("format", "gray")?
+                .build()?;
+            self.apply_filter(&filter)?;
+        }
+
+        // Rows may be padded
+        let data = self.raw_data()?;
+        let line_size = data.len() / height.max(1);
+        Ok((0..width * height)
+            .map(|index| data[(index / width) * line_size + index % width] > 127)
+            .collect())
+    }
+}
+
+/// Saves a row by row mask of `size` as PNG.
+pub fn save_mask_png(
+    mask: &BitSlice,
+    size: (i32, i32),
+    depth: MaskDepth,
+    path: impl AsRef<Path>,
+) -> Result<()> {
+    Image::from_mask(mask, size, depth)?.save_with_format(path)
+}
+
+/// Loads a mask saved by [`save_mask_png`], or any image whose bright pixels are the mask,
+/// with its size.
+pub fn load_mask_png(path: impl AsRef<Path>) -> Result<(BitVec, (i32, i32))> {
+    let image = Image::open_file(path)?;
+    let size = image.get_size();
+    Ok((image.into_mask()?, size))
+}
+
+#[test]
+fn test_mask_png_round_trip() {
+    // A width that is neither a multiple of 8 nor of the line alignment, so rows are padded
+    let size = (13, 5);
+    let mask = (0..13 * 5)
+        .map(|index| (index % 13 + index / 13) % 3 == 0 || index % 13 == 12)
+        .collect::<BitVec>();
+
+    for depth in [MaskDepth::Bit, MaskDepth::Gray] {
+        let path =
+            std::env::temp_dir().join(format!("spark-mask-{}-{:?}.png", std::process::id(), depth));
+        save_mask_png(&mask, size, depth, &path).unwrap();
+        let loaded = load_mask_png(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap(), (mask.clone(), size), "{:?}", depth);
+    }
+
+    assert!(save_mask_png(&mask, (12, 5), MaskDepth::Bit, "unused.png").is_err());
+}
@@ -0,0 +1,232 @@
+use crate::utils::graph::Point;
+use anyhow::{ensure, Result};
+use bitvec::prelude::*;
+use std::collections::HashMap;
+
+/// Outline of one region along the edges of its pixels, corners in clockwise order
+/// (`y` pointing down) and the first corner not repeated at the end.
+pub type Polygon = Vec<Point<i32>>;
+
+/// Traces the outline of every 4-connected region of a row by row mask and drops the corners
+/// that are less than `tolerance` pixels away from the simplified outline, 0 keeps every corner.
+/// Holes are not outlined, the polygons cover them.
+pub fn mask_to_polygons(
+    mask: &BitSlice,
+    width: usize,
+    height: usize,
+    tolerance: f32,
+) -> Result<Vec<Polygon>> {
+    ensure!(
+        mask.len() == width * height,
+        "Mask has {} pixels, expected {}x{}",
+        mask.len(),
+        width,
+        height
+    );
+
+    let is_set = |x: i32, y: i32| {
+        x >= 0
+            && y >= 0
+            && (x as usize) < width
+            && (y as usize) < height
+            && mask[y as usize * width + x as usize]
+    };
+
+    // Every pixel edge between a set and an unset pixel, directed to keep the set pixel on its right
+    let mut edges: HashMap<(i32, i32), Vec<(i32, i32)>> = HashMap::new();
+    for index in mask.iter_ones() {
+        let (x, y) = ((index % width) as i32, (index / width) as i32);
+        let sides = [
+            ((x, y - 1), (x, y), (x + 1, y)),
+            ((x + 1, y), (x + 1, y), (x + 1, y + 1)),
+            ((x, y + 1), (x + 1, y + 1), (x, y + 1)),
+            ((x - 1, y), (x, y + 1), (x, y)),
+        ];
+        for ((neighbour_x, neighbour_y), from, to) in sides {
+            if !is_set(neighbour_x, neighbour_y) {
+                edges.entry(from).or_default().push(to);
+            }
+        }
+    }
+
+    let mut polygons = Vec::new();
+    let mut starts = edges.keys().copied().collect::<Vec<_>>();
+    starts.sort_unstable_by_key(|&(x, y)| (y, x));
+    for start in starts {
+        while let Some(outline) = trace_outline(&mut edges, start) {
+            // Holes run counter clockwise
+            if signed_area(&outline) <= 0 {
+                continue;
+            }
+
+            let outline = simplify(&outline, tolerance);
+            polygons.push(outline.into_iter().map(|(x, y)| Point { x, y }).collect());
+        }
+    }
+
+    Ok(polygons)
+}
+
+/// Follows unused edges from `start` until it is reached again, removing them on the way.
+/// Where two outlines touch at a corner it turns right, so they stay apart.
+fn trace_outline(
+    edges: &mut HashMap<(i32, i32), Vec<(i32, i32)>>,
+    start: (i32, i32),
+) -> Option<Vec<(i32, i32)>> {
+    let mut outline = vec![start];
+    let mut current = start;
+    let mut direction: Option<(i32, i32)> = None;
+    loop {
+        let next = edges.get_mut(&current)?;
+        let index = match direction {
+            Some((dx, dy)) if next.len() > 1 => next
+                .iter()
+                .position(|&(x, y)| (x - current.0, y - current.1) == (-dy, dx))
+                .unwrap_or(0),
+            _ => 0,
+        };
+        let next_corner = next.swap_remove(index);
+        if next.is_empty() {
+            edges.remove(&current);
+        }
+
+        direction = Some((next_corner.0 - current.0, next_corner.1 - current.1));
+        current = next_corner;
+        if current == start {
+            break;
+        }
+        outline.push(current);
+    }
+
+    Some(remove_collinear(outline))
+}
+
+fn remove_collinear(outline: Vec<(i32, i32)>) -> Vec<(i32, i32)> {
+    let count = outline.len();
+    (0..count)
+        .filter(|&index| {
+            let (px, py) = outline[(index + count - 1) % count];
+            let (x, y) = outline[index];
+            let (nx, ny) = outline[(index + 1) % count];
+            (x - px) * (ny - y) != (y - py) * (nx - x)
+        })
+        .map(|index| outline[index])
+        .collect()
+}
+
+/// Twice the area enclosed by the outline, positive when it runs clockwise.
+fn signed_area(outline: &[(i32, i32)]) -> i64 {
+    outline
+        .iter()
+        .zip(outline.iter().cycle().skip(1))
+        .map(|(&(x1, y1), &(x2, y2))| x1 as i64 * y2 as i64 - x2 as i64 * y1 as i64)
+        .sum()
+}
+
+/// Ramer–Douglas–Peucker on a closed outline, split at its first corner and the corner
+/// farthest from it.
+fn simplify(outline: &[(i32, i32)], tolerance: f32) -> Vec<(i32, i32)> {
+    if tolerance <= 0.0 || outline.len() <= 3 {
+        return outline.to_vec();
+    }
+
+    let distance = |a: (i32, i32), b: (i32, i32)| ((a.0 - b.0) as f32).hypot((a.1 - b.1) as f32);
+    let farthest = (1..outline.len())
+        .max_by(|&a, &b| {
+            distance(outline[0], outline[a]).total_cmp(&distance(outline[0], outline[b]))
+        })
+        .unwrap_or(1);
+
+    let mut closed = outline.to_vec();
+    closed.push(outline[0]);
+    let mut kept = vec![false; closed.len()];
+    kept[0] = true;
+    kept[farthest] = true;
+    simplify_section(&closed, 0, farthest, tolerance, &mut kept);
+    simplify_section(&closed, farthest, closed.len() - 1, tolerance, &mut kept);
+
+    outline
+        .iter()
+        .zip(kept)
+        .filter(|(_, kept)| *kept)
+        .map(|(&corner, _)| corner)
+        .collect()
+}
+
+fn simplify_section(
+    corners: &[(i32, i32)],
+    first: usize,
+    last: usize,
+    tolerance: f32,
+    kept: &mut [bool],
+) {
+    if last <= first + 1 {
+        return;
+    }
+
+    let (ax, ay) = (corners[first].0 as f32, corners[first].1 as f32);
+    let (bx, by) = (corners[last].0 as f32, corners[last].1 as f32);
+    let length = (bx - ax).hypot(by - ay);
+    let distance = |&(x, y): &(i32, i32)| {
+        let (x, y) = (x as f32, y as f32);
+        if length == 0.0 {
+            (x - ax).hypot(y - ay)
+        } else {
+            ((bx - ax) * (ay - y) - (ax - x) * (by - ay)).abs() / length
+        }
+    };
+
+    let (index, farthest) = (first + 1..last)
+        .map(|index| (index, distance(&corners[index])))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .unwrap();
+    if farthest > tolerance {
+        kept[index] = true;
+        simplify_section(corners, first, index, tolerance, kept);
+        simplify_section(corners, index, last, tolerance, kept);
+    }
+}
+
+#[test]
+fn test_mask_to_polygons() {
+    // Two rectangles touching at a corner, the left one with a hole
+    // # # # . .
+    // # . # . .
+    // # # # . .
+    // . . . # #
+    let mask = bitvec![
+        1, 1, 1, 0, 0, //
+        1, 0, 1, 0, 0, //
+        1, 1, 1, 0, 0, //
+        0, 0, 0, 1, 1,
+    ];
+    let polygons = mask_to_polygons(&mask, 5, 4, 0.0).unwrap();
+    let corners = polygons
+        .iter()
+        .map(|polygon| {
+            polygon
+                .iter()
+                .map(|point| (point.x, point.y))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        corners,
+        vec![
+            vec![(0, 0), (3, 0), (3, 3), (0, 3)],
+            vec![(3, 3), (5, 3), (5, 4), (3, 4)],
+        ]
+    );
+
+    // A staircase flattens into a triangle once single pixel steps are tolerated
+    let mut staircase = BitVec::repeat(false, 8 * 8);
+    for y in 0..8 {
+        for x in 0..=y {
+            staircase.set(y * 8 + x, true);
+        }
+    }
+    let polygons = mask_to_polygons(&staircase, 8, 8, 1.0).unwrap();
+    assert_eq!(polygons.len(), 1);
+    assert!(polygons[0].len() <= 4);
+    assert!(mask_to_polygons(&staircase, 8, 8, 0.0).unwrap()[0].len() > 10);
+}
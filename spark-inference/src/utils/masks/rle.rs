@@ -0,0 +1,206 @@
+use anyhow::{anyhow, ensure, Result};
+use bitvec::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A mask in COCO's run-length encoding. Unlike the row by row masks everywhere else, COCO
+/// walks the mask column by column, and the runs alternate between unset and set pixels,
+/// starting with unset ones.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaskRle {
+    /// `[height, width]`, in COCO's order.
+    pub size: [usize; 2],
+    pub counts: RleCounts,
+}
+
+/// Serialises like COCO does, a list of runs or the compressed string of pycocotools.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RleCounts {
+    Runs(Vec<u32>),
+    Compressed(String),
+}
+
+impl MaskRle {
+    /// Encodes a row by row mask of `width` x `height` pixels.
+    pub fn encode(mask: &BitSlice, width: usize, height: usize) -> Result<Self> {
+        ensure!(
+            mask.len() == width * height,
+            "Mask has {} pixels, expected {}x{}",
+            mask.len(),
+            width,
+            height
+        );
+
+        let mut runs = Vec::new();
+        let (mut current, mut length) = (false, 0_u32);
+        for x in 0..width {
+            for y in 0..height {
+                if mask[y * width + x] != current {
+                    runs.push(length);
+                    current = !current;
+                    length = 0;
+                }
+                length += 1;
+            }
+        }
+        runs.push(length);
+
+        Ok(Self {
+            size: [height, width],
+            counts: RleCounts::Runs(runs),
+        })
+    }
+
+    /// The row by row mask.
+    pub fn decode(&self) -> Result<BitVec> {
+        let [height, width] = self.size;
+        let mut mask = BitVec::repeat(false, width * height);
+        let mut index = 0;
+        for (run, length) in self.runs()?.into_iter().enumerate() {
+            let end = index + length as usize;
+            ensure!(
+                end <= width * height,
+                "RLE runs cover more than {}x{} pixels",
+                width,
+                height
+            );
+            if run % 2 == 1 {
+                for index in index..end {
+                    mask.set((index % height) * width + index / height, true);
+                }
+            }
+            index = end;
+        }
+        ensure!(
+            index == width * height,
+            "RLE runs cover {} of {}x{} pixels",
+            index,
+            width,
+            height
+        );
+
+        Ok(mask)
+    }
+
+    /// The same mask with the runs packed into the string form of pycocotools.
+    pub fn compress(self) -> Result<Self> {
+        let runs = self.runs()?;
+        Ok(Self {
+            size: self.size,
+            counts: RleCounts::Compressed(compress_runs(&runs)),
+        })
+    }
+
+    pub fn runs(&self) -> Result<Vec<u32>> {
+        match &self.counts {
+            RleCounts::Runs(runs) => Ok(runs.clone()),
+            RleCounts::Compressed(counts) => decompress_runs(counts),
+        }
+    }
+
+    /// Number of set pixels.
+    pub fn area(&self) -> Result<usize> {
+        let runs = self.runs()?;
+        Ok(runs
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .map(|&run| run as usize)
+            .sum())
+    }
+}
+
+/// Each run from the fourth on is stored as the difference to the run two before it,
+/// in little endian groups of five bits with a continuation bit, offset into printable ASCII.
+fn compress_runs(runs: &[u32]) -> String {
+    let mut counts = String::new();
+    for (index, &run) in runs.iter().enumerate() {
+        let mut value = run as i64;
+        if index > 2 {
+            value -= runs[index - 2] as i64;
+        }
+
+        loop {
+            let mut char = (value & 0x1f) as u8;
+            value >>= 5;
+            let more = if char & 0x10 != 0 {
+                value != -1
+            } else {
+                value != 0
+            };
+            if more {
+                char |= 0x20;
+            }
+            counts.push((char + 48) as char);
+            if !more {
+                break;
+            }
+        }
+    }
+
+    counts
+}
+
+fn decompress_runs(counts: &str) -> Result<Vec<u32>> {
+    let mut runs: Vec<u32> = Vec::new();
+    let mut bytes = counts.bytes().peekable();
+    while bytes.peek().is_some() {
+        let (mut value, mut shift) = (0_i64, 0);
+        loop {
+            let char = bytes
+                .next()
+                .ok_or(anyhow!("RLE string ends inside a run"))?
+                .checked_sub(48)
+                .filter(|&char| char < 64)
+                .ok_or(anyhow!("RLE string has characters outside of its alphabet"))?;
+            ensure!(shift < 64, "RLE run does not fit into 64 bits");
+            value |= ((char & 0x1f) as i64) << shift;
+            shift += 5;
+            if char & 0x20 == 0 {
+                if char & 0x10 != 0 && shift < 64 {
+                    value |= -1_i64 << shift;
+                }
+                break;
+            }
+        }
+
+        if runs.len() > 2 {
+            value += runs[runs.len() - 2] as i64;
+        }
+        let run = u32::try_from(value).map_err(|_| anyhow!("RLE run {} out of range", value))?;
+        runs.push(run);
+    }
+
+    Ok(runs)
+}
+
+#[test]
+fn test_rle_round_trip() {
+    // . # #
+    // . . #
+    let mask = bitvec![0, 1, 1, 0, 0, 1];
+    let rle = MaskRle::encode(&mask, 3, 2).unwrap();
+    assert_eq!(rle.size, [2, 3]);
+    assert_eq!(rle.counts, RleCounts::Runs(vec![2, 1, 1, 2]));
+    assert_eq!(rle.area().unwrap(), 3);
+
+    let compressed = rle.clone().compress().unwrap();
+    assert_eq!(compressed.counts, RleCounts::Compressed("2111".to_string()));
+    assert_eq!(compressed.runs().unwrap(), vec![2, 1, 1, 2]);
+    assert_eq!(compressed.decode().unwrap(), mask);
+
+    // Long runs need several characters, shrinking ones negative differences
+    let runs = vec![0, 5000, 3, 40, 1, 1_000_000];
+    assert_eq!(decompress_runs(&compress_runs(&runs)).unwrap(), runs);
+
+    let json = serde_json::to_string(&compressed).unwrap();
+    assert_eq!(json, r#"{"size":[2,3],"counts":"2111"}"#);
+    let parsed: MaskRle = serde_json::from_str(r#"{"size":[2,3],"counts":[2,1,1,2]}"#).unwrap();
+    assert_eq!(parsed, rle);
+    assert!(MaskRle {
+        size: [2, 3],
+        counts: RleCounts::Runs(vec![2, 1])
+    }
+    .decode()
+    .is_err());
+}
@@ -1,3 +1,5 @@
 pub mod graph;
+pub mod letterbox;
+pub mod lru;
 pub mod masks;
 pub(crate) mod tensor;
@@ -1,4 +1,6 @@
-use ndarray::Array2;
+use anyhow::{ensure, Result};
+use ndarray::{Array2, Array4};
+use rayon::prelude::*;
 
 pub fn linear_interpolate(input: Array2<f32>, new_shape: (usize, usize)) -> Array2<f32> {
     let (old_height, old_width) = input.dim();
@@ -41,3 +43,92 @@ pub fn linear_interpolate(input: Array2<f32>, new_shape: (usize, usize)) -> Arra
 pub fn sigmoid(arr: Array2<f32>) -> Array2<f32> {
     arr.mapv(|x| 1.0 / (1.0 + (-x).exp()))
 }
+
+/// Turns packed rgb24 pixels into a `1x3xHxW` tensor scaled to `[0, 1]`,
+/// the host side twin of the `normalise_pixel_div` kernel.
+pub fn normalise_pixel_div(rgb: &[u8], width: usize, height: usize) -> Result<Array4<f32>> {
+    normalise_pixel_mean(rgb, width, height, [0.0; 3], [1.0; 3])
+}
+
+/// Same as [`normalise_pixel_div`], then `(value - mean) / std` per channel,
+/// the host side twin of the `normalise_pixel_mean` kernel.
+pub fn normalise_pixel_mean(
+    rgb: &[u8],
+    width: usize,
+    height: usize,
+    mean: [f32; 3],
+    std: [f32; 3],
+) -> Result<Array4<f32>> {
+    ensure!(
+        rgb.len() == width * height * 3,
+        "Expected {} bytes of rgb24 for {}x{}, got {}",
+        width * height * 3,
+        width,
+        height,
+        rgb.len()
+    );
+
+    normalise_pixel_batch(rgb, width, height, mean, std)
+}
+
+/// [`normalise_pixel_mean`] of frames stored back to back, stacked into an `Nx3xHxW` tensor.
+pub fn normalise_pixel_batch(
+    rgb: &[u8],
+    width: usize,
+    height: usize,
+    mean: [f32; 3],
+    std: [f32; 3],
+) -> Result<Array4<f32>> {
+    let plane = width * height;
+    ensure!(
+        plane > 0 && !rgb.is_empty() && rgb.len().is_multiple_of(plane * 3),
+        "Expected a multiple of {} bytes of rgb24 for {}x{} frames, got {}",
+        plane * 3,
+        width,
+        height,
+        rgb.len()
+    );
+    let batch = rgb.len() / (plane * 3);
+
+    let mut tensor = vec![0_f32; rgb.len()];
+    tensor
+        .par_chunks_mut(plane)
+        .enumerate()
+        .for_each(|(index, out)| {
+            let (frame, channel) = (index / 3, index % 3);
+            let rgb = &rgb[frame * plane * 3..(frame + 1) * plane * 3];
+            for (pixel, value) in out.iter_mut().enumerate() {
+                *value = (rgb[pixel * 3 + channel] as f32 / 255.0 - mean[channel]) / std[channel];
+            }
+        });
+
+    Ok(Array4::from_shape_vec((batch, 3, height, width), tensor)?)
+}
+
+#[test]
+fn test_normalise_pixels_to_nchw() {
+    let rgb = [255, 0, 51, 0, 255, 102];
+    let tensor = normalise_pixel_div(&rgb, 2, 1).unwrap();
+    assert_eq!(tensor.shape(), &[1, 3, 1, 2]);
+    assert_eq!(tensor[[0, 0, 0, 0]], 1.0);
+    assert_eq!(tensor[[0, 1, 0, 1]], 1.0);
+    assert_eq!(tensor[[0, 2, 0, 0]], 0.2);
+
+    let tensor = normalise_pixel_mean(&rgb, 2, 1, [0.5; 3], [0.5; 3]).unwrap();
+    assert_eq!(tensor[[0, 0, 0, 0]], 1.0);
+    assert_eq!(tensor[[0, 0, 0, 1]], -1.0);
+
+    assert!(normalise_pixel_div(&rgb, 2, 2).is_err());
+
+    // Frames back to back become the batch, in order
+    let frames = [rgb.as_slice(), &[0; 6]].concat();
+    let tensor = normalise_pixel_batch(&frames, 2, 1, [0.0; 3], [1.0; 3]).unwrap();
+    assert_eq!(tensor.shape(), &[2, 3, 1, 2]);
+    assert_eq!(tensor[[0, 2, 0, 0]], 0.2);
+    assert!(tensor
+        .slice(ndarray::s![1, .., .., ..])
+        .iter()
+        .all(|&value| value == 0.0));
+    assert!(normalise_pixel_mean(&frames, 2, 1, [0.0; 3], [1.0; 3]).is_err());
+    assert!(normalise_pixel_batch(&frames[1..], 2, 1, [0.0; 3], [1.0; 3]).is_err());
+}
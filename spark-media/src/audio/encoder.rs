@@ -0,0 +1,220 @@
+use crate::audio::audio::Audio;
+use crate::audio::format::AudioFormat;
+use anyhow::{bail, Result};
+use spark_ffmpeg::av_io_context::dynamic_buffer::AVDynamicBuffer;
+use spark_ffmpeg::avcodec::AVCodecContext;
+use spark_ffmpeg::avformat::AVFormatContext;
+use spark_ffmpeg::avframe::AVFrame;
+use spark_ffmpeg::ffi_enum::AVSampleFormat;
+use spark_ffmpeg::swr::SwrContext;
+
+/// Samples per frame for encoders without a fixed frame size (PCM).
+const PCM_FRAME_SIZE: usize = 4096;
+
+impl Audio {
+    /// Encodes the audio into `format`, resampled to `sample_rate` (or the closest rate
+    /// the codec supports). Without a sample rate the original one is kept where possible.
+    pub fn encode(&self, format: AudioFormat, sample_rate: Option<u32>) -> Result<Vec<u8>> {
+        if self.samples.is_empty() {
+            bail!("No audio samples to encode");
+        }
+
+        let dst_rate = format.supported_sample_rate(sample_rate.unwrap_or(self.sample_rate));
+        let dst_format = format.sample_format();
+        let samples = self.convert(format, dst_rate)?;
+
+        let buffer = AVDynamicBuffer::new()?;
+        let mut muxer = AVFormatContext::alloc_output(format.muxer())?;
+        let encoder = AVCodecContext::new_audio(
+            &format.encoder()?,
+            dst_format,
+            dst_rate as i32,
+            1,
+            format.bit_rate(),
+            muxer.needs_global_header(),
+            None,
+        )?;
+        let stream_index = muxer.add_stream(&encoder)?;
+        muxer.set_output(&buffer);
+        muxer.write_header()?;
+
+        // Codecs with a fixed frame size get their last frame padded with silence
+        let fixed_frame_size = encoder.frame_size() > 0;
+        let frame_size = if fixed_frame_size {
+            encoder.frame_size() as usize
+        } else {
+            PCM_FRAME_SIZE
+        };
+        let bytes_per_sample = format.bytes_per_sample();
+
+        let mut pts = 0;
+        for chunk in samples.chunks(frame_size * bytes_per_sample) {
+            let nb_samples = if fixed_frame_size {
+                frame_size
+            } else {
+                chunk.len() / bytes_per_sample
+            };
+
+            let mut frame = AVFrame::new()?;
+            frame.alloc_samples(dst_format, dst_rate as i32, 1, nb_samples as i32)?;
+            let data = frame.audio_data_mut();
+            data.fill(0);
+            data[..chunk.len()].copy_from_slice(chunk);
+            frame.set_pts(pts);
+            pts += nb_samples as i64;
+
+            encoder.send_frame(&frame)?;
+            Self::write_packets(&encoder, &mut muxer, stream_index)?;
+        }
+
+        encoder.send_eof()?;
+        Self::write_packets(&encoder, &mut muxer, stream_index)?;
+        muxer.write_trailer()?;
+        drop(muxer);
+
+        Ok(buffer.into_bytes())
+    }
+
+    /// Converts the float samples into the raw bytes of the encoder input of `format` at `sample_rate`.
+    fn convert(&self, format: AudioFormat, sample_rate: u32) -> Result<Vec<u8>> {
+        let (src_rate, dst_rate) = (self.sample_rate as i32, sample_rate as i32);
+        let format = format.sample_format();
+
+        let mut input = AVFrame::new()?;
+        input.alloc_samples(AVSampleFormat::Flt, src_rate, 1, self.samples.len() as i32)?;
+        for (target, sample) in input
+            .audio_data_mut()
+            .chunks_exact_mut(4)
+            .zip(self.samples.iter())
+        {
+            target.copy_from_slice(&sample.to_ne_bytes());
+        }
+
+        let swr = SwrContext::new(AVSampleFormat::Flt, src_rate, format, dst_rate, 1)?;
+        let mut bytes = Vec::new();
+
+        let mut output = AVFrame::new()?;
+        output.set_audio_format(format, dst_rate, 1);
+        swr.convert_frame(&mut output, Some(&input))?;
+        bytes.extend_from_slice(output.audio_data());
+
+        // Resampling keeps a few samples back, flush them out
+        if src_rate != dst_rate {
+            let mut tail = AVFrame::new()?;
+            tail.set_audio_format(format, dst_rate, 1);
+            swr.convert_frame(&mut tail, None)?;
+            bytes.extend_from_slice(tail.audio_data());
+        }
+
+        Ok(bytes)
+    }
+
+    fn write_packets(
+        encoder: &AVCodecContext,
+        muxer: &mut AVFormatContext,
+        stream_index: i32,
+    ) -> Result<()> {
+        while let Some(mut packet) = encoder.try_receive_packet()? {
+            muxer.write_packet(&mut packet, stream_index, encoder)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// One second of a 440 Hz tone.
+#[cfg(test)]
+fn tone(sample_rate: u32) -> Audio {
+    let samples = (0..sample_rate)
+        .map(|i| (i as f32 / sample_rate as f32 * 440.0 * std::f32::consts::TAU).sin() * 0.5)
+        .collect();
+
+    Audio::new(samples, sample_rate)
+}
+
+/// Encodes one second of tone from 24 kHz, checks the container's magic bytes at `offset` and
+/// that FFmpeg reads back a second of audio. Returns the sample rate of the stream read back.
+#[cfg(test)]
+fn assert_round_trip(
+    format: AudioFormat,
+    offset: usize,
+    magic: &[u8],
+    sample_rate: Option<u32>,
+) -> i32 {
+    use spark_ffmpeg::avformat::avformat_context::OpenFileToAVFormatContext;
+    use spark_ffmpeg::avformat::AVMediaType;
+
+    let bytes = tone(24000).encode(format, sample_rate).unwrap();
+    assert_eq!(&bytes[offset..offset + magic.len()], magic, "{}", format);
+
+    let path = std::env::temp_dir().join(format!(
+        "spark-media-{}-{}-{}.{}",
+        std::process::id(),
+        format,
+        sample_rate.unwrap_or_default(),
+        format.extension()
+    ));
+    std::fs::write(&path, &bytes).unwrap();
+    let mut context = AVFormatContext::open_file(&path, None).unwrap();
+    let rate = context
+        .audio_stream()
+        .unwrap()
+        .map(|(_, stream)| stream.sample_rate())
+        .next()
+        .unwrap();
+    let duration = context.duration_secs().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    // Lossy codecs pad the start and the last frame
+    assert!((duration - 1.0).abs() < 0.1, "{}: {}s", format, duration);
+    rate
+}
+
+#[test]
+fn test_encode_wav() {
+    assert_eq!(
+        assert_round_trip(AudioFormat::WavFloat, 8, b"WAVE", None),
+        24000
+    );
+    assert_eq!(
+        assert_round_trip(AudioFormat::WavPcm16, 8, b"WAVE", None),
+        24000
+    );
+    assert_eq!(
+        assert_round_trip(AudioFormat::WavPcm16, 8, b"WAVE", Some(16000)),
+        16000
+    );
+}
+
+#[test]
+fn test_encode_opus() {
+    // Ogg Opus always reports 48 kHz, the rate it is decoded at
+    assert_round_trip(AudioFormat::Opus, 0, b"OggS", None);
+    assert_round_trip(AudioFormat::Opus, 0, b"OggS", Some(16000));
+}
+
+#[test]
+fn test_encode_m4a() {
+    assert_eq!(assert_round_trip(AudioFormat::Aac, 4, b"ftyp", None), 24000);
+    assert_eq!(
+        assert_round_trip(AudioFormat::Aac, 4, b"ftyp", Some(44100)),
+        44100
+    );
+}
+
+#[test]
+fn test_encode_mp3() {
+    assert_eq!(assert_round_trip(AudioFormat::Mp3, 0, b"ID3", None), 24000);
+    // 20 kHz is not an MP3 rate, the next one up is taken
+    assert_eq!(
+        assert_round_trip(AudioFormat::Mp3, 0, b"ID3", Some(20000)),
+        22050
+    );
+}
+
+#[test]
+fn test_encode_empty_audio_fails() {
+    assert!(Audio::new(Vec::new(), 24000)
+        .encode(AudioFormat::WavPcm16, None)
+        .is_err());
+}
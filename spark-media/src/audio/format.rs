@@ -0,0 +1,144 @@
+use anyhow::{anyhow, bail, Result};
+use spark_ffmpeg::avcodec::AVCodec;
+use spark_ffmpeg::ffi_enum::AVSampleFormat;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+const OPUS_SAMPLE_RATES: [u32; 5] = [8000, 12000, 16000, 24000, 48000];
+const MP3_SAMPLE_RATES: [u32; 9] = [8000, 11025, 12000, 16000, 22050, 24000, 32000, 44100, 48000];
+const AAC_SAMPLE_RATES: [u32; 12] = [
+    8000, 11025, 12000, 16000, 22050, 24000, 32000, 44100, 48000, 64000, 88200, 96000,
+];
+
+/// Container and codec an `Audio` can be encoded to.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum AudioFormat {
+    /// 32-bit float WAV, lossless but the largest.
+    #[default]
+    WavFloat,
+    /// 16-bit PCM WAV.
+    WavPcm16,
+    /// Opus in Ogg, the smallest at speech quality.
+    Opus,
+    /// AAC in M4A.
+    Aac,
+    Mp3,
+}
+
+impl AudioFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            AudioFormat::WavFloat | AudioFormat::WavPcm16 => "audio/wav",
+            AudioFormat::Opus => "audio/ogg",
+            AudioFormat::Aac => "audio/mp4",
+            AudioFormat::Mp3 => "audio/mpeg",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AudioFormat::WavFloat | AudioFormat::WavPcm16 => "wav",
+            AudioFormat::Opus => "ogg",
+            AudioFormat::Aac => "m4a",
+            AudioFormat::Mp3 => "mp3",
+        }
+    }
+
+    /// Short name of the FFmpeg muxer writing the container.
+    pub(crate) fn muxer(&self) -> &'static str {
+        match self {
+            AudioFormat::WavFloat | AudioFormat::WavPcm16 => "wav",
+            AudioFormat::Opus => "ogg",
+            AudioFormat::Aac => "ipod",
+            AudioFormat::Mp3 => "mp3",
+        }
+    }
+
+    pub(crate) fn encoder(&self) -> Result<AVCodec> {
+        let name = match self {
+            AudioFormat::WavFloat => "pcm_f32le",
+            AudioFormat::WavPcm16 => "pcm_s16le",
+            AudioFormat::Opus => "libopus",
+            AudioFormat::Aac => "aac",
+            AudioFormat::Mp3 => "libmp3lame",
+        };
+
+        AVCodec::new_encoder_with_name(name)
+            .map_err(|e| anyhow!("FFmpeg was built without {} support: {}", self, e))
+    }
+
+    /// Sample format the encoder is fed with, the input is mono so packed and planar are the same.
+    pub(crate) fn sample_format(&self) -> AVSampleFormat {
+        match self {
+            AudioFormat::WavFloat | AudioFormat::Opus => AVSampleFormat::Flt,
+            AudioFormat::WavPcm16 => AVSampleFormat::S16,
+            AudioFormat::Aac | AudioFormat::Mp3 => AVSampleFormat::Fltp,
+        }
+    }
+
+    pub(crate) fn bytes_per_sample(&self) -> usize {
+        match self.sample_format() {
+            AVSampleFormat::S16 => 2,
+            _ => 4,
+        }
+    }
+
+    /// Bit rates good enough for speech, PCM has none.
+    pub(crate) fn bit_rate(&self) -> Option<i64> {
+        match self {
+            AudioFormat::WavFloat | AudioFormat::WavPcm16 => None,
+            AudioFormat::Opus => Some(24_000),
+            AudioFormat::Aac | AudioFormat::Mp3 => Some(64_000),
+        }
+    }
+
+    /// The requested sample rate, or the closest one above it the codec supports.
+    pub fn supported_sample_rate(&self, requested: u32) -> u32 {
+        let rates: &[u32] = match self {
+            AudioFormat::WavFloat | AudioFormat::WavPcm16 => return requested,
+            AudioFormat::Opus => &OPUS_SAMPLE_RATES,
+            AudioFormat::Aac => &AAC_SAMPLE_RATES,
+            AudioFormat::Mp3 => &MP3_SAMPLE_RATES,
+        };
+
+        rates
+            .iter()
+            .copied()
+            .find(|rate| *rate >= requested)
+            .unwrap_or(*rates.last().unwrap())
+    }
+}
+
+impl FromStr for AudioFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let format = match s.to_ascii_lowercase().as_str() {
+            "wav" | "wav_f32" => AudioFormat::WavFloat,
+            "wav16" | "wav_s16" | "pcm16" => AudioFormat::WavPcm16,
+            "opus" | "ogg" => AudioFormat::Opus,
+            "aac" | "m4a" => AudioFormat::Aac,
+            "mp3" => AudioFormat::Mp3,
+            _ => bail!(
+                "Unknown audio format '{}', expected one of wav, wav16, opus, m4a, mp3",
+                s
+            ),
+        };
+
+        Ok(format)
+    }
+}
+
+impl Display for AudioFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            AudioFormat::WavFloat => "wav",
+            AudioFormat::WavPcm16 => "wav16",
+            AudioFormat::Opus => "opus",
+            AudioFormat::Aac => "m4a",
+            AudioFormat::Mp3 => "mp3",
+        };
+
+        write!(f, "{}", name)
+    }
+}
@@ -0,0 +1,3 @@
+pub mod audio;
+pub mod encoder;
+pub mod format;
@@ -7,9 +7,12 @@ use parking_lot::RwLock;
 use spark_ffmpeg::avcodec::AVCodec;
 use std::sync::LazyLock;
 
+pub mod audio;
 pub mod filter;
 pub mod image;
 
+pub use audio::audio::Audio;
+pub use audio::format::AudioFormat;
 pub use image::image::Image;
 pub use spark_ffmpeg::pixel::pixel_formater::RGB;
 pub use spark_ffmpeg::DeepClone;
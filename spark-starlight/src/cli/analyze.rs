@@ -0,0 +1,283 @@
+use crate::cli::overlay;
+use crate::config::Config;
+use crate::detect::analysis::locale::Language;
+use crate::engine::InferenceEngine;
+use crate::pipeline::{
+    analyse_scenes_with_detections, synthesize, AudioOutput, SceneAnalysis, SceneDetections,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Args;
+use log::{error, info};
+use spark_media::{AudioFormat, Image};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Extensions picked up when a folder is given.
+const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "bmp", "webp"];
+/// Descriptions longer than this are cut off in the summary table.
+const SUMMARY_TEXT_WIDTH: usize = 80;
+
+#[derive(Debug, Args)]
+pub struct AnalyzeArgs {
+    /// Image files, or folders whose images are analysed (not recursively).
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+    /// Folder the overlays, JSON and audio are written to.
+    #[arg(short, long, default_value = "./out")]
+    output: PathBuf,
+    /// Language of the descriptions, `en` or `zh`.
+    #[arg(short, long, default_value = "en")]
+    lang: Language,
+    /// Also synthesize each description.
+    #[arg(long)]
+    audio: bool,
+    /// Encoding of the audio: `wav`, `wav16`, `opus`, `m4a` or `mp3`.
+    #[arg(long, default_value = "wav")]
+    audio_format: AudioFormat,
+    /// Images whose boxes are detected together, in batched YOLO runs.
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..))]
+    batch: u16,
+}
+
+/// What one image came down to, for the summary table.
+struct Summary {
+    boxes: usize,
+    surfaces: usize,
+    text: String,
+}
+
+/// Analyses every image with the same pipeline the server uses and writes, per image,
+/// `<name>.overlay.png`, `<name>.json` and with `--audio` the spoken description.
+/// A broken image does not stop the run, it is reported in the summary and fails the command at the end.
+/// The time of an image is its share of the batch it was analysed in.
+pub async fn run(args: AnalyzeArgs, config: &'static Config) -> Result<()> {
+    let images = collect_images(&args.inputs)?;
+    if images.is_empty() {
+        bail!("No images found in {:?}", args.inputs);
+    }
+    std::fs::create_dir_all(&args.output)
+        .with_context(|| format!("Failed to create {}", args.output.display()))?;
+
+    let engine: &'static InferenceEngine =
+        Box::leak(Box::new(InferenceEngine::load(&config.model)?));
+
+    let mut rows = Vec::with_capacity(images.len());
+    for paths in images.chunks(args.batch as usize) {
+        info!(
+            "Analysing {} images from {}",
+            paths.len(),
+            paths[0].display()
+        );
+        let started = Instant::now();
+        let summaries = analyse_files(paths, &args, engine, config).await;
+        let elapsed = started.elapsed() / paths.len() as u32;
+        for (path, summary) in paths.iter().zip(summaries) {
+            if let Err(e) = &summary {
+                error!("Failed to analyse {}: {:?}", path.display(), e);
+            }
+            rows.push((path, elapsed, summary));
+        }
+    }
+
+    print_summary(&rows);
+
+    let failed = rows
+        .iter()
+        .filter(|(_, _, summary)| summary.is_err())
+        .count();
+    if failed > 0 {
+        bail!("{} of {} images failed", failed, rows.len());
+    }
+
+    Ok(())
+}
+
+/// Analyses the images at `paths` together, one summary per path in the same order.
+async fn analyse_files(
+    paths: &[PathBuf],
+    args: &AnalyzeArgs,
+    engine: &'static InferenceEngine,
+    config: &'static Config,
+) -> Vec<Result<Summary>> {
+    let loaded = paths
+        .iter()
+        .map(|path| load_image(path))
+        .collect::<Vec<_>>();
+    let images = loaded
+        .iter()
+        .filter_map(|loaded| loaded.as_ref().ok())
+        .map(|(_, image)| image.clone())
+        .collect();
+
+    let mut scenes = match analyse_scenes_with_detections(images, engine, config, args.lang).await {
+        Ok(scenes) => scenes.into_iter(),
+        Err(e) => {
+            // Detection failed for the batch as a whole
+            return loaded
+                .into_iter()
+                .map(|loaded| loaded.and_then(|_| Err(anyhow!("{:#}", e))))
+                .collect();
+        }
+    };
+
+    let mut summaries = Vec::with_capacity(paths.len());
+    for loaded in loaded {
+        let summary = match loaded {
+            Ok((name, image)) => match scenes.next() {
+                Some(Ok((scene, detections))) => {
+                    write_outputs(&name, image, scene, detections, args, engine).await
+                }
+                Some(Err(e)) => Err(e),
+                None => Err(anyhow!("No analysis was returned for {}", name)),
+            },
+            Err(e) => Err(e),
+        };
+        summaries.push(summary);
+    }
+
+    summaries
+}
+
+/// Reads and decodes one image, with the name its outputs are written under.
+fn load_image(path: &Path) -> Result<(String, Image)> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let image = Image::from_bytes(&bytes)?;
+    let name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| anyhow!("{} has no usable file name", path.display()))?;
+
+    Ok((name.to_string(), image))
+}
+
+/// Writes `<name>.json`, `<name>.overlay.png` and the audio of one analysed image.
+async fn write_outputs(
+    name: &str,
+    image: Image,
+    scene: SceneAnalysis,
+    detections: SceneDetections,
+    args: &AnalyzeArgs,
+    engine: &'static InferenceEngine,
+) -> Result<Summary> {
+    std::fs::write(
+        args.output.join(format!("{}.json", name)),
+        serde_json::to_vec_pretty(&scene)?,
+    )?;
+    overlay::render(image, &scene, &detections)?
+        .save_with_format(args.output.join(format!("{}.overlay.png", name)))?;
+
+    if args.audio && !scene.text.is_empty() {
+        let output = AudioOutput {
+            format: args.audio_format,
+            sample_rate: None,
+        };
+        let audio = synthesize(scene.text.clone(), scene.language, output, engine).await?;
+        std::fs::write(
+            args.output
+                .join(format!("{}.{}", name, args.audio_format.extension())),
+            audio,
+        )?;
+    }
+
+    Ok(Summary {
+        boxes: detections.highway.len() + detections.sidewalk.len() + detections.objects.len(),
+        surfaces: scene.surfaces.len(),
+        text: scene.text,
+    })
+}
+
+/// Expands folders into the images directly inside them, sorted by name. Files are taken as given.
+fn collect_images(inputs: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut images = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            let mut found = std::fs::read_dir(input)
+                .with_context(|| format!("Failed to list {}", input.display()))?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<std::io::Result<Vec<_>>>()?
+                .into_iter()
+                .filter(|path| path.is_file() && is_image(path))
+                .collect::<Vec<_>>();
+            found.sort();
+            images.extend(found);
+        } else if input.is_file() {
+            images.push(input.clone());
+        } else {
+            bail!("{} does not exist", input.display());
+        }
+    }
+
+    Ok(images)
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            IMAGE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+        })
+}
+
+fn print_summary(rows: &[(&PathBuf, Duration, Result<Summary>)]) {
+    let names = rows
+        .iter()
+        .map(|(path, _, _)| path.display().to_string())
+        .collect::<Vec<_>>();
+    let width = names.iter().map(String::len).max().unwrap_or(0).max(5);
+
+    println!(
+        "{:<width$}  {:>5}  {:>8}  {:>8}  DESCRIPTION",
+        "IMAGE",
+        "BOXES",
+        "SURFACES",
+        "TIME",
+        width = width
+    );
+    for (name, (_, elapsed, summary)) in names.iter().zip(rows) {
+        let time = format!("{}ms", elapsed.as_millis());
+        match summary {
+            Ok(summary) => println!(
+                "{:<width$}  {:>5}  {:>8}  {:>8}  {}",
+                name,
+                summary.boxes,
+                summary.surfaces,
+                time,
+                truncate(&summary.text, SUMMARY_TEXT_WIDTH),
+                width = width
+            ),
+            Err(e) => println!(
+                "{:<width$}  {:>5}  {:>8}  {:>8}  error: {}",
+                name,
+                "-",
+                "-",
+                time,
+                e,
+                width = width
+            ),
+        }
+    }
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+#[test]
+fn test_collect_images_filters_and_sorts() {
+    let folder = std::env::temp_dir().join(format!("starlight-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&folder).unwrap();
+    for name in ["b.JPG", "a.png", "notes.txt"] {
+        std::fs::write(folder.join(name), b"").unwrap();
+    }
+
+    let images = collect_images(&[folder.clone()]).unwrap();
+    std::fs::remove_dir_all(&folder).unwrap();
+
+    assert_eq!(images, vec![folder.join("a.png"), folder.join("b.JPG")]);
+    assert!(collect_images(&[folder]).is_err());
+    assert_eq!(truncate("人行道在前方", 3), "人行道...");
+}
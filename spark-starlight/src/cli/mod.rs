@@ -0,0 +1,22 @@
+pub mod analyze;
+mod overlay;
+
+use crate::cli::analyze::AnalyzeArgs;
+use clap::{Parser, Subcommand};
+
+/// Describes the road ahead of a pedestrian from camera images.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Starts the server when left out.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Serves `/uploadImage`, `/analyze` and `/stream` over HTTP.
+    Serve,
+    /// Runs the pipeline over image files and folders and writes overlays, JSON and audio.
+    Analyze(AnalyzeArgs),
+}
@@ -0,0 +1,100 @@
+use crate::pipeline::{SceneAnalysis, SceneDetections};
+use anyhow::Result;
+use spark_inference::inference::yolo::inference_yolo_detect::YoloDetectResult;
+use spark_inference::utils::masks::ApplyMask;
+use spark_media::filter::filter::AVFilter;
+use spark_media::{Image, RGB};
+
+/// Side length of the squares marking centerline points.
+const CENTERLINE_MARKER_SIZE: f32 = 8.0;
+/// Side length of the squares marking obstacles.
+const OBSTACLE_MARKER_SIZE: f32 = 24.0;
+
+/// Draws what the analysis was based on into the image, scaled to the mask size:
+/// highway boxes and mask in red, sidewalk in blue, other objects in green, centerlines in yellow
+/// and obstacles in magenta.
+pub fn render(
+    mut image: Image,
+    scene: &SceneAnalysis,
+    detections: &SceneDetections,
+) -> Result<Image> {
+    let size = detections.mask_size;
+    let mut filter = AVFilter::builder(image.pixel_format()?, image.get_size())?
+        .add_context("scale", &format!("{}:{}", size, size))?
+        .add_context("format", "rgb24")?;
+
+    for (boxes, color) in [
+        (&detections.highway, "red"),
+        (&detections.sidewalk, "blue"),
+        (&detections.objects, "green"),
+    ] {
+        for result in boxes {
+            filter = filter.add_context("drawbox", &detection_box(result, color))?;
+        }
+    }
+
+    for analysis in scene
+        .surfaces
+        .iter()
+        .filter_map(|surface| surface.analysis.as_ref())
+    {
+        for point in analysis.center_lines.iter() {
+            filter = filter.add_context(
+                "drawbox",
+                &marker(
+                    point.center_x,
+                    point.y as f32,
+                    CENTERLINE_MARKER_SIZE,
+                    "yellow",
+                ),
+            )?;
+        }
+        for obstacle in &analysis.obstacles {
+            filter = filter.add_context(
+                "drawbox",
+                &marker(
+                    obstacle.center_x,
+                    obstacle.y as f32,
+                    OBSTACLE_MARKER_SIZE,
+                    "magenta",
+                ),
+            )?;
+        }
+    }
+    image.apply_filter(&filter.build()?)?;
+
+    for (mask, color) in [
+        (&detections.highway_mask, RGB(75, 0, 0)),
+        (&detections.sidewalk_mask, RGB(0, 0, 75)),
+    ] {
+        if let Some(mask) = mask {
+            image.layering_mask(mask, color)?;
+        }
+    }
+
+    Ok(image)
+}
+
+/// `drawbox` arguments outlining a centre based detection box.
+fn detection_box(result: &YoloDetectResult, color: &str) -> String {
+    format!(
+        "x={}:y={}:w={}:h={}:color={}@1.0:t=4",
+        result.x - result.width / 2.0,
+        result.y - result.height / 2.0,
+        result.width,
+        result.height,
+        color
+    )
+}
+
+/// `drawbox` arguments for a filled square centred on a point.
+fn marker(x: f32, y: f32, size: f32, color: &str) -> String {
+    format!(
+        "x={}:y={}:w={}:h={}:color={}@1.0:t=fill",
+        x - size / 2.0,
+        y - size / 2.0,
+        size,
+        size,
+        color
+    )
+}
@@ -0,0 +1,837 @@
+use crate::detect::constants::*;
+use anyhow::{anyhow, bail, Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use spark_inference::engine::inference_engine::ExecutionProvider;
+use spark_inference::engine::session_pool::PoolOptions;
+use spark_inference::inference::depth::DepthOutput;
+use spark_inference::inference::tracker::TrackerOptions;
+use spark_inference::inference::yolo::{NmsOptions, NmsStrategy, NmsThresholds};
+use std::path::{Path, PathBuf};
+
+/// Environment variable pointing at the configuration file.
+const CONFIG_PATH_ENV: &str = "STARLIGHT_CONFIG";
+/// Configuration file used when `STARLIGHT_CONFIG` is not set.
+const DEFAULT_CONFIG_PATH: &str = "./starlight.toml";
+/// Prefix of environment overrides, e.g. `STARLIGHT__SERVER__PORT=8080`.
+const ENV_OVERRIDE_PREFIX: &str = "STARLIGHT__";
+
+/// Runtime configuration of the server, loaded once at startup.
+/// Every field has a default, so the file only needs to list what differs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub model: ModelConfig,
+    pub detection: DetectionConfig,
+    pub analysis: AnalysisConfig,
+    pub session: SessionConfig,
+    pub stream: StreamConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    /// Maximum accepted request body in bytes.
+    pub payload_limit: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "0.0.0.0".to_string(),
+            port: 7447,
+            payload_limit: 1024 * 1024 * 1024 * 25,
+        }
+    }
+}
+
+/// Which implementation runs detection, segmentation and speech synthesis.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelBackend {
+    /// The ONNX models on the GPU and the native TTS engines.
+    #[default]
+    Onnx,
+    /// Fixed boxes, box shaped masks and silent audio, needs neither a GPU nor model files.
+    Fake,
+}
+
+/// Where the ONNX models run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelProvider {
+    /// The first GPU when built with the `cuda` feature, the CPU otherwise.
+    #[default]
+    Auto,
+    Cpu,
+    Cuda,
+    TensorRT,
+}
+
+impl ModelProvider {
+    pub fn execution_provider(self) -> ExecutionProvider {
+        match self {
+            ModelProvider::Auto => ExecutionProvider::default(),
+            ModelProvider::Cpu => ExecutionProvider::CPU,
+            ModelProvider::Cuda => ExecutionProvider::CUDA(0),
+            ModelProvider::TensorRT => ExecutionProvider::TensorRT(0),
+        }
+    }
+}
+
+/// Which model produces the road masks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MaskSource {
+    /// YOLO boxes prompting SAM, the most accurate masks.
+    #[default]
+    Sam,
+    /// The masks of `yolo_seg.onnx`, a single model run but coarser edges.
+    Yolo,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModelConfig {
+    pub backend: ModelBackend,
+    pub provider: ModelProvider,
+    pub masks: MaskSource,
+    /// Folder containing `yolo_detect.onnx`, and `yolo_seg.onnx` for YOLO masks,
+    /// or the models its `manifest.toml` lists instead.
+    pub yolo_folder: PathBuf,
+    /// Folder containing `image_encoder.onnx` and `image_decoder.onnx`,
+    /// or the models its `manifest.toml` lists instead.
+    pub sam_folder: PathBuf,
+    /// Folder containing `depth.onnx`, or the model its `manifest.toml` lists instead.
+    /// Without one, distances are guessed from how low things are in the image.
+    pub depth_folder: Option<PathBuf>,
+    /// `{ unit = "meters" }` for metric models, `{ unit = "disparity", scale = 10.0 }` for
+    /// relative inverse depth, read as meters with `scale / value`.
+    pub depth_output: DepthOutput,
+    /// Folder of the English Matcha TTS model, vocoder, tokens and `espeak-ng-data`.
+    pub tts_en_folder: PathBuf,
+    /// Folder of the Chinese Matcha TTS model, vocoder, lexicon, dict and rule FSTs.
+    /// Chinese requests get no audio when it cannot be loaded.
+    pub tts_zh_folder: PathBuf,
+    /// Sessions loaded per model, so that as many requests run it at the same time.
+    pub pool_size: usize,
+    /// Milliseconds a request waits for a free session before it fails.
+    pub pool_wait_ms: u64,
+}
+
+impl Default for ModelConfig {
+    fn default() -> Self {
+        Self {
+            backend: ModelBackend::Onnx,
+            provider: ModelProvider::Auto,
+            masks: MaskSource::Sam,
+            yolo_folder: PathBuf::from("./data/model"),
+            sam_folder: PathBuf::from("./data/model/other5"),
+            depth_folder: None,
+            depth_output: DepthOutput::Meters,
+            tts_en_folder: PathBuf::from("./data/model/tts/matcha-en"),
+            tts_zh_folder: PathBuf::from("./data/model/tts/matcha-zh"),
+            pool_size: 1,
+            pool_wait_ms: 30_000,
+        }
+    }
+}
+
+impl ModelConfig {
+    pub fn pool_options(&self) -> PoolOptions {
+        PoolOptions {
+            size: self.pool_size,
+            wait_timeout: std::time::Duration::from_millis(self.pool_wait_ms),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DetectionConfig {
+    /// Minimum class score for YOLO to keep a box at all.
+    pub confidence: f32,
+    /// Overlap and minimum score of highway (class 0) boxes in NMS.
+    pub highway_nms: NmsThresholds,
+    /// Overlap and minimum score of sidewalk (class 1) boxes in NMS.
+    pub sidewalk_nms: NmsThresholds,
+    /// Overlap and minimum score of the classes after the two surfaces, the cars, people and
+    /// other objects of a detection model trained on more than the road.
+    pub object_nms: NmsThresholds,
+    /// `{ strategy = "hard" }`, `{ strategy = "diou" }` or `{ strategy = "soft", sigma = 0.5 }`.
+    pub nms: NmsStrategy,
+    /// Most boxes of all classes together that are kept, only the surfaces are segmented.
+    pub max_detections: usize,
+    /// Probability from which a pixel belongs to a YOLO mask, unused with SAM masks.
+    pub mask_probability: f32,
+    /// SAM masks with a lower predicted IoU are dropped, 0 keeps all of them.
+    pub min_mask_iou: f32,
+    /// Prompts SAM with a negative point at the centre of every highway box inside a sidewalk
+    /// box, so sidewalk masks stop running into the road next to them.
+    pub exclude_highway_from_sidewalk: bool,
+    /// Side length of the square SAM masks the analysis works on.
+    pub mask_size: u32,
+}
+
+impl Default for DetectionConfig {
+    fn default() -> Self {
+        Self {
+            confidence: 0.25,
+            highway_nms: NmsThresholds {
+                iou: 0.5,
+                score: 0.8,
+            },
+            sidewalk_nms: NmsThresholds {
+                iou: 0.5,
+                score: 0.4,
+            },
+            object_nms: NmsThresholds {
+                iou: 0.5,
+                score: 0.4,
+            },
+            nms: NmsStrategy::Hard,
+            max_detections: 32,
+            mask_probability: 0.5,
+            min_mask_iou: 0.5,
+            exclude_highway_from_sidewalk: false,
+            mask_size: 1024,
+        }
+    }
+}
+
+impl DetectionConfig {
+    /// NMS thresholds of the first `classes` classes in class order, the surfaces then the objects.
+    pub fn nms_thresholds(&self, classes: usize) -> Vec<NmsThresholds> {
+        let mut thresholds = vec![self.highway_nms, self.sidewalk_nms];
+        thresholds.resize(classes.max(thresholds.len()), self.object_nms);
+        thresholds
+    }
+
+    pub fn nms_options(&self) -> NmsOptions {
+        NmsOptions {
+            strategy: self.nms,
+            max_detections: Some(self.max_detections),
+        }
+    }
+}
+
+/// Tuning knobs of the mask analysis and the describers.
+/// The defaults are the values in `detect/constants.rs`, see there for what each one means.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalysisConfig {
+    pub num_vertical_samples: u32,
+    pub min_mask_width_for_center: u32,
+    pub road_start_y_threshold: f32,
+    pub road_center_x_threshold: f32,
+    pub min_centerline_points_for_shape: usize,
+    pub straight_road_x_drift_threshold: f32,
+    pub obstacle_width_change_factor: f32,
+    pub obstacle_gap_rows_threshold: u32,
+    pub distance_perspective_power: f32,
+    pub near_object_height_threshold_factor: f32,
+    pub near_object_width_threshold_factor: f32,
+    pub far_point_y_threshold_factor: f32,
+    pub min_points_for_continuation: usize,
+    pub close_gap_y_threshold_factor: f32,
+    pub edge_proximity_threshold_factor: f32,
+    pub min_sidewalk_width_factor_for_edge_warning: f32,
+    pub mask_close_radius: u32,
+    pub mask_open_radius: u32,
+    pub fill_mask_holes: bool,
+    pub keep_largest_mask_component: bool,
+    pub collision_warning_secs: f32,
+    pub collision_urgent_secs: f32,
+    pub approach_min_growth: f32,
+    pub horizon_y_factor: f32,
+    pub very_near_meters: f32,
+    pub relatively_near_meters: f32,
+    pub near_meters: f32,
+}
+
+impl Default for AnalysisConfig {
+    fn default() -> Self {
+        Self {
+            num_vertical_samples: NUM_VERTICAL_SAMPLES,
+            min_mask_width_for_center: MIN_MASK_WIDTH_FOR_CENTER,
+            road_start_y_threshold: ROAD_START_Y_THRESHOLD,
+            road_center_x_threshold: ROAD_CENTER_X_THRESHOLD,
+            min_centerline_points_for_shape: MIN_CENTERLINE_POINTS_FOR_SHAPE,
+            straight_road_x_drift_threshold: STRAIGHT_ROAD_X_DRIFT_THRESHOLD,
+            obstacle_width_change_factor: OBSTACLE_WIDTH_CHANGE_FACTOR,
+            obstacle_gap_rows_threshold: OBSTACLE_GAP_ROWS_THRESHOLD,
+            distance_perspective_power: DISTANCE_PERSPECTIVE_POWER,
+            near_object_height_threshold_factor: NEAR_OBJECT_HEIGHT_THRESHOLD_FACTOR,
+            near_object_width_threshold_factor: NEAR_OBJECT_WIDTH_THRESHOLD_FACTOR,
+            far_point_y_threshold_factor: FAR_POINT_Y_THRESHOLD_FACTOR,
+            min_points_for_continuation: MIN_POINTS_FOR_CONTINUATION,
+            close_gap_y_threshold_factor: CLOSE_GAP_Y_THRESHOLD_FACTOR,
+            edge_proximity_threshold_factor: EDGE_PROXIMITY_THRESHOLD_FACTOR,
+            min_sidewalk_width_factor_for_edge_warning: MIN_SIDEWALK_WIDTH_FACTOR_FOR_EDGE_WARNING,
+            mask_close_radius: MASK_CLOSE_RADIUS,
+            mask_open_radius: MASK_OPEN_RADIUS,
+            fill_mask_holes: FILL_MASK_HOLES,
+            keep_largest_mask_component: KEEP_LARGEST_MASK_COMPONENT,
+            collision_warning_secs: COLLISION_WARNING_SECS,
+            collision_urgent_secs: COLLISION_URGENT_SECS,
+            approach_min_growth: APPROACH_MIN_GROWTH,
+            horizon_y_factor: HORIZON_Y_FACTOR,
+            very_near_meters: VERY_NEAR_METERS,
+            relatively_near_meters: RELATIVELY_NEAR_METERS,
+            near_meters: NEAR_METERS,
+        }
+    }
+}
+
+/// Temporal smoothing of the announcements of one client across frames.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    /// Frames of analysis kept per surface.
+    pub history_size: usize,
+    /// Consecutive frames a new shape, start or obstacle judgment must persist before it is announced.
+    pub switch_frames: usize,
+    /// Seconds a warning stays muted after being announced, unless it gets more severe.
+    pub warning_cooldown_secs: u64,
+    /// Seconds without requests after which a session is dropped.
+    pub idle_timeout_secs: u64,
+    /// Most sessions kept at once, the one seen least recently makes room for a new client.
+    pub max_sessions: usize,
+    /// Milliseconds between two frames after which the objects tracked so far are forgotten,
+    /// their motion across such a gap says nothing about the next frame.
+    pub max_frame_gap_ms: u64,
+    /// How detections are followed across frames to tell approaching objects from standing ones.
+    pub tracker: TrackerOptions,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            history_size: 5,
+            switch_frames: 3,
+            warning_cooldown_secs: 10,
+            idle_timeout_secs: 300,
+            max_sessions: 1024,
+            max_frame_gap_ms: 2000,
+            tracker: TrackerOptions::default(),
+        }
+    }
+}
+
+/// The WebSocket streaming mode on `/stream`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamConfig {
+    /// Frames analysed at the same time across all streaming clients.
+    pub max_concurrent_inferences: usize,
+    /// Frames which waited longer than this for an inference slot are dropped unanalysed.
+    pub stale_frame_ms: u64,
+    /// Maximum size of one WebSocket message in bytes.
+    pub max_frame_size: usize,
+    /// Seconds without any message after which a client is disconnected.
+    pub client_timeout_secs: u64,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_inferences: 2,
+            stale_frame_ms: 1000,
+            max_frame_size: 16 * 1024 * 1024,
+            client_timeout_secs: 30,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from `STARLIGHT_CONFIG` (or `./starlight.toml`),
+    /// applies `STARLIGHT__SECTION__KEY` environment overrides and validates the result.
+    /// A missing default file is not an error, the built-in defaults are used instead.
+    pub fn load() -> Result<Self> {
+        let (path, explicit) = match std::env::var(CONFIG_PATH_ENV) {
+            Ok(path) => (PathBuf::from(path), true),
+            Err(_) => (PathBuf::from(DEFAULT_CONFIG_PATH), false),
+        };
+
+        let source = if path.exists() {
+            info!("Loading configuration from {}", path.display());
+            std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read config file {}", path.display()))?
+        } else if explicit {
+            bail!(
+                "Config file {} set by {} does not exist",
+                path.display(),
+                CONFIG_PATH_ENV
+            );
+        } else {
+            info!("No config file found at {}, using defaults", path.display());
+            String::new()
+        };
+
+        let config = Self::from_source(&source, std::env::vars())
+            .with_context(|| format!("Invalid configuration in {}", path.display()))?;
+        config.validate_paths()?;
+
+        Ok(config)
+    }
+
+    /// Parses a TOML document on top of the defaults, applies the matching overrides from `vars`
+    /// and validates the values.
+    pub fn from_source(
+        source: &str,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self> {
+        let mut table = toml::Table::try_from(Config::default())?;
+        merge_tables(&mut table, source.parse::<toml::Table>()?);
+        apply_env_overrides(&mut table, vars)?;
+
+        let config: Config = toml::Value::Table(table).try_into()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Checks every value for range errors and reports all of them at once.
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = Vec::new();
+        let mut check = |ok: bool, message: String| {
+            if !ok {
+                errors.push(message);
+            }
+        };
+        let fraction = |value: f32| (0.0..=1.0).contains(&value);
+
+        let server = &self.server;
+        check(server.port != 0, "server.port must not be 0".to_string());
+        check(
+            server.payload_limit > 0,
+            "server.payload_limit must be greater than 0".to_string(),
+        );
+
+        let model = &self.model;
+        check(
+            model.pool_size >= 1,
+            "model.pool_size must be at least 1".to_string(),
+        );
+        check(
+            model.pool_wait_ms > 0,
+            "model.pool_wait_ms must be greater than 0".to_string(),
+        );
+        if let DepthOutput::Disparity { scale } = model.depth_output {
+            check(
+                scale.is_finite() && scale > 0.0,
+                format!(
+                    "model.depth_output.scale must be a positive number, got {}",
+                    scale
+                ),
+            );
+        }
+
+        let detection = &self.detection;
+        for (name, value) in [
+            ("detection.confidence", detection.confidence),
+            ("detection.highway_nms.score", detection.highway_nms.score),
+            ("detection.sidewalk_nms.score", detection.sidewalk_nms.score),
+            ("detection.object_nms.score", detection.object_nms.score),
+            ("detection.mask_probability", detection.mask_probability),
+            ("detection.min_mask_iou", detection.min_mask_iou),
+        ] {
+            check(
+                fraction(value),
+                format!("{} must be within [0, 1], got {}", name, value),
+            );
+        }
+        for (name, value) in [
+            ("detection.highway_nms.iou", detection.highway_nms.iou),
+            ("detection.sidewalk_nms.iou", detection.sidewalk_nms.iou),
+            ("detection.object_nms.iou", detection.object_nms.iou),
+        ] {
+            check(
+                value > 0.0 && value <= 1.0,
+                format!("{} must be within (0, 1], got {}", name, value),
+            );
+        }
+        if let NmsStrategy::Soft { sigma } = detection.nms {
+            check(
+                sigma > 0.0,
+                format!("detection.nms.sigma must be greater than 0, got {}", sigma),
+            );
+        }
+        check(
+            detection.max_detections > 0,
+            "detection.max_detections must be greater than 0".to_string(),
+        );
+        check(
+            detection.mask_size >= 64,
+            format!(
+                "detection.mask_size must be at least 64, got {}",
+                detection.mask_size
+            ),
+        );
+
+        let analysis = &self.analysis;
+        check(
+            analysis.num_vertical_samples >= 2
+                && analysis.num_vertical_samples < detection.mask_size,
+            format!(
+                "analysis.num_vertical_samples must be within [2, detection.mask_size), got {}",
+                analysis.num_vertical_samples
+            ),
+        );
+        check(
+            analysis.min_mask_width_for_center >= 1,
+            "analysis.min_mask_width_for_center must be at least 1".to_string(),
+        );
+        check(
+            analysis.min_centerline_points_for_shape >= 2,
+            format!(
+                "analysis.min_centerline_points_for_shape must be at least 2, got {}",
+                analysis.min_centerline_points_for_shape
+            ),
+        );
+        check(
+            analysis.obstacle_gap_rows_threshold >= 1
+                && analysis.obstacle_gap_rows_threshold <= analysis.num_vertical_samples,
+            format!(
+                "analysis.obstacle_gap_rows_threshold must be within [1, analysis.num_vertical_samples], got {}",
+                analysis.obstacle_gap_rows_threshold
+            ),
+        );
+        for (name, radius) in [
+            ("analysis.mask_close_radius", analysis.mask_close_radius),
+            ("analysis.mask_open_radius", analysis.mask_open_radius),
+        ] {
+            check(
+                radius < detection.mask_size / 4,
+                format!(
+                    "{} must be below a quarter of detection.mask_size, got {}",
+                    name, radius
+                ),
+            );
+        }
+        check(
+            analysis.distance_perspective_power.is_finite()
+                && analysis.distance_perspective_power > 0.0,
+            format!(
+                "analysis.distance_perspective_power must be a positive number, got {}",
+                analysis.distance_perspective_power
+            ),
+        );
+        for (name, value) in [
+            (
+                "analysis.road_start_y_threshold",
+                analysis.road_start_y_threshold,
+            ),
+            (
+                "analysis.road_center_x_threshold",
+                analysis.road_center_x_threshold,
+            ),
+            (
+                "analysis.straight_road_x_drift_threshold",
+                analysis.straight_road_x_drift_threshold,
+            ),
+            (
+                "analysis.obstacle_width_change_factor",
+                analysis.obstacle_width_change_factor,
+            ),
+            (
+                "analysis.near_object_height_threshold_factor",
+                analysis.near_object_height_threshold_factor,
+            ),
+            (
+                "analysis.near_object_width_threshold_factor",
+                analysis.near_object_width_threshold_factor,
+            ),
+            (
+                "analysis.far_point_y_threshold_factor",
+                analysis.far_point_y_threshold_factor,
+            ),
+            (
+                "analysis.close_gap_y_threshold_factor",
+                analysis.close_gap_y_threshold_factor,
+            ),
+            (
+                "analysis.edge_proximity_threshold_factor",
+                analysis.edge_proximity_threshold_factor,
+            ),
+            (
+                "analysis.min_sidewalk_width_factor_for_edge_warning",
+                analysis.min_sidewalk_width_factor_for_edge_warning,
+            ),
+            ("analysis.horizon_y_factor", analysis.horizon_y_factor),
+        ] {
+            check(
+                fraction(value),
+                format!("{} must be within [0, 1], got {}", name, value),
+            );
+        }
+
+        check(
+            analysis.collision_urgent_secs > 0.0
+                && analysis.collision_urgent_secs <= analysis.collision_warning_secs,
+            format!(
+                "analysis.collision_urgent_secs must be within (0, analysis.collision_warning_secs], got {}",
+                analysis.collision_urgent_secs
+            ),
+        );
+        check(
+            analysis.approach_min_growth.is_finite() && analysis.approach_min_growth > 0.0,
+            format!(
+                "analysis.approach_min_growth must be a positive number, got {}",
+                analysis.approach_min_growth
+            ),
+        );
+
+        check(
+            analysis.very_near_meters > 0.0
+                && analysis.very_near_meters < analysis.relatively_near_meters
+                && analysis.relatively_near_meters < analysis.near_meters,
+            format!(
+                "analysis.very_near_meters, relatively_near_meters and near_meters must be positive and increasing, got {}, {} and {}",
+                analysis.very_near_meters, analysis.relatively_near_meters, analysis.near_meters
+            ),
+        );
+
+        let session = &self.session;
+        check(
+            session.history_size >= 1,
+            "session.history_size must be at least 1".to_string(),
+        );
+        check(
+            session.switch_frames >= 1 && session.switch_frames <= session.history_size,
+            format!(
+                "session.switch_frames must be within [1, session.history_size], got {}",
+                session.switch_frames
+            ),
+        );
+        check(
+            session.idle_timeout_secs > 0,
+            "session.idle_timeout_secs must be greater than 0".to_string(),
+        );
+        check(
+            session.max_sessions > 0,
+            "session.max_sessions must be greater than 0".to_string(),
+        );
+        check(
+            session.max_frame_gap_ms > 0,
+            "session.max_frame_gap_ms must be greater than 0".to_string(),
+        );
+        let tracker = &session.tracker;
+        for (name, value) in [
+            ("session.tracker.high_score", tracker.high_score),
+            ("session.tracker.low_score", tracker.low_score),
+            ("session.tracker.min_iou", tracker.min_iou),
+        ] {
+            check(
+                fraction(value),
+                format!("{} must be within [0, 1], got {}", name, value),
+            );
+        }
+        check(
+            tracker.low_score <= tracker.high_score,
+            format!(
+                "session.tracker.low_score must not exceed session.tracker.high_score, got {}",
+                tracker.low_score
+            ),
+        );
+        check(
+            tracker.min_hits >= 1,
+            "session.tracker.min_hits must be at least 1".to_string(),
+        );
+
+        let stream = &self.stream;
+        check(
+            stream.max_concurrent_inferences >= 1,
+            "stream.max_concurrent_inferences must be at least 1".to_string(),
+        );
+        check(
+            stream.stale_frame_ms > 0,
+            "stream.stale_frame_ms must be greater than 0".to_string(),
+        );
+        check(
+            stream.max_frame_size > 8,
+            format!(
+                "stream.max_frame_size must hold a timestamp and an image, got {}",
+                stream.max_frame_size
+            ),
+        );
+        check(
+            stream.client_timeout_secs > 0,
+            "stream.client_timeout_secs must be greater than 0".to_string(),
+        );
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("Invalid configuration:\n  {}", errors.join("\n  ")))
+        }
+    }
+
+    /// Model folders are checked separately so that `validate` stays usable without model files.
+    fn validate_paths(&self) -> Result<()> {
+        if self.model.backend == ModelBackend::Fake {
+            return Ok(());
+        }
+
+        let mut folders = vec![
+            ("model.yolo_folder", &self.model.yolo_folder),
+            ("model.tts_en_folder", &self.model.tts_en_folder),
+        ];
+        if self.model.masks == MaskSource::Sam {
+            folders.push(("model.sam_folder", &self.model.sam_folder));
+        }
+        if let Some(depth_folder) = &self.model.depth_folder {
+            folders.push(("model.depth_folder", depth_folder));
+        }
+        for (name, path) in folders {
+            if !Path::new(path).is_dir() {
+                bail!(
+                    "{} points to {}, which is not a directory",
+                    name,
+                    path.display()
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Recursively copies `overlay` into `base`, so nested tables only replace the keys they list.
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => {
+                merge_tables(base, overlay)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Writes `STARLIGHT__SECTION__KEY=value` pairs into the parsed document.
+/// Values are read as TOML literals (`0.7`, `true`, `"text"`), anything else is taken as a plain string.
+fn apply_env_overrides(
+    table: &mut toml::Table,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<()> {
+    for (key, raw) in vars {
+        let Some(path) = key.strip_prefix(ENV_OVERRIDE_PREFIX) else {
+            continue;
+        };
+        let path = path
+            .split("__")
+            .map(|segment| segment.to_ascii_lowercase())
+            .collect::<Vec<_>>();
+        if path.iter().any(|segment| segment.is_empty()) {
+            bail!("Malformed config override variable {}", key);
+        }
+
+        let value = format!("value = {}", raw)
+            .parse::<toml::Table>()
+            .ok()
+            .and_then(|mut parsed| parsed.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(raw.clone()));
+
+        let (last, sections) = path.split_last().unwrap();
+        let mut current = &mut *table;
+        for section in sections {
+            current = current
+                .entry(section.as_str())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .ok_or_else(|| anyhow!("Override {} does not point into a table", key))?;
+        }
+        info!("Config override {} = {}", path.join("."), raw);
+        current.insert(last.clone(), value);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_config_defaults_are_valid() {
+    let config = Config::from_source("", Vec::new()).unwrap();
+    assert_eq!(config.server.port, 7447);
+    assert_eq!(config.detection.mask_size, 1024);
+    assert_eq!(config.analysis.num_vertical_samples, NUM_VERTICAL_SAMPLES);
+}
+
+#[test]
+fn test_config_overrides_and_validation() {
+    let source = r#"
+        [analysis]
+        distance_perspective_power = 0.8
+    "#;
+    let config = Config::from_source(
+        source,
+        vec![
+            ("STARLIGHT__SERVER__PORT".to_string(), "8080".to_string()),
+            (
+                "STARLIGHT__DETECTION__HIGHWAY_NMS__IOU".to_string(),
+                "0.6".to_string(),
+            ),
+            ("UNRELATED".to_string(), "1".to_string()),
+        ],
+    )
+    .unwrap();
+    assert_eq!(config.server.port, 8080);
+    assert_eq!(config.detection.highway_nms.iou, 0.6);
+    assert_eq!(config.analysis.distance_perspective_power, 0.8);
+
+    let config = Config::from_source(
+        "",
+        vec![("STARLIGHT__MODEL__BACKEND".to_string(), "fake".to_string())],
+    )
+    .unwrap();
+    assert_eq!(config.model.backend, ModelBackend::Fake);
+    assert!(config.validate_paths().is_ok());
+
+    let config = Config::from_source("[model]\nprovider = \"cpu\"", Vec::new()).unwrap();
+    assert!(matches!(
+        config.model.provider.execution_provider(),
+        ExecutionProvider::CPU
+    ));
+
+    let error = Config::from_source("[model]\npool_size = 0", Vec::new()).unwrap_err();
+    assert!(error.to_string().contains("model.pool_size"));
+
+    let config = Config::from_source(
+        "[detection]\nnms = { strategy = \"soft\", sigma = 0.5 }",
+        Vec::new(),
+    )
+    .unwrap();
+    assert_eq!(config.detection.nms, NmsStrategy::Soft { sigma: 0.5 });
+    let thresholds = config.detection.nms_thresholds(4);
+    assert_eq!(thresholds.len(), 4);
+    assert_eq!(thresholds[0].score, 0.8);
+    assert_eq!(thresholds[3], config.detection.object_nms);
+
+    let error = Config::from_source(
+        "[detection]\nsidewalk_nms = { iou = 0.5, score = 1.5 }",
+        Vec::new(),
+    )
+    .unwrap_err();
+    assert!(error.to_string().contains("detection.sidewalk_nms.score"));
+
+    // The score lives in the NMS thresholds only
+    assert!(Config::from_source("[detection]\nhighway_score = 0.8", Vec::new()).is_err());
+
+    let error = Config::from_source("[analysis]\nunknown_knob = 1", Vec::new()).unwrap_err();
+    assert!(error.to_string().contains("unknown_knob"));
+}
+
+#[test]
+fn test_config_example_file() {
+    let config = Config::from_source(include_str!("../../starlight.toml"), Vec::new()).unwrap();
+    assert_eq!(config.detection.sidewalk_nms.score, 0.4);
+    assert_eq!(
+        config.model.tts_zh_folder,
+        ModelConfig::default().tts_zh_folder
+    );
+}
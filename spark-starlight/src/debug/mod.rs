@@ -9,7 +9,7 @@ pub fn debug_image() {
     use spark_inference::inference::yolo::inference_yolo_detect::{
         YoloDetectInference, YoloDetectSession,
     };
-    use spark_inference::inference::yolo::NMSImplement;
+    use spark_inference::inference::yolo::{NMSImplement, NmsOptions, NmsThresholds};
     use spark_inference::utils::graph::SamPrompt;
     use spark_inference::utils::masks::ApplyMask;
     use spark_media::filter::filter::AVFilter;
@@ -32,17 +32,20 @@ pub fn debug_image() {
         let results = yolo.inference_yolo(image.clone(), 0.25)?;
         println!("results: {:?}", results.len());
 
-        let result_highway = results
-            .clone()
+        let thresholds = [
+            NmsThresholds {
+                iou: 0.5,
+                score: 0.7,
+            },
+            NmsThresholds {
+                iou: 0.5,
+                score: 0.4,
+            },
+        ];
+        let (result_highway, result_sidewalk): (Vec<_>, Vec<_>) = results
+            .non_maximum_suppression(&thresholds, NmsOptions::default())
             .into_iter()
-            .filter(|result| result.score[0] >= 0.7)
-            .collect::<Vec<_>>();
-        let result_sidewalk = results
-            .into_iter()
-            .filter(|result| result.score[1] >= 0.4)
-            .collect::<Vec<_>>();
-        let result_highway = result_highway.non_maximum_suppression(0.5, 0.35, 0);
-        let result_sidewalk = result_sidewalk.non_maximum_suppression(0.5, 0.25, 1);
+            .partition(|result| result.class_id == 0);
         println!("highway: {:?}", result_highway);
         println!("sidewalk: {:?}", result_sidewalk);
 
@@ -76,7 +79,7 @@ pub fn debug_image() {
         let result_highway = result_highway
             .iter()
             .map(|yolo| {
-                SamPrompt::Box(spark_inference::utils::graph::Box {
+                SamPrompt::from_box(spark_inference::utils::graph::Box {
                     x: yolo.x,
                     y: yolo.y,
                     width: yolo.width,
@@ -88,7 +91,7 @@ pub fn debug_image() {
         let result_sidewalk = result_sidewalk
             .iter()
             .map(|yolo| {
-                SamPrompt::Box(spark_inference::utils::graph::Box {
+                SamPrompt::from_box(spark_inference::utils::graph::Box {
                     x: yolo.x,
                     y: yolo.y,
                     width: yolo.width,
@@ -133,6 +136,8 @@ pub fn debug_image() {
 
 #[tokio::test]
 async fn debug_tts() -> anyhow::Result<()> {
+    use crate::config::AnalysisConfig;
+    use crate::detect::analysis::locale::Language;
     use crate::detect::mask::{analyze_road_mask, get_best_highway};
     use crate::log_init;
     use bitvec::prelude::BitVec;
@@ -144,7 +149,7 @@ async fn debug_tts() -> anyhow::Result<()> {
     use spark_inference::inference::yolo::inference_yolo_detect::{
         YoloDetectInference, YoloDetectResult, YoloDetectSession,
     };
-    use spark_inference::inference::yolo::NMSImplement;
+    use spark_inference::inference::yolo::{NMSImplement, NmsOptions, NmsThresholds};
     use spark_inference::utils::graph::Point;
     use spark_inference::utils::graph::SamPrompt;
     use spark_media::Image;
@@ -174,52 +179,51 @@ async fn debug_tts() -> anyhow::Result<()> {
         };
         info!("detect results: {:?}", results.len());
 
-        let result_highway = results
-            .clone()
+        let thresholds = [
+            NmsThresholds {
+                iou: 0.5,
+                score: 0.8,
+            },
+            NmsThresholds {
+                iou: 0.5,
+                score: 0.4,
+            },
+        ];
+        let (mut result_highway, mut result_sidewalk): (Vec<_>, Vec<_>) = results
+            .non_maximum_suppression(&thresholds, NmsOptions::default())
             .into_iter()
-            .filter(|result| result.score[0] >= 0.8)
-            .collect::<Vec<_>>();
-        let result_sidewalk = results
-            .into_iter()
-            .filter(|result| result.score[1] >= 0.4)
-            .collect::<Vec<_>>();
-        let mut result_highway = result_highway.non_maximum_suppression(0.5, 0.35, 0);
-        let mut result_sidewalk = result_sidewalk.non_maximum_suppression(0.5, 0.25, 1);
+            .partition(|result| result.class_id == 0);
 
         let mask = {
             let result_highway = result_highway
                 .iter()
                 .map(|yolo| {
-                    SamPrompt::Both(
-                        Point {
-                            x: yolo.x,
-                            y: yolo.y,
-                        },
-                        spark_inference::utils::graph::Box {
-                            x: yolo.x - yolo.width / 2.0,
-                            y: yolo.y - yolo.height / 2.0,
-                            width: yolo.width,
-                            height: yolo.height,
-                        },
-                    )
+                    SamPrompt::from_box(spark_inference::utils::graph::Box {
+                        x: yolo.x - yolo.width / 2.0,
+                        y: yolo.y - yolo.height / 2.0,
+                        width: yolo.width,
+                        height: yolo.height,
+                    })
+                    .positive(Point {
+                        x: yolo.x,
+                        y: yolo.y,
+                    })
                 })
                 .collect::<Vec<_>>();
 
             let result_sidewalk = result_sidewalk
                 .iter()
                 .map(|yolo| {
-                    SamPrompt::Both(
-                        Point {
-                            x: yolo.x,
-                            y: yolo.y,
-                        },
-                        spark_inference::utils::graph::Box {
-                            x: yolo.x - yolo.width / 2.0,
-                            y: yolo.y - yolo.height / 2.0,
-                            width: yolo.x + yolo.width / 2.0,
-                            height: yolo.y + yolo.height / 2.0,
-                        },
-                    )
+                    SamPrompt::from_box(spark_inference::utils::graph::Box {
+                        x: yolo.x - yolo.width / 2.0,
+                        y: yolo.y - yolo.height / 2.0,
+                        width: yolo.x + yolo.width / 2.0,
+                        height: yolo.y + yolo.height / 2.0,
+                    })
+                    .positive(Point {
+                        x: yolo.x,
+                        y: yolo.y,
+                    })
                 })
                 .collect::<Vec<_>>();
 
@@ -249,12 +253,23 @@ async fn debug_tts() -> anyhow::Result<()> {
             yolo.height = yolo.height / image_height as f32 * 1024.0;
         }
 
+        let config = AnalysisConfig::default();
         println!("--- Analyzing Highway ---");
         // Filter highway masks: prioritize closest to user (highest average y)
-        if let Some(mask) = get_best_highway(&mask[0]) {
+        if let Some(mask) = get_best_highway(&mask[0], 1024, &config) {
             println!(
                 "Highway: {}",
-                analyze_road_mask(mask, result_highway.as_slice(), 1024, 1024, "Highway").await
+                analyze_road_mask(
+                    mask,
+                    result_highway.as_slice(),
+                    1024,
+                    1024,
+                    "Highway",
+                    &config,
+                    Language::English
+                )
+                .await
+                .description
             );
         }
 
@@ -278,7 +293,17 @@ async fn debug_tts() -> anyhow::Result<()> {
         if let Some(mask) = best_sidewalk_mask {
             println!(
                 "Sidewalk: {}",
-                analyze_road_mask(mask, result_sidewalk.as_slice(), 1024, 1024, "Sidewalk").await
+                analyze_road_mask(
+                    mask,
+                    result_sidewalk.as_slice(),
+                    1024,
+                    1024,
+                    "Sidewalk",
+                    &config,
+                    Language::English
+                )
+                .await
+                .description
             );
         }
 
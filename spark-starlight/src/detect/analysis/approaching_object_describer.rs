@@ -0,0 +1,64 @@
+use crate::config::AnalysisConfig;
+use crate::detect::analysis::locale::{Language, Message};
+use crate::detect::analysis::Describer;
+use crate::detect::property::analyse_result::RoadAnalysisData;
+use crate::detect::property::approach::TrackedObject;
+use crate::detect::property::direction::DirectionCategory;
+
+/// Warns about the tracked object that would reach the user first, unlike the distance
+/// of a single frame this tells an oncoming car from a parked one.
+#[derive(Debug, Copy, Clone)]
+pub struct ApproachingObjectDescriber;
+
+impl ApproachingObjectDescriber {
+    /// The object with the shortest time to collision within the warning time, and that time.
+    fn most_imminent<'a>(
+        data: &'a RoadAnalysisData,
+        config: &AnalysisConfig,
+    ) -> Option<(&'a TrackedObject, f32)> {
+        data.tracks
+            .iter()
+            .filter_map(|track| {
+                let ttc = track.time_to_collision(data.image_height, config)?;
+                (ttc <= config.collision_warning_secs).then_some((track, ttc))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+    }
+}
+
+impl Describer for ApproachingObjectDescriber {
+    async fn describe(
+        &self,
+        data: &RoadAnalysisData,
+        _object_type_name: &str,
+        config: &AnalysisConfig,
+        language: Language,
+    ) -> Option<String> {
+        let (track, ttc) = Self::most_imminent(data, config)?;
+        // The footpoint tells the direction, the centre of a tall box is far above the ground
+        let direction = DirectionCategory::get_direction(
+            track.x,
+            track.y + track.height / 2.0,
+            None,
+            data.image_width,
+            data.image_height,
+        );
+
+        Some(language.text(&Message::ApproachingObject {
+            label: &track.label,
+            direction: &direction,
+            quickly: ttc <= config.collision_urgent_secs,
+        }))
+    }
+
+    fn severity(&self, data: &RoadAnalysisData, config: &AnalysisConfig) -> Option<u8> {
+        // Above every distance based warning, an urgent one above all of them
+        Self::most_imminent(data, config).map(|(_, ttc)| {
+            if ttc <= config.collision_urgent_secs {
+                6
+            } else {
+                5
+            }
+        })
+    }
+}
@@ -1,4 +1,6 @@
-use crate::detect::analysis::{Describer, DescriberDispatcher};
+use crate::config::AnalysisConfig;
+use crate::detect::analysis::locale::{Language, Message};
+use crate::detect::analysis::{Describer, DescriberDispatcher, DescriptionPart};
 use crate::detect::property::analyse_result::RoadAnalysisData;
 
 pub struct CompositeDescriber {
@@ -13,53 +15,77 @@ impl CompositeDescriber {
     }
 
     /// Generates the full description by combining outputs from individual describers.
-    pub async fn describe(&self, data: &RoadAnalysisData<'_>, object_type_name: &str) -> String {
-        let mut parts: Vec<String> = Vec::new();
+    pub async fn describe(
+        &self,
+        data: &RoadAnalysisData,
+        object_type_name: &str,
+        config: &AnalysisConfig,
+        language: Language,
+    ) -> String {
+        let parts = self
+            .describe_parts(data, object_type_name, config, language)
+            .await;
+        self.compose(&parts, data, object_type_name, language)
+    }
 
-        let back = self.describers.describe(data, object_type_name).await;
-        if let Some(desc) = back {
-            parts.push(desc);
-        }
+    /// Runs the individual describers without joining them, so callers can drop parts first.
+    pub async fn describe_parts(
+        &self,
+        data: &RoadAnalysisData,
+        object_type_name: &str,
+        config: &AnalysisConfig,
+        language: Language,
+    ) -> Vec<DescriptionPart> {
+        DescriberDispatcher::describe_all(
+            &self.describers,
+            data,
+            object_type_name,
+            config,
+            language,
+        )
+        .await
+    }
 
-        if parts.is_empty() {
+    /// Joins the describer outputs into the final sentence, falling back to a generic
+    /// message when no describer had anything to say.
+    pub fn compose(
+        &self,
+        describer_parts: &[DescriptionPart],
+        data: &RoadAnalysisData,
+        object_type_name: &str,
+        language: Language,
+    ) -> String {
+        if describer_parts.is_empty() {
             // Handle the case where absolutely nothing could be described
             // This might happen if core analysis returns None or if all describers return None.
             // Check the RoadStartDescriber's initial message if it was generated.
             // A more robust check could involve looking at the initial data more directly.
-            if data.center_lines.is_empty() && data.detect_results.is_empty() {
-                return format!("No {} or related objects detected.", object_type_name);
+            let message = if data.center_lines.is_empty() && data.detect_results.is_empty() {
+                Message::NothingDetected {
+                    surface: object_type_name,
+                }
             } else if data.center_lines.is_empty() {
                 // If objects were detected but no road, DetectedObjectDescriber should have run.
                 // If it didn't produce output for some reason, provide a fallback.
-                return format!(
-                    "Could not clearly identify the {}. No specific objects described.",
-                    object_type_name
-                );
+                Message::SurfaceUnclear {
+                    surface: object_type_name,
+                }
             } else {
                 // Has centerline, but maybe no shape, no obstacles, no objects? Unlikely but possible.
-                return format!(
-                    "Analysis complete for {}. Path appears clear.",
-                    object_type_name
-                );
-            }
-        }
-
-        // Combine the parts into a single sentence-like structure.
-        // Capitalize the first part, add periods, and join with ". ".
-        if let Some(first_part) = parts.first_mut() {
-            let mut c = first_part.chars();
-            if let Some(f) = c.next() {
-                *first_part = f.to_uppercase().collect::<String>() + c.as_str();
-            }
-        }
-
-        let mut final_description = parts.join(". ");
-        // Ensure the final string ends with a period.
-        if !final_description.ends_with('.') {
-            final_description.push('.');
+                Message::SurfaceClear {
+                    surface: object_type_name,
+                }
+            };
+            return language.text(&message);
         }
 
-        final_description
+        // Combine the parts into a single sentence-like structure:
+        // capitalize it where the language has cases and end it with a full stop.
+        let parts = describer_parts
+            .iter()
+            .map(|part| part.text.as_str())
+            .collect::<Vec<_>>();
+        language.finish_sentence(&language.join_parts(&parts))
     }
 }
 
@@ -68,13 +94,17 @@ impl CompositeDescriber {
 impl Describer for CompositeDescriber {
     async fn describe(
         &self,
-        data: &RoadAnalysisData<'_>,
+        data: &RoadAnalysisData,
         object_type_name: &str,
+        config: &AnalysisConfig,
+        language: Language,
     ) -> Option<String> {
         // The composite always aims to produce *some* description, so wrap in Some()
         // unless it's truly empty after joining.
-        let desc = self.describe(data, object_type_name).await;
-        if desc.is_empty() || desc == "." {
+        let desc = self
+            .describe(data, object_type_name, config, language)
+            .await;
+        if desc.is_empty() || desc == "." || desc == "。" {
             // Check for empty or just a period
             None
         } else {
@@ -8,29 +8,30 @@ macro_rules! define_describer {
         }
 
         impl Describer for DescriberDispatcher {
-            async fn describe(&self, data: &RoadAnalysisData<'_>, object_type_name: &str) -> Option<String> {
+            async fn describe(&self, data: &RoadAnalysisData, object_type_name: &str, config: &AnalysisConfig, language: Language) -> Option<String> {
                 match self {
                     $(
-                        DescriberDispatcher::$name(describer) => describer.describe(data, object_type_name).await,
+                        DescriberDispatcher::$name(describer) => describer.describe(data, object_type_name, config, language).await,
+                    )*
+                }
+            }
+
+            fn severity(&self, data: &RoadAnalysisData, config: &AnalysisConfig) -> Option<u8> {
+                match self {
+                    $(
+                        DescriberDispatcher::$name(describer) => describer.severity(data, config),
                     )*
                 }
             }
         }
 
         impl Describer for Vec<DescriberDispatcher> {
-            async fn describe(&self, data: &RoadAnalysisData<'_>, object_type_name: &str) -> Option<String> {
-                let mut descriptions = Vec::new();
-                let futures = self.iter().map(|describer| {
-                    describer.describe(&data, &object_type_name)
-                });
-
-                let results = futures::future::join_all(futures).await;
-
-                for result in results {
-                    if let Some(desc) = result {
-                        descriptions.push(desc);
-                    }
-                }
+            async fn describe(&self, data: &RoadAnalysisData, object_type_name: &str, config: &AnalysisConfig, language: Language) -> Option<String> {
+                let descriptions = DescriberDispatcher::describe_all(self, data, object_type_name, config, language)
+                    .await
+                    .into_iter()
+                    .map(|part| part.text)
+                    .collect::<Vec<_>>();
 
                 if descriptions.is_empty() {
                     None
@@ -41,6 +42,35 @@ macro_rules! define_describer {
         }
 
         impl DescriberDispatcher {
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(
+                        DescriberDispatcher::$name(_) => stringify!($name),
+                    )*
+                }
+            }
+
+            /// Runs every describer and keeps the parts that produced text, in describer order.
+            pub async fn describe_all(describers: &[Self], data: &RoadAnalysisData, object_type_name: &str, config: &AnalysisConfig, language: Language) -> Vec<DescriptionPart> {
+                let futures = describers.iter().map(|describer| {
+                    describer.describe(&data, &object_type_name, config, language)
+                });
+
+                let results = futures::future::join_all(futures).await;
+
+                describers
+                    .iter()
+                    .zip(results)
+                    .filter_map(|(describer, result)| {
+                        result.map(|text| DescriptionPart {
+                            source: describer.name(),
+                            text,
+                            severity: describer.severity(data, config),
+                        })
+                    })
+                    .collect()
+            }
+
             pub fn all() -> Vec<Self> {
                 vec![
                     $(
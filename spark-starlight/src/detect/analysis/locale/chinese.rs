@@ -0,0 +1,201 @@
+use crate::detect::analysis::locale::Message;
+use crate::detect::property::direction::DirectionCategory;
+use crate::detect::property::distance::DistanceCategory;
+use crate::detect::property::obstacle::ObstacleKind;
+
+pub(super) fn text(message: &Message) -> String {
+    match message {
+        Message::ShapeStraight => "道路笔直向前".to_string(),
+        Message::ShapeCurvesLeft => "道路向左弯".to_string(),
+        Message::ShapeCurvesRight => "道路向右弯".to_string(),
+        Message::ShapeUnclear => "道路走向不明确".to_string(),
+
+        Message::NoObstacles => "路上暂无障碍".to_string(),
+        Message::Obstacle {
+            kind,
+            direction,
+            distance,
+        } => {
+            let reason = match kind {
+                ObstacleKind::Gap => "路面可能中断",
+                ObstacleKind::Narrowing => "路面明显变窄",
+            };
+            format!(
+                "注意：{}{}，距离{}",
+                self::direction(direction),
+                reason,
+                self::distance(distance)
+            )
+        }
+        Message::MoreObstacles { count } => format!("，更远处还有{}处可能的障碍", count),
+
+        Message::PathEndsSoon => "注意：前方人行道很快就到尽头或被阻挡".to_string(),
+        Message::NearLeftEdge => "注意：你靠近人行道左侧边缘".to_string(),
+        Message::NearRightEdge => "注意：你靠近人行道右侧边缘".to_string(),
+        Message::SidewalkStartsAhead {
+            distance,
+            direction,
+        } => format!(
+            "人行道在前方{}，距离{}",
+            self::direction(direction),
+            self::distance(distance)
+        ),
+        Message::OffSidewalk { direction } => format!(
+            "注意：你似乎不在人行道上，人行道在{}",
+            self::direction(direction)
+        ),
+
+        Message::StartsAtFeet { surface } => format!("{}从你脚下开始", self::surface(surface)),
+        Message::StartsAway {
+            surface,
+            distance,
+            direction,
+        } => format!(
+            "注意：{}不是从你脚下开始，它在{}，距离{}",
+            self::surface(surface),
+            direction.map_or("正前方".to_string(), self::direction),
+            self::distance(distance)
+        ),
+
+        Message::ObjectsHeader { count } => format!("检测到{}个物体：", count),
+        Message::ObjectsOnPathHeader => "另外检测到：".to_string(),
+        Message::Object {
+            label,
+            distance,
+            direction,
+        } => format!(
+            "{}有一个{}，距离{}",
+            direction_phrase(direction),
+            self::label(label),
+            self::distance(distance)
+        ),
+        Message::ObjectGroup {
+            label,
+            count,
+            distance,
+            direction,
+        } => format!(
+            "{}有{}个{}，最近的{}",
+            direction_phrase(direction),
+            count_word(*count),
+            self::label(label),
+            self::distance(distance)
+        ),
+        Message::LargeObjectUnderfoot { label, direction } => format!(
+            "脚下有一大片{}覆盖路面，主要在{}",
+            self::label(label),
+            self::direction(direction)
+        ),
+        Message::ApproachingObject {
+            label,
+            direction,
+            quickly,
+        } => format!(
+            "注意：{}有{}正在{}靠近",
+            direction_phrase(direction),
+            self::label(label),
+            if *quickly { "快速" } else { "" }
+        ),
+
+        Message::NothingDetected { surface } => {
+            format!("没有检测到{}或相关物体", self::surface(surface))
+        }
+        Message::SurfaceUnclear { surface } => {
+            format!("无法清楚识别{}，没有可描述的物体", self::surface(surface))
+        }
+        Message::SurfaceClear { surface } => {
+            format!("{}分析完成，路面畅通", self::surface(surface))
+        }
+        Message::AnalysisFailed { surface } => format!(
+            "{}分析失败：输入数据无效或未找到道路特征",
+            self::surface(surface)
+        ),
+        Message::NothingInScene => "注意：当前场景中没有发现任何东西".to_string(),
+        Message::NoRoad => "注意：没有检测到道路".to_string(),
+    }
+}
+
+fn distance(distance: &DistanceCategory) -> &'static str {
+    match distance {
+        DistanceCategory::VeryNear => "很近",
+        DistanceCategory::RelativelyNear => "较近",
+        DistanceCategory::Near => "不远",
+        DistanceCategory::Far => "较远",
+        DistanceCategory::Unknown => "未知",
+    }
+}
+
+/// "11 o'clock" becomes "11点钟方向", "11:30" becomes "11点半方向".
+fn direction(direction: &DirectionCategory) -> String {
+    match direction {
+        DirectionCategory::Clock(clock) => match clock.split_once(':') {
+            Some((hour, _)) => format!("{}点半方向", hour),
+            None => format!("{}点钟方向", clock.trim_end_matches(" o'clock")),
+        },
+        DirectionCategory::Unknown => "未知方向".to_string(),
+    }
+}
+
+fn direction_phrase(direction: &DirectionCategory) -> String {
+    match direction {
+        DirectionCategory::Clock("12 o'clock") => "正前方".to_string(),
+        _ => self::direction(direction),
+    }
+}
+
+fn count_word(count: usize) -> String {
+    const WORDS: [&str; 11] = [
+        "零", "一", "两", "三", "四", "五", "六", "七", "八", "九", "十",
+    ];
+    WORDS
+        .get(count)
+        .map_or_else(|| count.to_string(), |word| word.to_string())
+}
+
+fn surface(surface: &str) -> &str {
+    match surface {
+        "Highway" => "马路",
+        "Sidewalk" => "人行道",
+        _ => surface,
+    }
+}
+
+/// Names of the common detection classes, unknown labels are spoken as they are.
+fn label(label: &str) -> &str {
+    match label {
+        "highway" | "road" => "马路",
+        "sidewalk" => "人行道",
+        "person" => "行人",
+        "bicycle" => "自行车",
+        "car" => "汽车",
+        "motorcycle" => "摩托车",
+        "bus" => "公交车",
+        "truck" => "卡车",
+        "train" => "火车",
+        "traffic light" => "红绿灯",
+        "fire hydrant" => "消防栓",
+        "stop sign" => "停车标志",
+        "parking meter" => "停车计时器",
+        "bench" => "长椅",
+        "dog" => "狗",
+        "cat" => "猫",
+        "pole" => "电线杆",
+        "tree" => "树",
+        "stairs" => "台阶",
+        "crosswalk" => "人行横道",
+        _ => label,
+    }
+}
+
+#[test]
+fn test_chinese_directions() {
+    assert_eq!(
+        direction(&DirectionCategory::Clock("9 o'clock")),
+        "9点钟方向"
+    );
+    assert_eq!(direction(&DirectionCategory::Clock("10:30")), "10点半方向");
+    assert_eq!(
+        direction_phrase(&DirectionCategory::Clock("12 o'clock")),
+        "正前方"
+    );
+}
@@ -0,0 +1,211 @@
+use crate::detect::analysis::locale::Message;
+use crate::detect::property::direction::DirectionCategory;
+use crate::detect::property::distance::DistanceCategory;
+use crate::detect::property::obstacle::ObstacleKind;
+
+pub(super) fn text(message: &Message) -> String {
+    match message {
+        Message::ShapeStraight => "It proceeds straight".to_string(),
+        Message::ShapeCurvesLeft => "It curves left".to_string(),
+        Message::ShapeCurvesRight => "It curves right".to_string(),
+        Message::ShapeUnclear => "Its shape is unclear".to_string(),
+
+        Message::NoObstacles => "No immediate obstacles detected on the path".to_string(),
+        Message::Obstacle {
+            kind,
+            direction,
+            distance,
+        } => {
+            let reason = match kind {
+                ObstacleKind::Gap => "Potential gap",
+                ObstacleKind::Narrowing => "Significant narrowing",
+            };
+            format!(
+                "Obstacle ({} near {}) detected, {}",
+                reason,
+                self::direction(direction),
+                self::distance(distance)
+            )
+        }
+        Message::MoreObstacles { count } => {
+            format!(", with {} other potential obstacles further down", count)
+        }
+
+        Message::PathEndsSoon => {
+            "Warning: The sidewalk path ahead appears short or obstructed soon".to_string()
+        }
+        Message::NearLeftEdge => "Warning: You are near the left edge of the sidewalk".to_string(),
+        Message::NearRightEdge => {
+            "Warning: You are near the right edge of the sidewalk".to_string()
+        }
+        Message::SidewalkStartsAhead {
+            distance,
+            direction,
+        } => format!(
+            "The sidewalk starts {} ahead and is in the {}",
+            self::distance(distance),
+            self::direction(direction)
+        ),
+        Message::OffSidewalk { direction } => format!(
+            "Warning: You appear to be off the sidewalk. The sidewalk is in the {}",
+            self::direction(direction)
+        ),
+
+        Message::StartsAtFeet { surface } => format!("The {} starts at your feet.", surface),
+        Message::StartsAway {
+            surface,
+            distance,
+            direction,
+        } => format!(
+            "Warning: The {} doesn't start directly underfoot. It appears {} in the {}.",
+            surface,
+            self::distance(distance),
+            direction.map_or("centered".to_string(), self::direction)
+        ),
+
+        Message::ObjectsHeader { count } => format!("Detected {} objects: ", count),
+        Message::ObjectsOnPathHeader => "Additionally, detected objects include: ".to_string(),
+        Message::Object {
+            label,
+            distance,
+            direction,
+        } => format!(
+            "{} {}, {}, {}",
+            indefinite_article(label),
+            label,
+            self::distance(distance).to_lowercase(),
+            direction_phrase(direction)
+        ),
+        Message::ObjectGroup {
+            label,
+            count,
+            distance,
+            direction,
+        } => format!(
+            "{} {} {}, the closest is {}",
+            count_word(*count),
+            pluralize(label),
+            direction_phrase(direction),
+            self::distance(distance).to_lowercase()
+        ),
+        Message::LargeObjectUnderfoot { label, direction } => format!(
+            "a large {} covering the path underfoot, primarily in the {}",
+            label,
+            self::direction(direction)
+        ),
+        Message::ApproachingObject {
+            label,
+            direction,
+            quickly,
+        } => format!(
+            "Warning: {} {} approaching {}from {}",
+            indefinite_article(label),
+            label,
+            if *quickly { "quickly " } else { "" },
+            match direction {
+                DirectionCategory::Clock("12 o'clock") => "straight ahead",
+                DirectionCategory::Clock(clock) => clock,
+                DirectionCategory::Unknown => "an unknown direction",
+            }
+        ),
+
+        Message::NothingDetected { surface } => {
+            format!("No {} or related objects detected.", surface)
+        }
+        Message::SurfaceUnclear { surface } => format!(
+            "Could not clearly identify the {}. No specific objects described.",
+            surface
+        ),
+        Message::SurfaceClear { surface } => {
+            format!("Analysis complete for {}. Path appears clear.", surface)
+        }
+        Message::AnalysisFailed { surface } => format!(
+            "Analysis failed for {}: Invalid input data or no road features found.",
+            surface
+        ),
+        Message::NothingInScene => "Warning: Nothing could be found in current scene".to_string(),
+        Message::NoRoad => "Warning: No road detected".to_string(),
+    }
+}
+
+fn distance(distance: &DistanceCategory) -> String {
+    distance.to_string()
+}
+
+fn direction(direction: &DirectionCategory) -> String {
+    direction.to_string()
+}
+
+/// "ahead" for 12 o'clock, "at 10 o'clock" otherwise.
+fn direction_phrase(direction: &DirectionCategory) -> String {
+    match direction {
+        DirectionCategory::Clock("12 o'clock") => "ahead".to_string(),
+        DirectionCategory::Clock(clock) => format!("at {}", clock),
+        DirectionCategory::Unknown => "in an unknown direction".to_string(),
+    }
+}
+
+fn count_word(count: usize) -> String {
+    const WORDS: [&str; 11] = [
+        "no", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+    ];
+    WORDS
+        .get(count)
+        .map_or_else(|| count.to_string(), |word| word.to_string())
+}
+
+fn indefinite_article(label: &str) -> &'static str {
+    match label.chars().next().map(|c| c.to_ascii_lowercase()) {
+        Some('a' | 'e' | 'i' | 'o' | 'u') => "an",
+        _ => "a",
+    }
+}
+
+/// English plural of a class label, only the last word of "traffic light" and the like changes.
+fn pluralize(label: &str) -> String {
+    let (head, last) = match label.rsplit_once(' ') {
+        Some((head, last)) => (format!("{} ", head), last),
+        None => (String::new(), label),
+    };
+
+    let plural = match last {
+        "person" => "people".to_string(),
+        "man" => "men".to_string(),
+        "woman" => "women".to_string(),
+        "child" => "children".to_string(),
+        "mouse" => "mice".to_string(),
+        "sheep" | "deer" | "fish" | "skis" | "scissors" => last.to_string(),
+        _ if last.ends_with('s')
+            || last.ends_with('x')
+            || last.ends_with('z')
+            || last.ends_with("ch")
+            || last.ends_with("sh") =>
+        {
+            format!("{}es", last)
+        }
+        _ if last.ends_with('y')
+            && !last.ends_with("ay")
+            && !last.ends_with("ey")
+            && !last.ends_with("oy")
+            && !last.ends_with("uy") =>
+        {
+            format!("{}ies", &last[..last.len() - 1])
+        }
+        _ => format!("{}s", last),
+    };
+
+    head + &plural
+}
+
+#[test]
+fn test_pluralize_labels() {
+    assert_eq!(pluralize("person"), "people");
+    assert_eq!(pluralize("bus"), "buses");
+    assert_eq!(pluralize("traffic light"), "traffic lights");
+    assert_eq!(pluralize("fire hydrant"), "fire hydrants");
+    assert_eq!(pluralize("lorry"), "lorries");
+    assert_eq!(pluralize("toy"), "toys");
+    assert_eq!(count_word(3), "three");
+    assert_eq!(count_word(12), "12");
+    assert_eq!(indefinite_article("umbrella"), "an");
+}
@@ -0,0 +1,226 @@
+mod chinese;
+mod english;
+
+use crate::detect::property::direction::DirectionCategory;
+use crate::detect::property::distance::DistanceCategory;
+use crate::detect::property::obstacle::ObstacleKind;
+use serde::de::value::StrDeserializer;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Language the descriptions are written (and spoken) in, picked by the client per request.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Language {
+    #[default]
+    #[serde(rename = "en")]
+    English,
+    #[serde(rename = "zh", alias = "zh-CN", alias = "zh-Hans")]
+    Chinese,
+}
+
+/// Everything a describer can say. The catalogs of each language turn these into text,
+/// so the describers only decide *what* to say.
+#[derive(Debug, Clone)]
+pub enum Message<'a> {
+    // Road shape
+    ShapeStraight,
+    ShapeCurvesLeft,
+    ShapeCurvesRight,
+    ShapeUnclear,
+
+    // Obstacles on the centerline
+    NoObstacles,
+    Obstacle {
+        kind: ObstacleKind,
+        direction: &'a DirectionCategory,
+        distance: &'a DistanceCategory,
+    },
+    MoreObstacles {
+        count: usize,
+    },
+
+    // Sidewalk continuation and the user's position on it
+    PathEndsSoon,
+    NearLeftEdge,
+    NearRightEdge,
+    SidewalkStartsAhead {
+        distance: &'a DistanceCategory,
+        direction: &'a DirectionCategory,
+    },
+    OffSidewalk {
+        direction: &'a DirectionCategory,
+    },
+
+    // Where the road starts
+    StartsAtFeet {
+        surface: &'a str,
+    },
+    StartsAway {
+        surface: &'a str,
+        distance: &'a DistanceCategory,
+        direction: Option<&'a DirectionCategory>,
+    },
+
+    // Detected objects
+    ObjectsHeader {
+        count: usize,
+    },
+    ObjectsOnPathHeader,
+    Object {
+        label: &'a str,
+        distance: &'a DistanceCategory,
+        direction: &'a DirectionCategory,
+    },
+    ObjectGroup {
+        label: &'a str,
+        count: usize,
+        distance: &'a DistanceCategory,
+        direction: &'a DirectionCategory,
+    },
+    LargeObjectUnderfoot {
+        label: &'a str,
+        direction: &'a DirectionCategory,
+    },
+    ApproachingObject {
+        label: &'a str,
+        direction: &'a DirectionCategory,
+        /// Close to colliding, said with more urgency.
+        quickly: bool,
+    },
+
+    // Fallbacks of the composition and the pipeline
+    NothingDetected {
+        surface: &'a str,
+    },
+    SurfaceUnclear {
+        surface: &'a str,
+    },
+    SurfaceClear {
+        surface: &'a str,
+    },
+    AnalysisFailed {
+        surface: &'a str,
+    },
+    NothingInScene,
+    NoRoad,
+}
+
+impl Language {
+    pub fn text(&self, message: &Message) -> String {
+        match self {
+            Language::English => english::text(message),
+            Language::Chinese => chinese::text(message),
+        }
+    }
+
+    /// Joins the outputs of the individual describers of one surface.
+    pub fn join_parts(&self, parts: &[&str]) -> String {
+        match self {
+            Language::English => parts.join(" "),
+            Language::Chinese => parts.join("，"),
+        }
+    }
+
+    /// Separator between several messages of one describer.
+    pub fn sentence_separator(&self) -> &'static str {
+        match self {
+            Language::English => ". ",
+            Language::Chinese => "。",
+        }
+    }
+
+    /// Separator between the items of a list, e.g. the detected objects.
+    pub fn list_separator(&self) -> &'static str {
+        match self {
+            Language::English => "; ",
+            Language::Chinese => "；",
+        }
+    }
+
+    /// Separator between the descriptions of the surfaces of one scene.
+    pub fn surface_separator(&self) -> &'static str {
+        match self {
+            Language::English => ", ",
+            Language::Chinese => "；",
+        }
+    }
+
+    /// Capitalizes the sentence where the language has cases and makes sure it ends with a full stop.
+    pub fn finish_sentence(&self, sentence: &str) -> String {
+        let mut sentence = match self {
+            Language::English => {
+                let mut c = sentence.chars();
+                match c.next() {
+                    Some(f) => f.to_uppercase().collect::<String>() + c.as_str(),
+                    None => String::new(),
+                }
+            }
+            Language::Chinese => sentence.to_string(),
+        };
+
+        let full_stop = match self {
+            Language::English => '.',
+            Language::Chinese => '。',
+        };
+        if !sentence.ends_with(full_stop) {
+            sentence.push(full_stop);
+        }
+
+        sentence
+    }
+}
+
+impl FromStr for Language {
+    type Err = anyhow::Error;
+
+    /// Accepts the same codes as the `lang` query parameter.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Language::deserialize(StrDeserializer::<serde::de::value::Error>::new(s))
+            .map_err(|_| anyhow::anyhow!("Unknown language '{}', expected en or zh", s))
+    }
+}
+
+#[test]
+fn test_messages_are_localized() {
+    let distance = DistanceCategory::Near;
+    let direction = DirectionCategory::Clock("11 o'clock");
+    let message = Message::ObjectGroup {
+        label: "person",
+        count: 3,
+        distance: &distance,
+        direction: &direction,
+    };
+
+    assert_eq!(
+        Language::English.text(&message),
+        "three people at 11 o'clock, the closest is near"
+    );
+    assert_eq!(
+        Language::Chinese.text(&message),
+        "11点钟方向有三个行人，最近的不远"
+    );
+    let direction = DirectionCategory::Clock("2 o'clock");
+    let message = Message::ApproachingObject {
+        label: "bicycle",
+        direction: &direction,
+        quickly: true,
+    };
+    assert_eq!(
+        Language::English.text(&message),
+        "Warning: a bicycle approaching quickly from 2 o'clock"
+    );
+    assert_eq!(
+        Language::Chinese.text(&message),
+        "注意：2点钟方向有自行车正在快速靠近"
+    );
+    assert_eq!(
+        Language::Chinese.finish_sentence("人行道从你脚下开始"),
+        "人行道从你脚下开始。"
+    );
+    assert_eq!(
+        Language::English.finish_sentence("it proceeds straight"),
+        "It proceeds straight."
+    );
+    assert_eq!("zh-CN".parse::<Language>().unwrap(), Language::Chinese);
+    assert!("fr".parse::<Language>().is_err());
+}
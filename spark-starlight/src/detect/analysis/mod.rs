@@ -1,4 +1,6 @@
+mod approaching_object_describer;
 pub mod compose;
+pub mod locale;
 mod object_detected_describer;
 mod obstacle_describer;
 mod path_ending_describer;
@@ -8,6 +10,9 @@ mod user_position_describer;
 #[macro_use]
 mod dispatch_macro;
 
+use crate::config::AnalysisConfig;
+use crate::detect::analysis::approaching_object_describer::ApproachingObjectDescriber;
+use crate::detect::analysis::locale::Language;
 use crate::detect::analysis::object_detected_describer::DetectedObjectDescriber;
 use crate::detect::analysis::obstacle_describer::ObstacleDescriber;
 use crate::detect::analysis::path_ending_describer::PathEndingDescriber;
@@ -19,11 +24,34 @@ use crate::detect::property::analyse_result::RoadAnalysisData;
 /// Trait for generating a specific *part* of the textual description based on analysis results.
 pub trait Describer {
     /// Generates a specific part of the description based on the analysis data.
+    /// Thresholds are taken from the runtime `AnalysisConfig`, the text is written in `language`.
     /// Returns Some(description_part) if relevant, None otherwise.
-    async fn describe(&self, data: &RoadAnalysisData, object_type_name: &str) -> Option<String>;
+    async fn describe(
+        &self,
+        data: &RoadAnalysisData,
+        object_type_name: &str,
+        config: &AnalysisConfig,
+        language: Language,
+    ) -> Option<String>;
+
+    /// How urgent the part is when it is a warning, higher means more urgent.
+    /// Informational parts return None, sessions never mute those.
+    fn severity(&self, _data: &RoadAnalysisData, _config: &AnalysisConfig) -> Option<u8> {
+        None
+    }
+}
+
+/// One describer's contribution to the final sentence.
+#[derive(Debug, Clone)]
+pub struct DescriptionPart {
+    /// Name of the describer that produced the text, e.g. "Obstacle".
+    pub source: &'static str,
+    pub text: String,
+    pub severity: Option<u8>,
 }
 
 define_describer![
+    Approaching => ApproachingObjectDescriber,
     ObjectDetected => DetectedObjectDescriber,
     Obstacle => ObstacleDescriber,
     PathEnding => PathEndingDescriber,
@@ -1,11 +1,10 @@
+use crate::config::AnalysisConfig;
+use crate::detect::analysis::locale::{Language, Message};
 use crate::detect::analysis::Describer;
-use crate::detect::constants::{
-    NEAR_OBJECT_HEIGHT_THRESHOLD_FACTOR, NEAR_OBJECT_WIDTH_THRESHOLD_FACTOR,
-};
 use crate::detect::property::analyse_result::RoadAnalysisData;
 use crate::detect::property::direction::DirectionCategory;
 use crate::detect::property::distance::DistanceCategory;
-use std::fmt::Write;
+use spark_inference::inference::yolo::inference_yolo_detect::YoloDetectResult;
 
 #[derive(Debug, Copy, Clone)]
 pub struct DetectedObjectDescriber;
@@ -13,8 +12,10 @@ pub struct DetectedObjectDescriber;
 impl Describer for DetectedObjectDescriber {
     async fn describe(
         &self,
-        data: &RoadAnalysisData<'_>,
+        data: &RoadAnalysisData,
         _object_type_name: &str,
+        config: &AnalysisConfig,
+        language: Language,
     ) -> Option<String> {
         if data.detect_results.is_empty() {
             // Only add "No specific objects detected" if the centerline also failed.
@@ -34,66 +35,76 @@ impl Describer for DetectedObjectDescriber {
 
         if data.center_lines.is_empty() {
             // If no road context, just list objects
-            write!(
-                description,
-                "Detected {} objects: ",
-                data.detect_results.len()
-            )
-            .unwrap();
+            description.push_str(&language.text(&Message::ObjectsHeader {
+                count: data.detect_results.len(),
+            }));
         } else {
             // If road context exists, phrase it as objects *on* or *near* the road
-            write!(description, "Additionally, detected objects include: ").unwrap();
+            description.push_str(&language.text(&Message::ObjectsOnPathHeader));
         }
 
-        for (i, result) in data.detect_results.iter().enumerate() {
+        // Group detections by class, nearest group first, so repeated classes are announced once
+        let mut groups: Vec<(&str, Vec<&YoloDetectResult>)> = Vec::new();
+        for result in data.detect_results.iter() {
+            match groups.iter_mut().find(|(label, _)| *label == result.label) {
+                Some((_, members)) => members.push(result),
+                None => groups.push((result.label.as_str(), vec![result])),
+            }
+        }
+        for (_, members) in groups.iter_mut() {
+            // Higher y is closer to the viewer
+            members.sort_by(|a, b| b.y.total_cmp(&a.y));
+        }
+        groups.sort_by(|(_, a), (_, b)| b[0].y.total_cmp(&a[0].y));
+
+        for (label, members) in groups {
             // Calculate perspective-corrected distance and direction relative to image bottom-center
-            let distance = DistanceCategory::get_distance(result.y, data.image_height);
+            let nearest = members[0];
+            let distance = DistanceCategory::of_detection(
+                nearest,
+                data.depth.as_deref(),
+                data.image_height,
+                config,
+            );
             let direction = DirectionCategory::get_direction(
-                result.x,
-                result.y,
+                nearest.x,
+                nearest.y,
                 None, // Relative to bottom-center origin
                 data.image_width,
                 data.image_height,
             );
 
-            let object_desc: String;
-
-            // Perspective Adjustment for "Very Near" Objects
-            if distance == DistanceCategory::VeryNear {
-                let box_height_ratio = result.height / data.image_height as f32;
-                let box_width_ratio = result.width / data.image_width as f32;
-
-                if box_height_ratio >= NEAR_OBJECT_HEIGHT_THRESHOLD_FACTOR
-                    && box_width_ratio >= NEAR_OBJECT_WIDTH_THRESHOLD_FACTOR
-                {
-                    // Special description for large, close objects that might be the road surface itself
-                    // Using a generic term might be safer than assuming it's `object_type_name`
-                    object_desc = format!(
-                        "a large surface covering the path underfoot, primarily in the {}",
-                        direction
-                    );
-                    // Avoid numbering if it's likely the main surface
-                } else {
-                    // Standard description for objects that are "Very near" but not huge
-                    object_desc = format!(
-                        "Item {} ({}, {})",
-                        i + 1,
-                        distance, // "Very near"
-                        direction
-                    );
-                }
+            let object_desc = if members.len() > 1 {
+                // e.g. "three people ahead, the closest is near"
+                language.text(&Message::ObjectGroup {
+                    label,
+                    count: members.len(),
+                    distance: &distance,
+                    direction: &direction,
+                })
+            } else if distance == DistanceCategory::VeryNear
+                && nearest.height / data.image_height as f32
+                    >= config.near_object_height_threshold_factor
+                && nearest.width / data.image_width as f32
+                    >= config.near_object_width_threshold_factor
+            {
+                // Perspective Adjustment for "Very Near" Objects:
+                // Special description for large, close objects that might be the road surface itself
+                language.text(&Message::LargeObjectUnderfoot {
+                    label,
+                    direction: &direction,
+                })
             } else {
-                // Standard description for objects not classified as "Very near"
-                object_desc = format!(
-                    "Item {} ({}, {})",
-                    i + 1,
-                    distance, // Near, Far, etc.
-                    direction
-                );
-            }
+                // e.g. "a car, near, at 11 o'clock"
+                language.text(&Message::Object {
+                    label,
+                    distance: &distance,
+                    direction: &direction,
+                })
+            };
             object_descs.push(object_desc);
         }
-        write!(description, "{}", object_descs.join("; ")).unwrap(); // Join with semicolon
+        description.push_str(&object_descs.join(language.list_separator()));
 
         Some(description)
     }
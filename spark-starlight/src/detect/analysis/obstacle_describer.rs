@@ -1,3 +1,5 @@
+use crate::config::AnalysisConfig;
+use crate::detect::analysis::locale::{Language, Message};
 use crate::detect::analysis::Describer;
 use crate::detect::property::analyse_result::RoadAnalysisData;
 use crate::detect::property::distance::DistanceCategory;
@@ -9,8 +11,10 @@ pub struct ObstacleDescriber;
 impl Describer for ObstacleDescriber {
     async fn describe(
         &self,
-        data: &RoadAnalysisData<'_>,
+        data: &RoadAnalysisData,
         _object_type_name: &str,
+        config: &AnalysisConfig,
+        language: Language,
     ) -> Option<String> {
         // Only describe centerline-derived obstacles if a centerline exists.
         if data.center_lines.is_empty() || data.obstacles.is_empty() {
@@ -19,7 +23,7 @@ impl Describer for ObstacleDescriber {
             // The CompositeDescriber could add a default "no obstacles" message if needed.
             // Let's return a positive confirmation if centerline exists but no obstacles were found on it.
             if !data.center_lines.is_empty() {
-                return Some("No immediate obstacles detected on the path".to_string());
+                return Some(language.text(&Message::NoObstacles));
             } else {
                 return None; // No centerline, so no centerline obstacles to describe.
             }
@@ -28,27 +32,55 @@ impl Describer for ObstacleDescriber {
         let mut description = String::new();
         // Describe the nearest obstacle first
         let first_obstacle = &data.obstacles[0];
-        let obstacle_distance =
-            DistanceCategory::get_distance(first_obstacle.y as f32, data.image_height); // Use perspective distance
+        let obstacle_distance = DistanceCategory::at(
+            first_obstacle.center_x,
+            first_obstacle.y as f32,
+            data.depth.as_deref(),
+            data.image_height,
+            config,
+        ); // Measured in the depth map, or perspective distance without one
 
-        // Reason already includes direction relative to road start from center_line.rs
+        // Direction is relative to road start, see center_line.rs
+        // e.g., "Obstacle (Potential gap near 11 o'clock direction) detected, Near"
         write!(
             description,
-            "Obstacle ({}) detected, {}",
-            first_obstacle.reason, // e.g., "Potential gap near 11 o'clock direction"
-            obstacle_distance      // e.g., "Near"
+            "{}",
+            language.text(&Message::Obstacle {
+                kind: first_obstacle.kind,
+                direction: &first_obstacle.direction,
+                distance: &obstacle_distance,
+            })
         )
         .unwrap();
 
         if data.obstacles.len() > 1 {
             write!(
                 description,
-                ", with {} other potential obstacles further down",
-                data.obstacles.len() - 1
+                "{}",
+                language.text(&Message::MoreObstacles {
+                    count: data.obstacles.len() - 1
+                })
             )
             .unwrap();
         }
 
         Some(description)
     }
+
+    fn severity(&self, data: &RoadAnalysisData, config: &AnalysisConfig) -> Option<u8> {
+        // Nearer obstacles are more urgent; "No immediate obstacles" is not a warning.
+        if data.center_lines.is_empty() {
+            return None;
+        }
+        data.obstacles.first().map(|obstacle| {
+            DistanceCategory::at(
+                obstacle.center_x,
+                obstacle.y as f32,
+                data.depth.as_deref(),
+                data.image_height,
+                config,
+            )
+            .urgency()
+        })
+    }
 }
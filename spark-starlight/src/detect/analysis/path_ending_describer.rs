@@ -1,10 +1,9 @@
+use crate::config::AnalysisConfig;
+use crate::detect::analysis::locale::{Language, Message};
 use crate::detect::analysis::Describer;
-use crate::detect::constants::{
-    CLOSE_GAP_Y_THRESHOLD_FACTOR, FAR_POINT_Y_THRESHOLD_FACTOR, MIN_POINTS_FOR_CONTINUATION,
-};
 use crate::detect::property::analyse_result::RoadAnalysisData;
 use crate::detect::property::distance::DistanceCategory;
-use std::fmt::Write;
+use crate::detect::property::obstacle::ObstacleKind;
 
 #[derive(Debug, Copy, Clone)]
 pub struct PathEndingDescriber;
@@ -12,8 +11,10 @@ pub struct PathEndingDescriber;
 impl Describer for PathEndingDescriber {
     async fn describe(
         &self,
-        data: &RoadAnalysisData<'_>,
+        data: &RoadAnalysisData,
         object_type_name: &str,
+        config: &AnalysisConfig,
+        language: Language,
     ) -> Option<String> {
         // This warning is primarily for sidewalks that start at the user's feet.
         if object_type_name != "Sidewalk" || !data.starts_at_feet || data.center_lines.is_empty() {
@@ -22,8 +23,8 @@ impl Describer for PathEndingDescriber {
 
         let farthest_point = data.center_lines.last().unwrap(); // Safe due to !is_empty check
 
-        let end_threshold_y = data.image_height as f32 * FAR_POINT_Y_THRESHOLD_FACTOR;
-        let close_gap_threshold_y = data.image_height as f32 * CLOSE_GAP_Y_THRESHOLD_FACTOR;
+        let end_threshold_y = data.image_height as f32 * config.far_point_y_threshold_factor;
+        let close_gap_threshold_y = data.image_height as f32 * config.close_gap_y_threshold_factor;
 
         let mut sidewalk_ends_soon = false;
 
@@ -36,8 +37,8 @@ impl Describer for PathEndingDescriber {
         }
 
         // Check Condition 2: Centerline is very short
-        if !sidewalk_ends_soon && data.center_lines.len() < MIN_POINTS_FOR_CONTINUATION {
-            // log::debug!("Path ending check 2: Centerline length ({}) < min points ({})", data.center_lines.len(), MIN_POINTS_FOR_CONTINUATION);
+        if !sidewalk_ends_soon && data.center_lines.len() < config.min_points_for_continuation {
+            // log::debug!("Path ending check 2: Centerline length ({}) < min points ({})", data.center_lines.len(), config.min_points_for_continuation);
             sidewalk_ends_soon = true;
         }
 
@@ -45,7 +46,7 @@ impl Describer for PathEndingDescriber {
         if !sidewalk_ends_soon {
             if let Some(first_obstacle) = data.obstacles.first() {
                 // Check if the obstacle is a gap AND its y-coordinate is "close" (greater than the close gap threshold)
-                if first_obstacle.reason.contains("gap")
+                if first_obstacle.kind == ObstacleKind::Gap
                     && (first_obstacle.y as f32) > close_gap_threshold_y
                 {
                     // log::debug!("Path ending check 3: Close gap obstacle detected at y ({}) > threshold y ({})", first_obstacle.y, close_gap_threshold_y);
@@ -58,22 +59,26 @@ impl Describer for PathEndingDescriber {
             // Avoid redundant warnings: Check if the *first* reported obstacle is already very close.
             // The ObstacleDescriber would have already given a strong warning.
             let already_warned_by_close_obstacle = data.obstacles.first().map_or(false, |obs| {
-                let obs_dist = DistanceCategory::get_distance(obs.y as f32, data.image_height);
+                let obs_dist = DistanceCategory::at(
+                    obs.center_x,
+                    obs.y as f32,
+                    data.depth.as_deref(),
+                    data.image_height,
+                    config,
+                );
                 obs_dist == DistanceCategory::VeryNear
                     || obs_dist == DistanceCategory::RelativelyNear
             });
 
             if !already_warned_by_close_obstacle {
-                let mut description = String::new();
-                write!(
-                    description,
-                    "Warning: The sidewalk path ahead appears short or obstructed soon"
-                )
-                .unwrap();
-                return Some(description);
+                return Some(language.text(&Message::PathEndsSoon));
             }
         }
 
         None // No "ending soon" warning needed
     }
+
+    fn severity(&self, _data: &RoadAnalysisData, _config: &AnalysisConfig) -> Option<u8> {
+        Some(1)
+    }
 }
@@ -1,7 +1,8 @@
+use crate::config::AnalysisConfig;
+use crate::detect::analysis::locale::{Language, Message};
 use crate::detect::analysis::Describer;
 use crate::detect::property::analyse_result::RoadAnalysisData;
 use crate::detect::property::road_shape::RoadShape;
-use std::fmt::Write;
 
 #[derive(Debug, Copy, Clone)]
 pub struct RoadShapeDescriber;
@@ -9,20 +10,21 @@ pub struct RoadShapeDescriber;
 impl Describer for RoadShapeDescriber {
     async fn describe(
         &self,
-        data: &RoadAnalysisData<'_>,
+        data: &RoadAnalysisData,
         _object_type_name: &str,
+        _config: &AnalysisConfig,
+        language: Language,
     ) -> Option<String> {
         if data.center_lines.is_empty() {
             return None;
         }
 
-        let mut description = String::new();
-        match data.shape {
-            RoadShape::Straight => write!(description, "It proceeds straight").unwrap(),
-            RoadShape::CurvesLeft => write!(description, "It curves left").unwrap(),
-            RoadShape::CurvesRight => write!(description, "It curves right").unwrap(),
-            RoadShape::Undetermined => write!(description, "Its shape is unclear").unwrap(),
-        }
-        Some(description)
+        let message = match data.shape {
+            RoadShape::Straight => Message::ShapeStraight,
+            RoadShape::CurvesLeft => Message::ShapeCurvesLeft,
+            RoadShape::CurvesRight => Message::ShapeCurvesRight,
+            RoadShape::Undetermined => Message::ShapeUnclear,
+        };
+        Some(language.text(&message))
     }
 }
@@ -1,3 +1,5 @@
+use crate::config::AnalysisConfig;
+use crate::detect::analysis::locale::{Language, Message};
 use crate::detect::analysis::Describer;
 use crate::detect::property::analyse_result::RoadAnalysisData;
 use crate::detect::property::distance::DistanceCategory;
@@ -9,8 +11,10 @@ pub struct RoadStartDescriber;
 impl Describer for RoadStartDescriber {
     async fn describe(
         &self,
-        data: &RoadAnalysisData<'_>,
+        data: &RoadAnalysisData,
         object_type_name: &str,
+        config: &AnalysisConfig,
+        language: Language,
     ) -> Option<String> {
         let mut description = String::new();
 
@@ -18,10 +22,9 @@ impl Describer for RoadStartDescriber {
             // If centerline failed but objects were detected, CompositeDescriber might still call other describers.
             // Let's provide a specific message here if *nothing* at all was found.
             if data.detect_results.is_empty() {
-                return Some(format!(
-                    "No {} or related objects detected.",
-                    object_type_name
-                ));
+                return Some(language.text(&Message::NothingDetected {
+                    surface: object_type_name,
+                }));
             } else {
                 // Let the object describer handle the detected objects.
                 // Optionally, add a note about the missing road context.
@@ -32,26 +35,32 @@ impl Describer for RoadStartDescriber {
         } else if data.starts_at_feet {
             write!(
                 description,
-                "The {} starts at your feet.", // Removed trailing space, will be added by composer
-                object_type_name
+                "{}",
+                language.text(&Message::StartsAtFeet {
+                    surface: object_type_name,
+                })
             )
             .unwrap();
             Some(description)
         } else {
             // Use perspective-corrected distance for the warning message
             if let Some(nearest_point) = data.center_lines.first() {
-                let start_distance =
-                    DistanceCategory::get_distance(nearest_point.y as f32, data.image_height);
-                let direction_str = match &data.start_direction {
-                    Some(dir) => format!("{}", dir),
-                    None => "centered".to_string(), // Should have direction if not starts_at_feet
-                };
+                let start_distance = DistanceCategory::at(
+                    nearest_point.center_x,
+                    nearest_point.y as f32,
+                    data.depth.as_deref(),
+                    data.image_height,
+                    config,
+                );
+                // Should have direction if not starts_at_feet, the catalogs fall back to "centered"
                 write!(
                     description,
-                    "Warning: The {} doesn't start directly underfoot. It appears {} in the {}.",
-                    object_type_name,
-                    start_distance, // e.g., "Relatively near", "Near"
-                    direction_str
+                    "{}",
+                    language.text(&Message::StartsAway {
+                        surface: object_type_name,
+                        distance: &start_distance, // e.g., "Relatively near", "Near"
+                        direction: data.start_direction.as_ref(),
+                    })
                 )
                 .unwrap();
                 Some(description)
@@ -61,4 +70,9 @@ impl Describer for RoadStartDescriber {
             }
         }
     }
+
+    fn severity(&self, data: &RoadAnalysisData, _config: &AnalysisConfig) -> Option<u8> {
+        // Only the "doesn't start directly underfoot" message is a warning.
+        (!data.center_lines.is_empty() && !data.starts_at_feet).then_some(1)
+    }
 }
@@ -1,7 +1,6 @@
+use crate::config::AnalysisConfig;
+use crate::detect::analysis::locale::{Language, Message};
 use crate::detect::analysis::Describer;
-use crate::detect::constants::{
-    EDGE_PROXIMITY_THRESHOLD_FACTOR, MIN_SIDEWALK_WIDTH_FACTOR_FOR_EDGE_WARNING,
-};
 use crate::detect::property::analyse_result::RoadAnalysisData;
 use crate::detect::property::direction::DirectionCategory;
 use crate::detect::property::distance::DistanceCategory;
@@ -14,8 +13,10 @@ pub struct UserPositionDescriber;
 impl Describer for UserPositionDescriber {
     async fn describe(
         &self,
-        data: &RoadAnalysisData<'_>,
+        data: &RoadAnalysisData,
         object_type_name: &str,
+        config: &AnalysisConfig,
+        language: Language,
     ) -> Option<String> {
         // This logic is primarily for sidewalks and requires a centerline.
         // Only run if the object is explicitly a "Sidewalk".
@@ -55,22 +56,18 @@ impl Describer for UserPositionDescriber {
 
             // Avoid edge warnings on very narrow sidewalks
             let min_width_for_warning =
-                data.image_width as f32 * MIN_SIDEWALK_WIDTH_FACTOR_FOR_EDGE_WARNING;
+                data.image_width as f32 * config.min_sidewalk_width_factor_for_edge_warning;
             if nearest_sidewalk_point.width as f32 >= min_width_for_warning && half_width > 1.0 {
                 // Check half_width > 0 avoids division by zero if width is tiny
                 let distance_from_center = (user_x - nearest_sidewalk_point.center_x).abs();
-                let edge_proximity_threshold = half_width * EDGE_PROXIMITY_THRESHOLD_FACTOR;
+                let edge_proximity_threshold = half_width * config.edge_proximity_threshold_factor;
 
                 if distance_from_center >= edge_proximity_threshold {
                     // User is near an edge
                     if user_x < nearest_sidewalk_point.center_x {
-                        messages.push(
-                            "Warning: You are near the left edge of the sidewalk".to_string(),
-                        );
+                        messages.push(language.text(&Message::NearLeftEdge));
                     } else {
-                        messages.push(
-                            "Warning: You are near the right edge of the sidewalk".to_string(),
-                        );
+                        messages.push(language.text(&Message::NearRightEdge));
                     }
                 } else {
                     // User is relatively centered, no message needed unless explicitly desired.
@@ -94,9 +91,12 @@ impl Describer for UserPositionDescriber {
                 data.image_width,
                 data.image_height,
             );
-            let distance_to_sidewalk = DistanceCategory::get_distance(
+            let distance_to_sidewalk = DistanceCategory::at(
+                nearest_sidewalk_point.center_x,
                 nearest_sidewalk_point.y as f32, // Use sidewalk's Y for distance perception
+                data.depth.as_deref(),
                 data.image_height,
+                config,
             );
 
             // Refine message based on whether it starts at feet but user is off horizontally,
@@ -108,11 +108,10 @@ impl Describer for UserPositionDescriber {
                 // Let's assume RoadStartDescriber handles the vertical distance warning.
                 // We can still add guidance if the user is *also* horizontally off.
                 if !user_is_horizontally_on_sidewalk {
-                    messages.push(format!(
-                        "The sidewalk starts {} ahead and is in the {}",
-                        distance_to_sidewalk,  // e.g., "Near"
-                        direction_to_sidewalk  // e.g., "11 o'clock direction"
-                    ));
+                    messages.push(language.text(&Message::SidewalkStartsAhead {
+                        distance: &distance_to_sidewalk,   // e.g., "Near"
+                        direction: &direction_to_sidewalk, // e.g., "11 o'clock direction"
+                    }));
                 } else {
                     // Starts ahead, but user *would* be horizontally aligned if they moved forward.
                     // RoadStartDescriber likely covers this.
@@ -120,10 +119,9 @@ impl Describer for UserPositionDescriber {
                 }
             } else {
                 // Starts at feet vertically, but user is horizontally off.
-                messages.push(format!(
-                    "Warning: You appear to be off the sidewalk. The sidewalk is in the {}",
-                    direction_to_sidewalk
-                ));
+                messages.push(language.text(&Message::OffSidewalk {
+                    direction: &direction_to_sidewalk,
+                }));
             }
         }
 
@@ -132,8 +130,17 @@ impl Describer for UserPositionDescriber {
         } else {
             // Combine messages if multiple warnings were generated (unlikely with current logic, but good practice)
             let mut description = String::new();
-            write!(description, "{}", messages.join(". ")).unwrap();
+            write!(
+                description,
+                "{}",
+                messages.join(language.sentence_separator())
+            )
+            .unwrap();
             Some(description)
         }
     }
+
+    fn severity(&self, _data: &RoadAnalysisData, _config: &AnalysisConfig) -> Option<u8> {
+        Some(1)
+    }
 }
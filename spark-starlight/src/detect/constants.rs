@@ -1,3 +1,4 @@
+// Default values of `AnalysisConfig`, a config file or environment overrides take precedence at runtime.
 pub(crate) const NUM_VERTICAL_SAMPLES: u32 = 20;
 pub(crate) const MIN_MASK_WIDTH_FOR_CENTER: u32 = 5;
 pub(crate) const ROAD_START_Y_THRESHOLD: f32 = 0.90; // Normalized Y threshold for "at feet"
@@ -34,3 +35,33 @@ pub(crate) const CLOSE_GAP_Y_THRESHOLD_FACTOR: f32 = 0.50;
 pub(crate) const EDGE_PROXIMITY_THRESHOLD_FACTOR: f32 = 0.7;
 /// Minimum sidewalk width (as fraction of image width) to apply edge warnings. Avoids warnings on very narrow paths.
 pub(crate) const MIN_SIDEWALK_WIDTH_FACTOR_FOR_EDGE_WARNING: f32 = 0.05;
+
+// --- Mask Cleanup Constants ---
+/// Radius in mask pixels of the closing that bridges small gaps and notches in SAM masks, 0 skips it.
+pub(crate) const MASK_CLOSE_RADIUS: u32 = 4;
+/// Radius in mask pixels of the opening that removes speckles and thin spurs, 0 skips it.
+pub(crate) const MASK_OPEN_RADIUS: u32 = 2;
+/// Whether holes, e.g. left by objects standing on the road, are filled before the centerline is extracted.
+pub(crate) const FILL_MASK_HOLES: bool = true;
+/// Whether only the largest connected region of a mask is analysed.
+pub(crate) const KEEP_LARGEST_MASK_COMPONENT: bool = true;
+
+// --- Metric Distance Constants ---
+// Used instead of the perspective guess when a depth model estimates how far things are.
+/// Meters up to which a point is "very near".
+pub(crate) const VERY_NEAR_METERS: f32 = 3.0;
+/// Meters up to which a point is "relatively near".
+pub(crate) const RELATIVELY_NEAR_METERS: f32 = 6.0;
+/// Meters up to which a point is "near", anything farther is "far".
+pub(crate) const NEAR_METERS: f32 = 12.0;
+
+// --- Approaching Object Constants ---
+/// Seconds to collision below which an approaching object is announced.
+pub(crate) const COLLISION_WARNING_SECS: f32 = 4.0;
+/// Seconds to collision below which the warning says the object approaches quickly.
+pub(crate) const COLLISION_URGENT_SECS: f32 = 2.0;
+/// Relative growth of a box per second below which an object is not considered approaching.
+/// Keeps boxes that only jitter from producing huge but meaningless times to collision.
+pub(crate) const APPROACH_MIN_GROWTH: f32 = 0.05;
+/// Normalized Y of the horizon, where the ground seen by a level camera meets the sky.
+pub(crate) const HORIZON_Y_FACTOR: f32 = 0.5;
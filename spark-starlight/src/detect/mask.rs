@@ -1,10 +1,14 @@
+use crate::config::AnalysisConfig;
 use crate::detect::analysis::compose::CompositeDescriber;
-use crate::detect::property::analyse_result::RoadAnalysisData;
+use crate::detect::analysis::locale::{Language, Message};
+use crate::detect::property::analyse_result::{RoadAnalysisData, SurfaceAnalysis};
 use crate::detect::property::center_line::CenterLines;
 use crate::detect::property::road_shape::RoadShape;
+use crate::session::smoothing::Session;
 use bitvec::prelude::BitVec;
 use log::{error, info};
 use spark_inference::inference::yolo::inference_yolo_detect::YoloDetectResult;
+use spark_inference::utils::masks::geometry::MaskGeometry;
 
 pub async fn analyze_road_mask(
     mask: &BitVec,
@@ -12,25 +16,70 @@ pub async fn analyze_road_mask(
     image_width: u32,
     image_height: u32,
     object_type_name: &str,
-) -> String {
-    let analysis_data_option = perform_core_analysis(mask, detections, image_width, image_height);
+    config: &AnalysisConfig,
+    language: Language,
+) -> SurfaceAnalysis {
+    let analysis_data_option =
+        perform_core_analysis(mask, detections, image_width, image_height, config);
+    describe_road_analysis(
+        analysis_data_option,
+        object_type_name,
+        config,
+        language,
+        None,
+    )
+    .await
+}
+
+/// Turns the core analysis of one surface into its description.
+/// With a session, the analysis is first smoothed over the client's recent frames
+/// and warnings the client just heard are left out of the description.
+pub async fn describe_road_analysis(
+    analysis_data_option: Option<RoadAnalysisData>,
+    object_type_name: &str,
+    config: &AnalysisConfig,
+    language: Language,
+    mut session: Option<&mut Session>,
+) -> SurfaceAnalysis {
+    let analysis_data_option = match (analysis_data_option, session.as_deref_mut()) {
+        (Some(data), Some(session)) => Some(session.smooth(object_type_name, data)),
+        (data, _) => data,
+    };
 
-    let description = match analysis_data_option {
+    let description = match &analysis_data_option {
         Some(data) => {
             // Use the CompositeDescriber to generate the final text
             let composite_describer = CompositeDescriber::new();
-            composite_describer.describe(&data, object_type_name).await // Call the composite describe method
+            let parts = composite_describer
+                .describe_parts(data, object_type_name, config, language)
+                .await;
+
+            match session {
+                Some(session) if !parts.is_empty() => {
+                    let parts = session.filter_warnings(object_type_name, parts);
+                    if parts.is_empty() {
+                        // Everything left to say was just said, stay silent for this surface
+                        String::new()
+                    } else {
+                        composite_describer.compose(&parts, data, object_type_name, language)
+                    }
+                }
+                _ => composite_describer.compose(&parts, data, object_type_name, language),
+            }
         }
         None => {
             // Core analysis failed, provide a generic failure message
-            format!(
-                "Analysis failed for {}: Invalid input data or no road features found.",
-                object_type_name
-            )
+            language.text(&Message::AnalysisFailed {
+                surface: object_type_name,
+            })
         }
     };
 
-    description
+    SurfaceAnalysis {
+        surface: object_type_name.to_string(),
+        analysis: analysis_data_option,
+        description,
+    }
 }
 
 // --- Move perform_core_analysis here if not in analysis/mod.rs ---
@@ -39,16 +88,17 @@ pub async fn analyze_road_mask(
 ///    mask (BitVec): The road mask to analyze.
 ///    image_width (u32): The width of the image frame.
 ///    image_height (u32): The height of the image frame.
-///    object_type_name (str): The name of the object type (e.g., "Highway", "Sidewalk").
+///    config (AnalysisConfig): The thresholds used by the centerline analysis.
 ///
 /// Returns:
 ///   Option<RoadAnalysisData>: A struct containing analysis results or None if basic checks fail.
-fn perform_core_analysis<'a>(
-    mask: &'a BitVec,
-    detections: &'a [YoloDetectResult],
+pub fn perform_core_analysis(
+    mask: &BitVec,
+    detections: &[YoloDetectResult],
     image_width: u32,
     image_height: u32,
-) -> Option<RoadAnalysisData<'a>> {
+    config: &AnalysisConfig,
+) -> Option<RoadAnalysisData> {
     if image_width == 0
         || image_height == 0
         || mask.is_empty()
@@ -58,9 +108,10 @@ fn perform_core_analysis<'a>(
         return None;
     }
 
-    // 1. Calculate Centerline
+    // 1. Clean the mask, then calculate Centerline
     // Make sure CenterLines and its methods are accessible (e.g., pub in property module)
-    let center_lines = CenterLines::extract_center_line(mask, image_width, image_height);
+    let mask = clean_mask(mask, image_width, config);
+    let center_lines = CenterLines::extract_center_line(&mask, image_width, image_height, config);
 
     // If centerline extraction fails completely, we might still have detected objects.
     // Return a RoadAnalysisData with centerline-dependent fields empty/defaulted.
@@ -70,41 +121,65 @@ fn perform_core_analysis<'a>(
         return Some(RoadAnalysisData {
             image_width,
             image_height,
-            detect_results: detections,
+            detect_results: detections.to_vec(),
             shape: RoadShape::Undetermined, // No centerline, shape unknown
             obstacles: vec![],              // No centerline, no obstacles derived from it
             center_lines,                   // Empty centerline vector
             starts_at_feet: false,          // Cannot start at feet without centerline
             start_direction: None,
+            tracks: vec![],
+            depth: None,
         });
     }
 
     // 2. Analyze Shape
-    let shape = center_lines.analyze_center_line_shape(image_width);
+    let shape = center_lines.analyze_center_line_shape(image_width, config);
 
     // 3. Detect Obstacles
-    let obstacles = center_lines.detect_obstacles(image_width, image_height);
+    let obstacles = center_lines.detect_obstacles(image_width, image_height, config);
 
     // 4. Check Start Position
     let (starts_at_feet, start_direction) =
-        center_lines.check_road_start(image_width, image_height);
+        center_lines.check_road_start(image_width, image_height, config);
 
     // 5. Assemble Intermediate Data Structure
     Some(RoadAnalysisData {
         image_width,
         image_height,
-        detect_results: detections,
+        detect_results: detections.to_vec(),
         shape,
         obstacles,
         center_lines, // Move the calculated centerline here
         starts_at_feet,
         start_direction,
+        tracks: vec![],
+        depth: None,
     })
 }
 
-pub fn get_best_highway(masks: &Vec<BitVec>) -> Option<&BitVec> {
+/// Removes the speckles, gaps and holes SAM leaves in a mask `width` pixels wide,
+/// as far as the config asks for it.
+pub fn clean_mask(mask: &BitVec, width: u32, config: &AnalysisConfig) -> BitVec {
+    let width = width as usize;
+    let mut mask = mask.close(width, config.mask_close_radius as usize);
+    mask = mask.open(width, config.mask_open_radius as usize);
+    if config.fill_mask_holes {
+        mask = mask.fill_holes(width);
+    }
+    if config.keep_largest_mask_component {
+        mask = mask.keep_largest_component(width);
+    }
+
+    mask
+}
+
+pub fn get_best_highway<'a>(
+    masks: &'a Vec<BitVec>,
+    mask_size: u32,
+    config: &AnalysisConfig,
+) -> Option<&'a BitVec> {
     masks.iter().max_by_key(|mask| {
-        let center_line = CenterLines::extract_center_line(mask, 1024, 1024);
+        let center_line = CenterLines::extract_center_line(mask, mask_size, mask_size, config);
         center_line.first().map_or(0, |p| p.y) // Use y of nearest point
     })
 }
@@ -1,4 +1,4 @@
-mod analysis;
-mod constants;
+pub(crate) mod analysis;
+pub(crate) mod constants;
 pub mod mask;
-mod property;
+pub(crate) mod property;
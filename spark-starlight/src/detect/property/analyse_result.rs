@@ -1,15 +1,19 @@
+use crate::detect::property::approach::TrackedObject;
 use crate::detect::property::center_line::CenterLines;
 use crate::detect::property::direction::DirectionCategory;
 use crate::detect::property::obstacle::ObstacleInfo;
 use crate::detect::property::road_shape::RoadShape;
+use serde::Serialize;
+use spark_inference::inference::depth::DepthMap;
 use spark_inference::inference::yolo::inference_yolo_detect::YoloDetectResult;
+use std::sync::Arc;
 
-#[derive(Debug, Clone)]
-pub struct RoadAnalysisData<'a> {
+#[derive(Debug, Clone, Serialize)]
+pub struct RoadAnalysisData {
     // Renamed to avoid confusion with the final *output* struct if needed later
     pub image_width: u32,
     pub image_height: u32,
-    pub detect_results: &'a [YoloDetectResult], // Renamed for clarity
+    pub detect_results: Vec<YoloDetectResult>, // Renamed for clarity
 
     pub shape: RoadShape,
     pub obstacles: Vec<ObstacleInfo>,
@@ -18,4 +22,22 @@ pub struct RoadAnalysisData<'a> {
     // These are essential intermediate results derived from center_lines
     pub starts_at_feet: bool,
     pub start_direction: Option<DirectionCategory>, // Direction if not starting ideally
+
+    /// Objects followed across the frames of a session that stand on this surface,
+    /// or on none of the surfaces if this is the first one. Empty without a session.
+    pub tracks: Vec<TrackedObject>,
+    /// Depth of the frame in the coordinates of the analysis, None without a depth model.
+    /// Shared with the other surfaces of the frame and left out of the serialized analysis.
+    #[serde(skip)]
+    pub depth: Option<Arc<DepthMap>>,
+}
+
+/// The result of analysing one road surface (e.g. "Highway" or "Sidewalk").
+/// Carries the structured analysis alongside the sentence composed from it,
+/// so callers can either speak the text or render the raw data.
+#[derive(Debug, Clone, Serialize)]
+pub struct SurfaceAnalysis {
+    pub surface: String,
+    pub analysis: Option<RoadAnalysisData>, // None if core analysis failed
+    pub description: String,
 }
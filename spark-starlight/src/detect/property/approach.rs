@@ -0,0 +1,96 @@
+use crate::config::AnalysisConfig;
+use serde::Serialize;
+use spark_inference::inference::tracker::Track;
+
+/// An object followed across the recent frames of a session, with its motion per second.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackedObject {
+    /// Stays the same while the object is tracked.
+    pub id: u64,
+    pub label: String,
+    /// Box centre and size, in the same coordinates as the detections.
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    /// Pixels the box centre moves per second.
+    pub velocity_x: f32,
+    pub velocity_y: f32,
+    /// Relative change of the box height per second, positive while the object comes closer.
+    pub growth: f32,
+}
+
+impl TrackedObject {
+    /// Converts the per-frame motion of `track` with the seconds between two frames.
+    pub fn from_track(track: &Track, frame_secs: f32) -> Self {
+        Self {
+            id: track.id,
+            label: track.label.clone(),
+            x: track.x,
+            y: track.y,
+            width: track.width,
+            height: track.height,
+            velocity_x: track.velocity.x / frame_secs,
+            velocity_y: track.velocity.y / frame_secs,
+            growth: track.growth / frame_secs,
+        }
+    }
+
+    /// Seconds until the object reaches the camera if it keeps its speed, None unless it approaches.
+    ///
+    /// The size of an object in the image is inversely proportional to its distance, so a box
+    /// growing by `g` per second is `1 / g` seconds away. For something standing on the ground
+    /// the same holds for the distance of its footpoint below the horizon, which also covers
+    /// objects seen from the side whose height barely changes. The earlier estimate is taken.
+    pub fn time_to_collision(&self, image_height: u32, config: &AnalysisConfig) -> Option<f32> {
+        if self.growth < config.approach_min_growth {
+            return None;
+        }
+        let from_scale = 1.0 / self.growth;
+
+        let horizon = config.horizon_y_factor * image_height as f32;
+        let bottom = self.y + self.height / 2.0;
+        let bottom_speed = self.velocity_y + self.growth * self.height / 2.0;
+        let from_ground = (bottom > horizon && bottom_speed > 0.0)
+            .then(|| (bottom - horizon) / bottom_speed)
+            .unwrap_or(f32::INFINITY);
+
+        Some(from_scale.min(from_ground))
+    }
+}
+
+#[test]
+fn test_time_to_collision() {
+    let config = AnalysisConfig::default();
+    let object = |y: f32, height: f32, velocity_y: f32, growth: f32| TrackedObject {
+        id: 1,
+        label: "bicycle".to_string(),
+        x: 500.0,
+        y,
+        width: height / 2.0,
+        height,
+        velocity_x: 0.0,
+        velocity_y,
+        growth,
+    };
+
+    // Growing by half its size per second, two seconds away
+    let ttc = object(300.0, 100.0, 0.0, 0.5).time_to_collision(1000, &config);
+    assert_eq!(ttc, Some(2.0));
+
+    // The footpoint 200 px below the horizon sinks 100 px per second, before the scale says so
+    let ttc = object(650.0, 100.0, 80.0, 0.4).time_to_collision(1000, &config);
+    assert_eq!(ttc, Some(2.0));
+    let ttc = object(650.0, 100.0, 25.0, 0.1).time_to_collision(1000, &config);
+    assert_eq!(ttc, Some(200.0 / 30.0));
+
+    // Standing and receding objects never collide
+    assert_eq!(
+        object(650.0, 100.0, 0.0, 0.01).time_to_collision(1000, &config),
+        None
+    );
+    assert_eq!(
+        object(650.0, 100.0, -20.0, -0.2).time_to_collision(1000, &config),
+        None
+    );
+}
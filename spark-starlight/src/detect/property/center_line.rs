@@ -1,25 +1,22 @@
-use crate::detect::constants::{
-    MIN_CENTERLINE_POINTS_FOR_SHAPE, MIN_MASK_WIDTH_FOR_CENTER, NUM_VERTICAL_SAMPLES,
-    OBSTACLE_GAP_ROWS_THRESHOLD, OBSTACLE_WIDTH_CHANGE_FACTOR, ROAD_CENTER_X_THRESHOLD,
-    ROAD_START_Y_THRESHOLD, STRAIGHT_ROAD_X_DRIFT_THRESHOLD,
-};
+use crate::config::AnalysisConfig;
 use crate::detect::property::direction::DirectionCategory;
-use crate::detect::property::obstacle::ObstacleInfo;
+use crate::detect::property::obstacle::{ObstacleInfo, ObstacleKind};
 use crate::detect::property::road_shape::RoadShape;
 use bitvec::prelude::BitVec;
 use log::error;
+use serde::Serialize;
 use std::ops::{Deref, DerefMut};
 
 /// Center line is the line that runs through the center of the road mask.
 /// It is used to analyze the road shape and detect obstacles.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CenterLinePoint {
     pub y: u32,
     pub center_x: f32,
     pub width: u32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CenterLines(Vec<CenterLinePoint>);
 impl Deref for CenterLines {
     type Target = Vec<CenterLinePoint>;
@@ -36,7 +33,12 @@ impl DerefMut for CenterLines {
 
 impl CenterLines {
     /// Extracts the center line and width profile of the road mask.
-    pub fn extract_center_line(mask: &BitVec, image_width: u32, image_height: u32) -> Self {
+    pub fn extract_center_line(
+        mask: &BitVec,
+        image_width: u32,
+        image_height: u32,
+        config: &AnalysisConfig,
+    ) -> Self {
         let mut center_line = CenterLines(Vec::new());
         if image_width == 0 || image_height == 0 || mask.is_empty() {
             return center_line;
@@ -50,9 +52,9 @@ impl CenterLines {
             return center_line;
         }
 
-        let y_step = (image_height / (NUM_VERTICAL_SAMPLES + 1)).max(1); // Ensure step is at least 1
+        let y_step = (image_height / (config.num_vertical_samples + 1)).max(1); // Ensure step is at least 1
 
-        for i in 1..=NUM_VERTICAL_SAMPLES {
+        for i in 1..=config.num_vertical_samples {
             let y = image_height.saturating_sub(i * y_step); // Sample from bottom up
             if y >= image_height {
                 continue;
@@ -75,7 +77,7 @@ impl CenterLines {
 
             if let (Some(min_val), Some(max_val)) = (min_x, max_x) {
                 let width = max_val.saturating_sub(min_val) + 1;
-                if width >= MIN_MASK_WIDTH_FOR_CENTER {
+                if width >= config.min_mask_width_for_center {
                     let center_x = (min_val + max_val) as f32 / 2.0;
                     center_line.push(CenterLinePoint { y, center_x, width });
                 }
@@ -86,8 +88,12 @@ impl CenterLines {
     }
 
     /// Analyzes the shape (straight, curve left/right) based on center_line points.
-    pub fn analyze_center_line_shape(&self, image_width: u32) -> RoadShape {
-        if self.len() < MIN_CENTERLINE_POINTS_FOR_SHAPE {
+    pub fn analyze_center_line_shape(
+        &self,
+        image_width: u32,
+        config: &AnalysisConfig,
+    ) -> RoadShape {
+        if self.len() < config.min_centerline_points_for_shape {
             return RoadShape::Undetermined;
         }
         let nearest_point = &self[0];
@@ -95,7 +101,7 @@ impl CenterLines {
         let normalized_drift =
             (farthest_point.center_x - nearest_point.center_x) / image_width as f32;
 
-        if normalized_drift.abs() < STRAIGHT_ROAD_X_DRIFT_THRESHOLD {
+        if normalized_drift.abs() < config.straight_road_x_drift_threshold {
             RoadShape::Straight
         } else if normalized_drift > 0.0 {
             // Farthest point is right -> Curves Right
@@ -108,20 +114,25 @@ impl CenterLines {
 
     /// Detects obstacles like gaps or significant narrowing based on the center line profile.
     /// Obstacle direction is calculated relative to the road's starting point.
-    pub fn detect_obstacles(&self, image_width: u32, image_height: u32) -> Vec<ObstacleInfo> {
+    pub fn detect_obstacles(
+        &self,
+        image_width: u32,
+        image_height: u32,
+        config: &AnalysisConfig,
+    ) -> Vec<ObstacleInfo> {
         let mut obstacles = Vec::new();
         if self.len() < 2 {
             return obstacles;
         }
 
-        let y_step = (image_height / (NUM_VERTICAL_SAMPLES + 1)).max(1);
+        let y_step = (image_height / (config.num_vertical_samples + 1)).max(1);
         let origin_point = &self[0]; // Use the nearest point on the centerline as the origin for obstacle directions
 
         // Detect Gaps
         let mut consecutive_missing_rows = 0;
         let mut last_valid_y = origin_point.y; // Keep track of the last row where the mask was found
 
-        for i in 1..=NUM_VERTICAL_SAMPLES {
+        for i in 1..=config.num_vertical_samples {
             let current_y = image_height.saturating_sub(i * y_step);
             let sampled_point_exists = self.iter().any(|p| p.y.abs_diff(current_y) < y_step / 2); // Check if a point exists near this y
 
@@ -133,10 +144,10 @@ impl CenterLines {
                 consecutive_missing_rows = 0; // Reset gap counter
             } else {
                 consecutive_missing_rows += 1;
-                if consecutive_missing_rows == OBSTACLE_GAP_ROWS_THRESHOLD {
+                if consecutive_missing_rows == config.obstacle_gap_rows_threshold {
                     // Estimate gap position: halfway through the missing rows, below the last seen row
-                    let gap_y_estimate =
-                        last_valid_y.saturating_sub((OBSTACLE_GAP_ROWS_THRESHOLD * y_step) / 2);
+                    let gap_y_estimate = last_valid_y
+                        .saturating_sub((config.obstacle_gap_rows_threshold * y_step) / 2);
 
                     // Find the center_x of the point just before the gap started
                     let point_before_gap = self.iter().find(|p| p.y == last_valid_y);
@@ -154,7 +165,8 @@ impl CenterLines {
                     obstacles.push(ObstacleInfo {
                         y: gap_y_estimate,
                         center_x: center_x_estimate,
-                        reason: format!("Potential gap near {}", obstacle_direction),
+                        kind: ObstacleKind::Gap,
+                        direction: obstacle_direction,
                     });
                     // Don't reset consecutive_missing_rows here, let it continue counting if the gap is larger
                 }
@@ -167,12 +179,13 @@ impl CenterLines {
             let p_far = &self[i + 1]; // Further from viewer (lower y)
 
             // Check if width decreased significantly going further away
-            if (p_far.width as f32) < (p_near.width as f32 * OBSTACLE_WIDTH_CHANGE_FACTOR) {
+            if (p_far.width as f32) < (p_near.width as f32 * config.obstacle_width_change_factor) {
                 // Check if this narrowing isn't already marked as part of a gap
                 let y_mid_narrowing = (p_near.y + p_far.y) / 2;
                 let is_near_gap = obstacles.iter().any(
                     |obs| {
-                        obs.reason.contains("gap") && obs.y.abs_diff(y_mid_narrowing) < y_step * 2
+                        obs.kind == ObstacleKind::Gap
+                            && obs.y.abs_diff(y_mid_narrowing) < y_step * 2
                     }, // Generous window around gap Y
                 );
 
@@ -187,7 +200,8 @@ impl CenterLines {
                     obstacles.push(ObstacleInfo {
                         y: p_far.y, // Position where the narrowing is significant
                         center_x: p_far.center_x,
-                        reason: format!("Significant narrowing near {}", obstacle_direction),
+                        kind: ObstacleKind::Narrowing,
+                        direction: obstacle_direction,
                     });
                 }
             }
@@ -207,16 +221,17 @@ impl CenterLines {
         &self,
         image_width: u32,
         image_height: u32,
+        config: &AnalysisConfig,
     ) -> (bool, Option<DirectionCategory>) {
         match self.first() {
             Some(nearest_point) => {
                 // Vertical check: Is the nearest point close enough to the bottom?
                 let normalized_y = nearest_point.y as f32 / image_height as f32;
-                let is_at_bottom = normalized_y >= ROAD_START_Y_THRESHOLD;
+                let is_at_bottom = normalized_y >= config.road_start_y_threshold;
 
                 // Horizontal check: Is the nearest point reasonably centered horizontally?
                 let normalized_x_offset = (nearest_point.center_x / image_width as f32 - 0.5).abs();
-                let is_centered = normalized_x_offset <= ROAD_CENTER_X_THRESHOLD;
+                let is_centered = normalized_x_offset <= config.road_center_x_threshold;
 
                 if is_at_bottom && is_centered {
                     (true, None) // Ideal start: at feet and centered
@@ -1,8 +1,9 @@
 use log::error;
+use serde::Serialize;
 use std::f32::consts::PI;
 use std::fmt::{Display, Formatter};
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize)]
 pub enum DirectionCategory {
     Clock(&'static str),
     Unknown,
@@ -1,5 +1,7 @@
-use crate::detect::constants::DISTANCE_PERSPECTIVE_POWER;
+use crate::config::AnalysisConfig;
 use log::error;
+use spark_inference::inference::depth::DepthMap;
+use spark_inference::inference::yolo::inference_yolo_detect::YoloDetectResult;
 use std::fmt::{Display, Formatter};
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
@@ -24,6 +26,17 @@ impl Display for DistanceCategory {
 }
 
 impl DistanceCategory {
+    /// Ranks the category by how pressing it is, closer is higher.
+    pub fn urgency(&self) -> u8 {
+        match self {
+            DistanceCategory::VeryNear => 4,
+            DistanceCategory::RelativelyNear => 3,
+            DistanceCategory::Near => 2,
+            DistanceCategory::Far => 1,
+            DistanceCategory::Unknown => 0,
+        }
+    }
+
     /// Calculates the perceived distance of an anchor point based on its y-coordinate.
     /// Considers perspective (lower y in image means closer to the viewer).
     /// Applies a non-linear mapping to account for perspective distortion.
@@ -31,10 +44,11 @@ impl DistanceCategory {
     /// Args:
     ///     y (f32): The y-coordinate of the anchor point (center of the bounding box).
     ///     image_height (u32): The total height of the image frame in pixels.
+    ///     config (AnalysisConfig): Supplies the perspective power factor.
     ///
     /// Returns:
     ///     DistanceCategory: The perceived distance category.
-    pub fn get_distance(y: f32, image_height: u32) -> Self {
+    pub fn get_distance(y: f32, image_height: u32, config: &AnalysisConfig) -> Self {
         if image_height == 0 {
             error!("Image height cannot be zero for distance calculation.");
             return DistanceCategory::Unknown;
@@ -48,7 +62,7 @@ impl DistanceCategory {
         // This maps the linear normalized_y to a non-linear scale.
         // With power < 1, values closer to 1.0 (bottom) increase faster,
         // simulating the visual effect of things getting much closer rapidly at the bottom.
-        let perspective_adjusted_y = normalized_y.powf(DISTANCE_PERSPECTIVE_POWER);
+        let perspective_adjusted_y = normalized_y.powf(config.distance_perspective_power);
 
         // Define thresholds based on the perspective-adjusted value.
         // *** These thresholds likely need tuning based on the chosen power factor ***
@@ -66,4 +80,82 @@ impl DistanceCategory {
             DistanceCategory::Far
         }
     }
+
+    /// Category of a distance measured in meters, bounded by the `*_meters` thresholds.
+    pub fn from_meters(meters: f32, config: &AnalysisConfig) -> Self {
+        if meters.is_nan() || meters <= 0.0 {
+            DistanceCategory::Unknown
+        } else if meters <= config.very_near_meters {
+            DistanceCategory::VeryNear
+        } else if meters <= config.relatively_near_meters {
+            DistanceCategory::RelativelyNear
+        } else if meters <= config.near_meters {
+            DistanceCategory::Near
+        } else {
+            DistanceCategory::Far
+        }
+    }
+
+    /// Distance of the ground at `(x, y)`: read from the depth map when the frame has one
+    /// that covers the point, guessed from `y` with [`Self::get_distance`] otherwise.
+    pub fn at(
+        x: f32,
+        y: f32,
+        depth: Option<&DepthMap>,
+        image_height: u32,
+        config: &AnalysisConfig,
+    ) -> Self {
+        match depth.and_then(|depth| depth.sample(x, y)) {
+            Some(meters) => Self::from_meters(meters, config),
+            None => Self::get_distance(y, image_height, config),
+        }
+    }
+
+    /// Distance of a detected object. The depth map is sampled at its footpoint, just above the
+    /// bottom edge of the box where it stands on the ground, the guess keeps using its centre.
+    pub fn of_detection(
+        detection: &YoloDetectResult,
+        depth: Option<&DepthMap>,
+        image_height: u32,
+        config: &AnalysisConfig,
+    ) -> Self {
+        let footpoint = detection.y + detection.height * 0.45;
+        match depth.and_then(|depth| depth.sample(detection.x, footpoint)) {
+            Some(meters) => Self::from_meters(meters, config),
+            None => Self::get_distance(detection.y, image_height, config),
+        }
+    }
+}
+
+#[test]
+fn test_distance_prefers_depth() {
+    let config = AnalysisConfig::default();
+    assert_eq!(
+        DistanceCategory::from_meters(2.0, &config),
+        DistanceCategory::VeryNear
+    );
+    assert_eq!(
+        DistanceCategory::from_meters(8.0, &config),
+        DistanceCategory::Near
+    );
+    assert_eq!(
+        DistanceCategory::from_meters(f32::INFINITY, &config),
+        DistanceCategory::Far
+    );
+
+    // A slope: the top of the image is as close as the bottom, which the guess cannot know
+    let depth = DepthMap::new(ndarray::Array2::from_elem((100, 100), 2.5));
+    assert_eq!(
+        DistanceCategory::at(50.0, 10.0, Some(&depth), 100, &config),
+        DistanceCategory::VeryNear
+    );
+    assert_eq!(
+        DistanceCategory::at(50.0, 10.0, None, 100, &config),
+        DistanceCategory::Far
+    );
+    // Outside the map the guess takes over
+    assert_eq!(
+        DistanceCategory::at(50.0, 150.0, Some(&depth), 100, &config),
+        DistanceCategory::VeryNear
+    );
 }
@@ -1,4 +1,5 @@
 pub(crate) mod analyse_result;
+pub(crate) mod approach;
 pub(crate) mod center_line;
 pub(crate) mod direction;
 pub(crate) mod distance;
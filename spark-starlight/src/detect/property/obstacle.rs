@@ -1,7 +1,17 @@
-#[derive(Debug, Clone)]
+use crate::detect::property::direction::DirectionCategory;
+use serde::Serialize;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+pub enum ObstacleKind {
+    Gap,       // The mask disappears for several sampled rows
+    Narrowing, // The mask width drops sharply between two rows
+}
+
+#[derive(Debug, Clone, Serialize)]
 #[allow(dead_code)]
 pub struct ObstacleInfo {
     pub y: u32,
     pub center_x: f32,
-    pub reason: String, // Includes direction description now
+    pub kind: ObstacleKind,
+    pub direction: DirectionCategory, // Relative to the start of the road
 }
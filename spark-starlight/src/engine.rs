@@ -0,0 +1,149 @@
+use crate::config::{MaskSource, ModelBackend, ModelConfig};
+use crate::detect::analysis::locale::Language;
+use log::{info, warn};
+use spark_inference::engine::session_pool::PoolStats;
+use spark_inference::inference::depth::{DepthEstimation, DepthSession};
+use spark_inference::inference::fake::{FakeDepth, FakeDetector, FakeSegmenter, FakeSpeech};
+use spark_inference::inference::sam::image_inference::{
+    SAMImageInferenceSession, SamImageInference,
+};
+use spark_inference::inference::tts::tts_engine::{SpeechSynthesizer, TTSEngine, TTS};
+use spark_inference::inference::yolo::inference_yolo_detect::{
+    YoloDetectInference, YoloDetectSession,
+};
+use spark_inference::inference::yolo::inference_yolo_seg::{
+    YoloSegmentInference, YoloSegmentSession,
+};
+
+pub type Detector = Box<dyn YoloDetectInference + Send + Sync>;
+pub type Segmenter = Box<dyn SamImageInference + Send + Sync>;
+pub type SegmentDetector = Box<dyn YoloSegmentInference + Send + Sync>;
+pub type DepthEstimator = Box<dyn DepthEstimation + Send + Sync>;
+pub type Speech = Box<dyn SpeechSynthesizer>;
+
+/// Where the boxes and road masks of a frame come from.
+pub enum Segmentation {
+    /// YOLO boxes used as SAM prompts.
+    Sam { yolo: Detector, sam2: Segmenter },
+    /// Boxes and masks of a single YOLO segmentation model.
+    YoloSeg(SegmentDetector),
+}
+
+/// The models behind the pipeline. Each one is only known by its trait,
+/// so the same server runs on the GPU models or on the fakes.
+pub struct InferenceEngine {
+    pub segmentation: Segmentation,
+    /// Metric depth of the frames, `None` when no depth model is configured.
+    pub depth: Option<DepthEstimator>,
+    pub tts: Speech,
+    /// Chinese voice, `None` when its model could not be loaded.
+    pub tts_zh: Option<Speech>,
+}
+
+impl InferenceEngine {
+    /// Loads the backend selected by `model.backend`.
+    pub fn load(config: &ModelConfig) -> anyhow::Result<Self> {
+        match config.backend {
+            ModelBackend::Onnx => Self::onnx(config),
+            ModelBackend::Fake => {
+                warn!("Running on the fake backend, descriptions do not reflect the images");
+                Ok(Self::fake())
+            }
+        }
+    }
+
+    fn onnx(config: &ModelConfig) -> anyhow::Result<Self> {
+        let provider = config.provider.execution_provider();
+        let pool = config.pool_options();
+        let segmentation = match config.masks {
+            MaskSource::Sam => Segmentation::Sam {
+                yolo: Box::new(YoloDetectSession::with_pool(
+                    &config.yolo_folder,
+                    provider,
+                    pool,
+                )?),
+                sam2: Box::new(SAMImageInferenceSession::with_pool(
+                    &config.sam_folder,
+                    provider,
+                    pool,
+                )?),
+            },
+            MaskSource::Yolo => Segmentation::YoloSeg(Box::new(YoloSegmentSession::with_pool(
+                &config.yolo_folder,
+                provider,
+                pool,
+            )?)),
+        };
+        let depth = match &config.depth_folder {
+            Some(folder) => Some(Box::new(DepthSession::with_pool(
+                folder,
+                config.depth_output,
+                provider,
+                pool,
+            )?) as DepthEstimator),
+            None => None,
+        };
+        let tts = TTSEngine::new_en(&config.tts_en_folder)?;
+        let tts_zh = match TTSEngine::new_zh(&config.tts_zh_folder) {
+            Ok(tts) => Some(Box::new(tts) as Speech),
+            Err(e) => {
+                warn!(
+                    "Chinese TTS engine unavailable, zh requests will not get audio: {:?}",
+                    e
+                );
+                None
+            }
+        };
+        info!(
+            "ONNX inference backend loaded on {:?} with {:?} masks, {} sessions per model",
+            provider, config.masks, pool.size
+        );
+
+        Ok(Self {
+            segmentation,
+            depth,
+            tts: Box::new(tts),
+            tts_zh,
+        })
+    }
+
+    /// Deterministic backend: one sidewalk in front of the camera, masks shaped like the boxes,
+    /// the depth of flat ground and silent speech in both languages.
+    pub fn fake() -> Self {
+        Self {
+            segmentation: Segmentation::Sam {
+                yolo: Box::new(FakeDetector::default()),
+                sam2: Box::new(FakeSegmenter),
+            },
+            depth: Some(Box::new(FakeDepth)),
+            tts: Box::new(FakeSpeech::default()),
+            tts_zh: Some(Box::new(FakeSpeech::default())),
+        }
+    }
+
+    /// Usage of the session pools of every model.
+    pub fn pool_stats(&self) -> Vec<PoolStats> {
+        let mut stats = match &self.segmentation {
+            Segmentation::Sam { yolo, sam2 } => {
+                let mut stats = yolo.pool_stats();
+                stats.extend(sam2.pool_stats());
+                stats
+            }
+            Segmentation::YoloSeg(yolo) => yolo.pool_stats(),
+        };
+        if let Some(depth) = &self.depth {
+            stats.extend(depth.pool_stats());
+        }
+        stats
+    }
+
+    pub fn tts(&self, language: Language) -> anyhow::Result<&dyn SpeechSynthesizer> {
+        match language {
+            Language::English => Ok(self.tts.as_ref()),
+            Language::Chinese => self
+                .tts_zh
+                .as_deref()
+                .ok_or_else(|| anyhow::anyhow!("Chinese speech synthesis is not available")),
+        }
+    }
+}
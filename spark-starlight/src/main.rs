@@ -1,30 +1,34 @@
 #![feature(let_chains)]
 #![cfg_attr(debug_assertions, allow(warnings))]
 
-use crate::detect::mask::{analyze_road_mask, get_best_highway};
-use actix_web::{web, App, Error, HttpResponse, HttpServer};
-use bitvec::prelude::BitVec;
+use crate::cli::{Cli, Command};
+use crate::config::Config;
+use crate::detect::analysis::locale::Language;
+use crate::engine::InferenceEngine;
+use crate::pipeline::{analyse_image, analyse_scene, synthesize, AudioOutput, SceneAnalysis};
+use crate::session::{attach_session_id, SessionStore};
+use crate::stream::{stream_handler, InferenceSlots};
+use actix_web::{web, App, Error, HttpRequest, HttpResponse, HttpServer};
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
 use bytes::Bytes;
+use clap::Parser;
 use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
 use spark_inference::disable_ffmpeg_logging;
-use spark_inference::inference::sam::image_inference::{
-    SAMImageInferenceSession, SamImageInference,
-};
-use spark_inference::inference::tts::tts_engine::{TTSEngine, TTS};
-use spark_inference::inference::yolo::inference_yolo_detect::{
-    YoloDetectInference, YoloDetectResult, YoloDetectSession,
-};
-use spark_inference::inference::yolo::NMSImplement;
-use spark_inference::utils::graph::{Point, SamPrompt};
+use spark_inference::engine::session_pool::PoolStats;
 use spark_media::Image;
 use std::ops::Deref;
-use std::sync::atomic::AtomicU64;
-use std::sync::Arc;
-use tokio::task::{spawn_blocking, JoinHandle};
-use tokio::{count, join, spawn};
+use tokio::task::spawn_blocking;
 
+mod cli;
+mod config;
 mod debug;
 mod detect;
+mod engine;
+mod pipeline;
+mod session;
+mod stream;
 
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
@@ -33,30 +37,98 @@ fn log_init() {
     tracing_subscriber::fmt::init();
 }
 
-struct InferenceEngine {
-    yolo: YoloDetectSession,
-    sam2: SAMImageInferenceSession,
-    tts: TTSEngine,
+async fn decode_image(body: Bytes) -> Result<Image, HttpResponse> {
+    match spawn_blocking(move || Image::from_bytes(body.deref())).await {
+        Ok(Ok(image)) => Ok(image),
+        err => {
+            warn!("Error processing image: {:?}", err);
+            Err(HttpResponse::BadRequest().body("Invalid image data"))
+        }
+    }
+}
+
+/// Sample rates a client may ask the audio to be resampled to.
+const SAMPLE_RATE_RANGE: std::ops::RangeInclusive<u32> = 8000..=48000;
+
+/// Parses the `format` and `rate` query parameters, answers 400 on values we cannot encode.
+pub(crate) fn audio_output(
+    format: Option<&str>,
+    rate: Option<u32>,
+) -> Result<AudioOutput, HttpResponse> {
+    let format = match format.map(str::parse).transpose() {
+        Ok(format) => format.unwrap_or_default(),
+        Err(e) => return Err(HttpResponse::BadRequest().body(format!("{}", e))),
+    };
+
+    if let Some(rate) = rate.filter(|rate| !SAMPLE_RATE_RANGE.contains(rate)) {
+        return Err(HttpResponse::BadRequest().body(format!(
+            "Unsupported sample rate {}, expected {} to {} Hz",
+            rate,
+            SAMPLE_RATE_RANGE.start(),
+            SAMPLE_RATE_RANGE.end()
+        )));
+    }
+
+    Ok(AudioOutput {
+        format,
+        sample_rate: rate,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct UploadImageQuery {
+    /// Language of the spoken description, `en` or `zh`.
+    #[serde(default)]
+    lang: Language,
+    /// Audio encoding: `wav` (32-bit float, default), `wav16`, `opus`, `m4a` or `mp3`.
+    format: Option<String>,
+    /// Sample rate of the audio in Hz, the TTS engine's own rate when unset.
+    rate: Option<u32>,
 }
 
 async fn upload_image_handler(
     engine: web::Data<&'static InferenceEngine>,
+    config: web::Data<&'static Config>,
+    sessions: web::Data<&'static SessionStore>,
+    request: HttpRequest,
+    query: web::Query<UploadImageQuery>,
     body: Bytes,
 ) -> Result<HttpResponse, Error> {
     info!("Received POST request on /uploadImage");
+    let output = match audio_output(query.format.as_deref(), query.rate) {
+        Ok(output) => output,
+        Err(response) => return Ok(response),
+    };
+    let (session_id, session) = sessions.resolve(&request);
 
-    let image = match spawn_blocking(move || Image::from_bytes(body.deref())).await {
-        Ok(Ok(image)) => image,
-        err => {
-            warn!("Error processing image: {:?}", err);
-            return Ok(HttpResponse::BadRequest().body("Invalid image data"));
-        }
+    let image = match decode_image(body).await {
+        Ok(image) => image,
+        Err(response) => return Ok(response),
     };
 
-    match analyse_image(image, engine.deref()).await {
+    match analyse_image(
+        image,
+        engine.deref(),
+        config.deref(),
+        query.lang,
+        output,
+        Some(&session),
+    )
+    .await
+    {
+        Ok(response_message) if response_message.is_empty() => {
+            info!("Processing successful, nothing new to announce.");
+            let mut response = HttpResponse::NoContent();
+            attach_session_id(&mut response, &session_id);
+            Ok(response.finish())
+        }
         Ok(response_message) => {
             info!("Processing successful.");
-            Ok(HttpResponse::Ok().body(response_message))
+            let mut response = HttpResponse::Ok();
+            attach_session_id(&mut response, &session_id);
+            Ok(response
+                .content_type(output.format.content_type())
+                .body(response_message))
         }
         Err(e) => {
             log::error!("Error processing image: {:?}", e);
@@ -65,175 +137,217 @@ async fn upload_image_handler(
     }
 }
 
+#[derive(Debug, Deserialize)]
+struct AnalyzeQuery {
+    /// Also synthesize the composed text and return it base64 encoded.
+    /// Stays null when the session has nothing new to announce.
+    #[serde(default)]
+    audio: bool,
+    /// Language of the description and the audio, `en` or `zh`.
+    #[serde(default)]
+    lang: Language,
+    /// Audio encoding: `wav` (32-bit float, default), `wav16`, `opus`, `m4a` or `mp3`.
+    format: Option<String>,
+    /// Sample rate of the audio in Hz, the TTS engine's own rate when unset.
+    rate: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct AnalyzeResponse {
+    #[serde(flatten)]
+    scene: SceneAnalysis,
+    audio: Option<String>,
+    /// Content type of the decoded `audio`.
+    audio_content_type: Option<&'static str>,
+}
+
+async fn analyze_handler(
+    engine: web::Data<&'static InferenceEngine>,
+    config: web::Data<&'static Config>,
+    sessions: web::Data<&'static SessionStore>,
+    request: HttpRequest,
+    query: web::Query<AnalyzeQuery>,
+    body: Bytes,
+) -> Result<HttpResponse, Error> {
+    info!("Received POST request on /analyze");
+    let output = match audio_output(query.format.as_deref(), query.rate) {
+        Ok(output) => output,
+        Err(response) => return Ok(response),
+    };
+    let (session_id, session) = sessions.resolve(&request);
+
+    let image = match decode_image(body).await {
+        Ok(image) => image,
+        Err(response) => return Ok(response),
+    };
+
+    let scene = match analyse_scene(
+        image,
+        engine.deref(),
+        config.deref(),
+        query.lang,
+        Some(&session),
+        None,
+    )
+    .await
+    {
+        Ok(scene) => scene,
+        Err(e) => {
+            error!("Error processing image: {:?}", e);
+            return Ok(
+                HttpResponse::InternalServerError().body(format!("Error processing image: {}", e))
+            );
+        }
+    };
+
+    let audio = if query.audio && !scene.text.is_empty() {
+        match synthesize(scene.text.clone(), scene.language, output, engine.deref()).await {
+            Ok(audio) => Some(BASE64_STANDARD.encode(audio)),
+            Err(e) => {
+                error!("Error synthesizing speech: {:?}", e);
+                return Ok(HttpResponse::InternalServerError()
+                    .body(format!("Error synthesizing speech: {}", e)));
+            }
+        }
+    } else {
+        None
+    };
+
+    info!("Analysis successful.");
+    let mut response = HttpResponse::Ok();
+    attach_session_id(&mut response, &session_id);
+    let audio_content_type = audio.as_ref().map(|_| output.format.content_type());
+    Ok(response.json(AnalyzeResponse {
+        scene,
+        audio,
+        audio_content_type,
+    }))
+}
+
+#[derive(Debug, Serialize)]
+struct MetricsResponse {
+    /// One entry per model session pool, empty on the fake backend.
+    pools: Vec<PoolStats>,
+}
+
+/// `GET /metrics`: how busy the model sessions are and how long requests waited for one.
+async fn metrics_handler(engine: web::Data<&'static InferenceEngine>) -> HttpResponse {
+    HttpResponse::Ok().json(MetricsResponse {
+        pools: engine.pool_stats(),
+    })
+}
+
+fn routes(config: &mut web::ServiceConfig) {
+    config
+        .route("/uploadImage", web::post().to(upload_image_handler))
+        .route("/analyze", web::post().to(analyze_handler))
+        .route("/stream", web::get().to(stream_handler))
+        .route("/metrics", web::get().to(metrics_handler));
+}
+
 #[actix_web::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
     log_init();
     disable_ffmpeg_logging();
 
-    let yolo = YoloDetectSession::new("./data/model")?;
-    let sam2 = SAMImageInferenceSession::new("./data/model/other5")?;
-    let tts = TTSEngine::new_en()?;
-    let engine: &'static InferenceEngine = Box::leak(Box::new(InferenceEngine { yolo, sam2, tts }));
+    let config: &'static Config = Box::leak(Box::new(Config::load()?));
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(config).await,
+        Command::Analyze(args) => cli::analyze::run(args, config).await,
+    }
+}
+
+async fn serve(config: &'static Config) -> anyhow::Result<()> {
+    let sessions: &'static SessionStore = Box::leak(Box::new(SessionStore::new(&config.session)));
+    let slots: &'static InferenceSlots = Box::leak(Box::new(InferenceSlots::new(&config.stream)));
+    let engine: &'static InferenceEngine =
+        Box::leak(Box::new(InferenceEngine::load(&config.model)?));
 
     HttpServer::new(move || {
         App::new()
-            .app_data(web::PayloadConfig::new(1024 * 1024 * 1024 * 25))
+            .app_data(web::PayloadConfig::new(config.server.payload_limit))
             .app_data(web::Data::new(engine))
-            .route("/uploadImage", web::post().to(upload_image_handler))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(sessions))
+            .app_data(web::Data::new(slots))
+            .configure(routes)
     })
-    .bind(("0.0.0.0", 7447))?
+    .bind((config.server.host.as_str(), config.server.port))?
     .run()
     .await?;
 
     Ok(())
 }
 
-async fn analyse_image(image: Image, engine: &'static InferenceEngine) -> anyhow::Result<Vec<u8>> {
-    let (image_width, image_height) = (image.get_width() as u32, image.get_height() as u32);
-    let (yolo, sam, tts) = (&engine.yolo, &engine.sam2, &engine.tts);
-
-    info!("Start Yolo detection");
-    let results = {
-        let image = image.clone();
-        spawn_blocking(move || {
-            Ok::<Vec<YoloDetectResult>, anyhow::Error>(yolo.inference_yolo(image, 0.25)?)
-        })
-        .await??
-    };
-
-    info!("detect results: {:?}", results.len());
-    if results.is_empty() {
-        let result: anyhow::Result<Vec<u8>> = spawn_blocking(move || {
-            Ok(tts.generate("Warning: Nothing could be found in current scene")?)
-        })
-        .await?;
-
-        return Ok(result?);
-    }
-
-    let result_highway = results
-        .clone()
-        .into_iter()
-        .filter(|result| result.score[0] >= 0.8)
-        .collect::<Vec<_>>();
-    let result_sidewalk = results
-        .into_iter()
-        .filter(|result| result.score[1] >= 0.4)
-        .collect::<Vec<_>>();
-    let mut result_highway = result_highway.non_maximum_suppression(0.5, 0.35, 0);
-    let mut result_sidewalk = result_sidewalk.non_maximum_suppression(0.5, 0.25, 1);
-    info!(
-        "Non max suppression results: {} {}",
-        result_highway.len(),
-        result_sidewalk.len()
-    );
-
-    let mask = {
-        let result_highway = result_highway
-            .iter()
-            .map(|yolo| {
-                SamPrompt::Box(spark_inference::utils::graph::Box {
-                    x: yolo.x,
-                    y: yolo.y,
-                    width: yolo.width,
-                    height: yolo.height,
-                })
-            })
-            .collect::<Vec<_>>();
-
-        let result_sidewalk = result_sidewalk
-            .iter()
-            .map(|yolo| {
-                SamPrompt::Box(spark_inference::utils::graph::Box {
-                    x: yolo.x,
-                    y: yolo.y,
-                    width: yolo.width,
-                    height: yolo.height,
-                })
-            })
-            .collect::<Vec<_>>();
-
-        info!("Start SAM inference");
-        let handle: JoinHandle<anyhow::Result<Vec<Vec<BitVec>>>> = spawn_blocking(move || {
-            Ok(sam.inference_frame(
-                image,
-                Some((1024, 1024)),
-                vec![result_highway, result_sidewalk],
-            )?)
-        });
-
-        handle.await??
-    };
-    info!("SAM inference results: {} {}", mask[0].len(), mask[1].len());
-
-    // Rescale the yolo results to 1024x1024
-    for yolo in result_highway.iter_mut() {
-        yolo.x = yolo.x / image_width as f32 * 1024.0;
-        yolo.y = yolo.y / image_height as f32 * 1024.0;
-        yolo.width = yolo.width / image_width as f32 * 1024.0;
-        yolo.height = yolo.height / image_height as f32 * 1024.0;
-    }
-    for yolo in result_sidewalk.iter_mut() {
-        yolo.x = yolo.x / image_width as f32 * 1024.0;
-        yolo.y = yolo.y / image_height as f32 * 1024.0;
-        yolo.width = yolo.width / image_width as f32 * 1024.0;
-        yolo.height = yolo.height / image_height as f32 * 1024.0;
-    }
-
-    let best_highway = get_best_highway(&mask[0]);
-
-    // Filter sidewalk masks: first find those under user's feet (contains bottom center)
-    let user_x = 1024 / 2;
-    let user_y = 1024 - 1; // Bottom center pixel
-    let mut valid_sidewalks: Vec<_> = mask[1]
-        .iter()
-        .filter(|mask| mask[user_y * 1024 + user_x]) // Check if contains user position
-        .collect();
-
-    // If none under feet, use all masks
-    if valid_sidewalks.is_empty() {
-        valid_sidewalks = mask[1].iter().collect();
-    }
+/// Uncompressed 64x48 grey PPM, decodable without any image files on disk.
+#[cfg(test)]
+fn test_image() -> Vec<u8> {
+    let mut image = b"P6\n64 48\n255\n".to_vec();
+    image.resize(image.len() + 64 * 48 * 3, 0x80);
+    image
+}
 
-    // Select sidewalk with the largest area (most true bits)
-    let best_sidewalk_mask = valid_sidewalks.iter().max_by_key(|mask| mask.count_ones());
+#[actix_web::test]
+async fn test_http_pipeline_on_fake_backend() {
+    use actix_web::test;
 
-    info!("Start analyzing masks");
-    let mut back = Vec::new();
-    // Filter highway masks: prioritize closest to user (highest average y)
-    let highway_future = best_highway
-        .map(|hw| analyze_road_mask(hw, result_highway.as_slice(), 1024, 1024, "Highway"));
+    let config: &'static Config = Box::leak(Box::new(
+        Config::from_source("[model]\nbackend = \"fake\"", Vec::new()).unwrap(),
+    ));
+    let engine: &'static InferenceEngine =
+        Box::leak(Box::new(InferenceEngine::load(&config.model).unwrap()));
+    let sessions: &'static SessionStore = Box::leak(Box::new(SessionStore::new(&config.session)));
+    let slots: &'static InferenceSlots = Box::leak(Box::new(InferenceSlots::new(&config.stream)));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(engine))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(sessions))
+            .app_data(web::Data::new(slots))
+            .configure(routes),
+    )
+    .await;
 
-    let sidewalk_future = best_sidewalk_mask
-        .map(|sw| analyze_road_mask(sw, result_sidewalk.as_slice(), 1024, 1024, "Sidewalk"));
+    let request = test::TestRequest::post()
+        .uri("/analyze?audio=true")
+        .set_payload(test_image())
+        .to_request();
+    let response: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(response["surfaces"][0]["surface"], "Sidewalk");
+    assert!(!response["text"].as_str().unwrap().is_empty());
+    assert_eq!(response["audio_content_type"], "audio/wav");
+    let audio = BASE64_STANDARD
+        .decode(response["audio"].as_str().unwrap())
+        .unwrap();
+    assert!(audio.starts_with(b"RIFF"));
 
-    match (highway_future, sidewalk_future) {
-        (Some(hw), Some(sw)) => {
-            let (highway, sidewalk) = join!(hw, sw);
-            back.push(highway);
-            back.push(sidewalk);
-        }
-        (Some(hw), None) => {
-            let highway = hw.await;
-            back.push(highway);
-        }
-        (None, Some(sw)) => {
-            let sidewalk = sw.await;
-            back.push(sidewalk);
-        }
-        (None, None) => {}
-    }
+    let request = test::TestRequest::post()
+        .uri("/uploadImage?lang=zh")
+        .set_payload(test_image())
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert!(response.status().is_success());
+    assert_eq!(
+        response
+            .headers()
+            .get("content-type")
+            .unwrap()
+            .to_str()
+            .unwrap(),
+        "audio/wav"
+    );
+    assert!(test::read_body(response).await.starts_with(b"RIFF"));
 
-    let string = back.join(", ");
-    info!("Get natural language: {}", string);
-    let result: anyhow::Result<Vec<u8>> = spawn_blocking(move || {
-        Ok(tts.generate(if back.is_empty() {
-            "Warning: No road detected"
-        } else {
-            string.as_str()
-        })?)
-    })
-    .await?;
+    let request = test::TestRequest::post()
+        .uri("/analyze")
+        .set_payload("not an image")
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 400);
 
-    Ok(result?)
+    let request = test::TestRequest::get().uri("/metrics").to_request();
+    let response: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(response["pools"], serde_json::json!([]));
 }
@@ -0,0 +1,725 @@
+use crate::config::{Config, DetectionConfig};
+use crate::detect::analysis::locale::{Language, Message};
+use crate::detect::mask::{describe_road_analysis, get_best_highway, perform_core_analysis};
+use crate::detect::property::analyse_result::SurfaceAnalysis;
+use crate::engine::{Detector, InferenceEngine, SegmentDetector, Segmentation, Segmenter};
+use crate::session::smoothing::Session;
+use bitvec::prelude::BitVec;
+use log::{info, warn};
+use serde::Serialize;
+use spark_inference::inference::sam::image_inference::SamDecoded;
+use spark_inference::inference::yolo::inference_yolo_detect::YoloDetectResult;
+use spark_inference::inference::yolo::NMSImplement;
+use spark_inference::utils::graph::{self, Point, SamPrompt};
+use spark_media::{AudioFormat, Image};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::task::{spawn_blocking, JoinHandle};
+
+/// Highway and sidewalk, the classes after them are objects on or next to the surfaces.
+const SURFACE_CLASSES: usize = 2;
+
+/// Everything the pipeline learned about one image: the per-surface analysis
+/// and the sentence that would be spoken for it.
+#[derive(Debug, Clone, Serialize)]
+pub struct SceneAnalysis {
+    pub surfaces: Vec<SurfaceAnalysis>,
+    pub text: String,
+    pub language: Language,
+}
+
+/// The intermediate results behind a [`SceneAnalysis`], in the coordinates of the square SAM masks.
+/// Only the offline tools use them, to draw what the analysis was based on.
+#[derive(Debug, Clone, Default)]
+pub struct SceneDetections {
+    pub mask_size: u32,
+    pub highway: Vec<YoloDetectResult>,
+    pub sidewalk: Vec<YoloDetectResult>,
+    /// Boxes of the other classes, e.g. cars and people.
+    pub objects: Vec<YoloDetectResult>,
+    /// The masks the highway and sidewalk analysis ran on.
+    pub highway_mask: Option<BitVec>,
+    pub sidewalk_mask: Option<BitVec>,
+}
+
+/// Encoding of the synthesized speech, picked by the client per request.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct AudioOutput {
+    pub format: AudioFormat,
+    /// Keeps the sample rate of the TTS engine when unset.
+    pub sample_rate: Option<u32>,
+}
+
+/// Runs detection, segmentation and road analysis on an image and returns the structured result.
+/// With a session the announcements are smoothed across the client's frames, and the text is empty
+/// when everything worth saying was said moments ago. The text is written in `language`.
+/// `timestamp` is the client's capture time of the frame in milliseconds, the motion of tracked
+/// objects is measured between those, or between the times the frames are analysed without one.
+pub async fn analyse_scene(
+    image: Image,
+    engine: &'static InferenceEngine,
+    config: &'static Config,
+    language: Language,
+    session: Option<&Mutex<Session>>,
+    timestamp: Option<u64>,
+) -> anyhow::Result<SceneAnalysis> {
+    let (scene, _) =
+        analyse_scene_with_detections(image, engine, config, language, session, timestamp).await?;
+    Ok(scene)
+}
+
+/// Same as [`analyse_scene`], but also hands back the boxes and masks the analysis was based on.
+pub async fn analyse_scene_with_detections(
+    image: Image,
+    engine: &'static InferenceEngine,
+    config: &'static Config,
+    language: Language,
+    session: Option<&Mutex<Session>>,
+    timestamp: Option<u64>,
+) -> anyhow::Result<(SceneAnalysis, SceneDetections)> {
+    analyse_frame(image, None, engine, config, language, session, timestamp).await
+}
+
+/// [`analyse_scene_with_detections`] for several images without a session. With YOLO boxes
+/// prompting SAM the boxes of all images are detected first, in as few runs as the model
+/// allows. Each image gets its own result, an error in one does not fail the others: when the
+/// batch fails, every image detects its own boxes again.
+pub async fn analyse_scenes_with_detections(
+    images: Vec<Image>,
+    engine: &'static InferenceEngine,
+    config: &'static Config,
+    language: Language,
+) -> anyhow::Result<Vec<anyhow::Result<(SceneAnalysis, SceneDetections)>>> {
+    let boxes = match &engine.segmentation {
+        Segmentation::Sam { yolo, .. } => {
+            info!("Start Yolo detection of {} images", images.len());
+            let batch = images.clone();
+            let confidence = config.detection.confidence;
+            match spawn_blocking(move || yolo.inference_yolo_batch(batch, confidence)).await? {
+                Ok(boxes) => boxes.into_iter().map(Some).collect(),
+                Err(e) => {
+                    // One broken image fails the whole batch, its error then ends up in its own result
+                    warn!(
+                        "Batched Yolo detection failed, detecting one by one: {:?}",
+                        e
+                    );
+                    vec![None; images.len()]
+                }
+            }
+        }
+        Segmentation::YoloSeg(_) => vec![None; images.len()],
+    };
+
+    let mut scenes = Vec::with_capacity(images.len());
+    for (image, boxes) in images.into_iter().zip(boxes) {
+        scenes.push(analyse_frame(image, boxes, engine, config, language, None, None).await);
+    }
+    Ok(scenes)
+}
+
+/// The analysis of one frame, `boxes` being its YOLO detections if they are already known.
+async fn analyse_frame(
+    image: Image,
+    boxes: Option<Vec<YoloDetectResult>>,
+    engine: &'static InferenceEngine,
+    config: &'static Config,
+    language: Language,
+    session: Option<&Mutex<Session>>,
+    timestamp: Option<u64>,
+) -> anyhow::Result<(SceneAnalysis, SceneDetections)> {
+    let (image_width, image_height) = (image.get_width() as u32, image.get_height() as u32);
+    let (detection, analysis) = (&config.detection, &config.analysis);
+    let mask_size = detection.mask_size;
+
+    // Frames of one session are analysed one after another, in the order they asked for the lock,
+    // so the smoothing and the tracking see them in the order they arrived
+    let mut session = match session {
+        Some(session) => Some(session.lock().await),
+        None => None,
+    };
+
+    // The depth model runs next to the segmentation, its map lines up with the masks.
+    // Both are waited for, so no model keeps running for a frame that was already answered.
+    let depth = engine.depth.as_ref().map(|depth| {
+        let image = image.clone();
+        let size = mask_size as usize;
+        spawn_blocking(move || depth.estimate_depth(image, Some((size, size))))
+    });
+    let depth = async {
+        match depth {
+            Some(depth) => Ok::<_, anyhow::Error>(Some(Arc::new(depth.await??))),
+            None => Ok(None),
+        }
+    };
+    let segments = async {
+        match &engine.segmentation {
+            Segmentation::Sam { yolo, sam2 } => {
+                segment_with_sam(image, boxes, yolo, sam2, detection).await
+            }
+            Segmentation::YoloSeg(yolo_seg) => segment_with_yolo(image, yolo_seg, detection).await,
+        }
+    };
+    let (segments, depth) = tokio::join!(segments, depth);
+    let (segments, depth) = (segments?, depth?);
+
+    let Some(Segments {
+        highway: mut result_highway,
+        sidewalk: mut result_sidewalk,
+        objects: mut result_objects,
+        masks: mask,
+    }) = segments
+    else {
+        let scene = SceneAnalysis {
+            surfaces: vec![],
+            text: language.text(&Message::NothingInScene),
+            language,
+        };
+        let detections = SceneDetections {
+            mask_size,
+            ..Default::default()
+        };
+        return Ok((scene, detections));
+    };
+
+    // Rescale the yolo results to the mask size
+    for yolo in result_highway
+        .iter_mut()
+        .chain(result_sidewalk.iter_mut())
+        .chain(result_objects.iter_mut())
+    {
+        yolo.x = yolo.x / image_width as f32 * mask_size as f32;
+        yolo.y = yolo.y / image_height as f32 * mask_size as f32;
+        yolo.width = yolo.width / image_width as f32 * mask_size as f32;
+        yolo.height = yolo.height / image_height as f32 * mask_size as f32;
+    }
+
+    let best_highway = get_best_highway(&mask[0], mask_size, analysis);
+
+    // Filter sidewalk masks: first find those under user's feet (contains bottom center)
+    let user_x = mask_size as usize / 2;
+    let user_y = mask_size as usize - 1; // Bottom center pixel
+    let mut valid_sidewalks: Vec<_> = mask[1]
+        .iter()
+        .filter(|mask| mask[user_y * mask_size as usize + user_x]) // Check if contains user position
+        .collect();
+
+    // If none under feet, use all masks
+    if valid_sidewalks.is_empty() {
+        valid_sidewalks = mask[1].iter().collect();
+    }
+
+    // Select sidewalk with the largest area (most true bits)
+    let best_sidewalk_mask = valid_sidewalks.iter().max_by_key(|mask| mask.count_ones());
+
+    info!("Start analyzing masks");
+
+    let best_sidewalk = best_sidewalk_mask.copied();
+    let tracks = match session.as_deref_mut() {
+        Some(session) => session.track(&result_objects, timestamp),
+        None => Vec::new(),
+    };
+    let masks = [best_highway, best_sidewalk];
+    let tracks = by_surface(tracks, masks, mask_size, |track| {
+        (track.x, track.y + track.height / 2.0)
+    });
+    let objects = by_surface(result_objects.clone(), masks, mask_size, |object| {
+        (object.x, object.y + object.height / 2.0)
+    });
+
+    let mut surfaces = Vec::new();
+    for (((mask, detections, name), objects), tracks) in [
+        (best_highway, &result_highway, "Highway"),
+        (best_sidewalk, &result_sidewalk, "Sidewalk"),
+    ]
+    .into_iter()
+    .zip(objects)
+    .zip(tracks)
+    {
+        let Some(mask) = mask else {
+            continue;
+        };
+
+        // The surface boxes and the objects standing on the surface
+        let detections = detections
+            .iter()
+            .cloned()
+            .chain(objects)
+            .collect::<Vec<_>>();
+        let mut data = perform_core_analysis(mask, &detections, mask_size, mask_size, analysis);
+        if let Some(data) = &mut data {
+            data.depth = depth.clone();
+            data.tracks = tracks;
+        }
+        let surface =
+            describe_road_analysis(data, name, analysis, language, session.as_deref_mut()).await;
+        surfaces.push(surface);
+    }
+
+    let text = if surfaces.is_empty() {
+        language.text(&Message::NoRoad)
+    } else {
+        // Surfaces muted by the session have an empty description
+        surfaces
+            .iter()
+            .map(|surface| surface.description.as_str())
+            .filter(|description| !description.is_empty())
+            .collect::<Vec<_>>()
+            .join(language.surface_separator())
+    };
+    info!("Get natural language: {}", text);
+
+    let detections = SceneDetections {
+        mask_size,
+        highway_mask: best_highway.cloned(),
+        sidewalk_mask: best_sidewalk.cloned(),
+        highway: result_highway,
+        sidewalk: result_sidewalk,
+        objects: result_objects,
+    };
+    let scene = SceneAnalysis {
+        surfaces,
+        text,
+        language,
+    };
+
+    Ok((scene, detections))
+}
+
+/// Hands each object, detected or tracked, to the surface its `footpoint` stands on, objects on
+/// neither of them to the first surface that was found, so that every object is described once.
+fn by_surface<T>(
+    objects: Vec<T>,
+    masks: [Option<&BitVec>; 2],
+    mask_size: u32,
+    footpoint: impl Fn(&T) -> (f32, f32),
+) -> [Vec<T>; 2] {
+    let mut by_surface = [Vec::new(), Vec::new()];
+    let Some(first) = masks.iter().position(Option::is_some) else {
+        return by_surface;
+    };
+
+    let size = mask_size as usize;
+    for object in objects {
+        let (x, y) = footpoint(&object);
+        let pixel = (x >= 0.0 && y >= 0.0 && (x as usize) < size && (y as usize) < size)
+            .then(|| y as usize * size + x as usize);
+        let surface = masks
+            .iter()
+            .position(|mask| mask.zip(pixel).is_some_and(|(mask, pixel)| mask[pixel]))
+            .unwrap_or(first);
+        by_surface[surface].push(object);
+    }
+
+    by_surface
+}
+
+/// Boxes in image coordinates and the masks found for them.
+struct Segments {
+    highway: Vec<YoloDetectResult>,
+    sidewalk: Vec<YoloDetectResult>,
+    /// Objects that are not segmented, described on the surface they stand on and tracked.
+    objects: Vec<YoloDetectResult>,
+    /// The highway masks, then the sidewalk masks, `mask_size` squared.
+    masks: Vec<Vec<BitVec>>,
+}
+
+/// YOLO boxes prompting SAM, detected here unless `boxes` are given. `None` when YOLO found
+/// nothing at all.
+async fn segment_with_sam(
+    image: Image,
+    boxes: Option<Vec<YoloDetectResult>>,
+    yolo: &'static Detector,
+    sam: &'static Segmenter,
+    detection: &'static DetectionConfig,
+) -> anyhow::Result<Option<Segments>> {
+    let results = match boxes {
+        Some(boxes) => boxes,
+        None => {
+            info!("Start Yolo detection");
+            let image = image.clone();
+            spawn_blocking(move || {
+                Ok::<Vec<YoloDetectResult>, anyhow::Error>(
+                    yolo.inference_yolo(image, detection.confidence)?,
+                )
+            })
+            .await??
+        }
+    };
+
+    info!("detect results: {:?}", results.len());
+    if results.is_empty() {
+        return Ok(None);
+    }
+
+    // Each box counts for its most likely class only
+    let classes = results.iter().map(|result| result.score.len()).max();
+    let thresholds = detection.nms_thresholds(classes.unwrap_or(SURFACE_CLASSES));
+    let (surfaces, result_objects): (Vec<_>, Vec<_>) = results
+        .non_maximum_suppression(&thresholds, detection.nms_options())
+        .into_iter()
+        .partition(|result| result.class_id < SURFACE_CLASSES);
+    let (result_highway, result_sidewalk): (Vec<_>, Vec<_>) = surfaces
+        .into_iter()
+        .partition(|result| result.class_id == 0);
+    info!(
+        "Non max suppression results: {} {} {}",
+        result_highway.len(),
+        result_sidewalk.len(),
+        result_objects.len()
+    );
+
+    let masks = {
+        let highway_prompts = result_highway
+            .iter()
+            .map(|yolo| SamPrompt::from_box(prompt_box(yolo)))
+            .collect::<Vec<_>>();
+
+        let sidewalk_prompts = result_sidewalk
+            .iter()
+            .map(|yolo| {
+                let prompt = SamPrompt::from_box(prompt_box(yolo));
+                if !detection.exclude_highway_from_sidewalk {
+                    return prompt;
+                }
+
+                let [x1, y1, x2, y2] = yolo.corners();
+                result_highway
+                    .iter()
+                    .filter(|highway| {
+                        highway.x > x1 && highway.x < x2 && highway.y > y1 && highway.y < y2
+                    })
+                    .fold(prompt, |prompt, highway| {
+                        prompt.negative(Point {
+                            x: highway.x,
+                            y: highway.y,
+                        })
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        info!("Start SAM inference");
+        let handle: JoinHandle<anyhow::Result<Vec<Vec<SamDecoded>>>> = spawn_blocking(move || {
+            Ok(sam.inference_frame_scored(
+                image,
+                Some((detection.mask_size as i32, detection.mask_size as i32)),
+                vec![highway_prompts, sidewalk_prompts],
+            )?)
+        });
+
+        handle.await??
+    };
+
+    // Masks SAM itself doubts are more likely to describe something else than the road
+    let masks = masks
+        .into_iter()
+        .map(|decoded| {
+            decoded
+                .into_iter()
+                .filter_map(|decoded| decoded.into_best().ok())
+                .filter(|best| best.iou >= detection.min_mask_iou)
+                .map(|best| best.mask)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    info!(
+        "SAM inference results: {} {}",
+        masks[0].len(),
+        masks[1].len()
+    );
+
+    Ok(Some(Segments {
+        highway: result_highway,
+        sidewalk: result_sidewalk,
+        objects: result_objects,
+        masks,
+    }))
+}
+
+fn prompt_box(yolo: &YoloDetectResult) -> graph::Box<f32> {
+    graph::Box {
+        x: yolo.x,
+        y: yolo.y,
+        width: yolo.width,
+        height: yolo.height,
+    }
+}
+
+/// Boxes and masks of the YOLO segmentation model, faster than SAM but with coarser edges.
+/// Only the surfaces are segmented, so no objects are tracked. `None` when no box survived NMS.
+async fn segment_with_yolo(
+    image: Image,
+    yolo_seg: &'static SegmentDetector,
+    detection: &'static DetectionConfig,
+) -> anyhow::Result<Option<Segments>> {
+    info!("Start Yolo segmentation");
+    let mask_size = detection.mask_size as i32;
+    let results = spawn_blocking(move || {
+        yolo_seg.inference_yolo(
+            image,
+            &detection.nms_thresholds(SURFACE_CLASSES),
+            detection.nms_options(),
+            detection.mask_probability,
+            Some((mask_size, mask_size)),
+        )
+    })
+    .await??;
+
+    info!("Yolo segmentation results: {}", results.len());
+    if results.is_empty() {
+        return Ok(None);
+    }
+
+    // Each box counts for its most likely class only
+    let (highway, sidewalk): (Vec<_>, Vec<_>) = results
+        .into_iter()
+        .partition(|result| result.detection.class_id == 0);
+    let (highway, highway_masks): (Vec<_>, Vec<_>) = highway
+        .into_iter()
+        .map(|result| (result.detection, result.mask))
+        .unzip();
+    let (sidewalk, sidewalk_masks): (Vec<_>, Vec<_>) = sidewalk
+        .into_iter()
+        .map(|result| (result.detection, result.mask))
+        .unzip();
+
+    Ok(Some(Segments {
+        highway,
+        sidewalk,
+        objects: Vec::new(),
+        masks: vec![highway_masks, sidewalk_masks],
+    }))
+}
+
+/// Converts the composed description of a scene into audio encoded as `output`, spoken by the
+/// TTS engine of `language`. Empty text yields no audio at all.
+pub async fn synthesize(
+    text: String,
+    language: Language,
+    output: AudioOutput,
+    engine: &'static InferenceEngine,
+) -> anyhow::Result<Vec<u8>> {
+    if text.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let tts = engine.tts(language)?;
+    let result: anyhow::Result<Vec<u8>> = spawn_blocking(move || {
+        Ok(tts.speak_as(text.as_str(), output.format, output.sample_rate)?)
+    })
+    .await?;
+
+    Ok(result?)
+}
+
+pub async fn analyse_image(
+    image: Image,
+    engine: &'static InferenceEngine,
+    config: &'static Config,
+    language: Language,
+    output: AudioOutput,
+    session: Option<&Mutex<Session>>,
+) -> anyhow::Result<Vec<u8>> {
+    let scene = analyse_scene(image, engine, config, language, session, None).await?;
+    synthesize(scene.text, language, output, engine).await
+}
+
+#[tokio::test]
+async fn test_growing_object_warns_on_fake_backend() {
+    use crate::engine::InferenceEngine;
+    use spark_inference::inference::fake::{FakeSegmenter, FakeSpeech};
+    use spark_inference::inference::yolo::inference_yolo_detect::YoloDetectInference;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// The sidewalk of the fake detector and a car on it that grows and sinks towards the bottom
+    /// of the image with every frame.
+    struct ApproachingCar(AtomicUsize);
+
+    impl YoloDetectInference for ApproachingCar {
+        fn inference_yolo(
+            &self,
+            image: Image,
+            _confidence: f32,
+        ) -> anyhow::Result<Vec<YoloDetectResult>> {
+            let (width, height) = image.get_size();
+            let (width, height) = (width as f32, height as f32);
+            let frame = self.0.fetch_add(1, Ordering::SeqCst) as f32;
+            let (y, size) = (0.55 + 0.05 * frame, 0.1 * 1.3f32.powf(frame));
+
+            Ok(vec![
+                YoloDetectResult {
+                    score: vec![0.05, 0.9, 0.0],
+                    class_id: 1,
+                    label: "sidewalk".to_string(),
+                    x: 0.5 * width,
+                    y: 0.75 * height,
+                    width: 0.6 * width,
+                    height: 0.5 * height,
+                },
+                YoloDetectResult {
+                    score: vec![0.0, 0.0, 0.9],
+                    class_id: 2,
+                    label: "car".to_string(),
+                    x: 0.5 * width,
+                    y: y * height,
+                    width: size * width,
+                    height: size * height,
+                },
+            ])
+        }
+    }
+
+    let config: &'static Config = Box::leak(Box::new(
+        Config::from_source(
+            "[model]\nbackend = \"fake\"\n[detection]\nmask_size = 64",
+            Vec::new(),
+        )
+        .unwrap(),
+    ));
+    let engine: &'static InferenceEngine = Box::leak(Box::new(InferenceEngine {
+        segmentation: Segmentation::Sam {
+            yolo: Box::new(ApproachingCar(AtomicUsize::new(0))),
+            sam2: Box::new(FakeSegmenter),
+        },
+        depth: None,
+        tts: Box::new(FakeSpeech::default()),
+        tts_zh: None,
+    }));
+    let session = Mutex::new(Session::new(&config.session));
+
+    // Frames captured every 250 ms, the warning comes once the track is confirmed
+    let mut texts = Vec::new();
+    for frame in 0..6 {
+        let image = Image::from_bytes(&crate::test_image()).unwrap();
+        let timestamp = Some(250 * frame);
+        let scene = analyse_scene(
+            image,
+            engine,
+            config,
+            Language::English,
+            Some(&session),
+            timestamp,
+        )
+        .await
+        .unwrap();
+        texts.push(scene.text);
+    }
+
+    assert!(!texts[0].contains("approaching"));
+    assert!(texts
+        .iter()
+        .any(|text| text.contains("Warning: a car approaching")));
+
+    // Without a session nothing is tracked
+    let image = Image::from_bytes(&crate::test_image()).unwrap();
+    let scene = analyse_scene(image, engine, config, Language::English, None, None)
+        .await
+        .unwrap();
+    assert!(!scene.text.contains("approaching"));
+}
+
+#[tokio::test]
+async fn test_objects_are_described_on_their_surface() {
+    use crate::engine::InferenceEngine;
+    use spark_inference::inference::fake::{FakeDetector, FakeSegmenter, FakeSpeech};
+
+    let detection = |class_id: usize, label: &str, x: f32, y: f32, width: f32, height: f32| {
+        let mut score = vec![0.0; 4];
+        score[class_id] = 0.9;
+        YoloDetectResult {
+            score,
+            class_id,
+            label: label.to_string(),
+            x,
+            y,
+            width,
+            height,
+        }
+    };
+    // A road in the distance with a car on it, the sidewalk underfoot with two people
+    let detector = FakeDetector::new(vec![
+        detection(0, "highway", 0.5, 0.3, 1.0, 0.4),
+        detection(1, "sidewalk", 0.5, 0.75, 0.6, 0.5),
+        detection(2, "car", 0.3, 0.3, 0.2, 0.1),
+        detection(3, "person", 0.4, 0.7, 0.05, 0.2),
+        detection(3, "person", 0.6, 0.8, 0.05, 0.2),
+    ]);
+
+    let config: &'static Config = Box::leak(Box::new(
+        Config::from_source(
+            "[model]\nbackend = \"fake\"\n[detection]\nmask_size = 64",
+            Vec::new(),
+        )
+        .unwrap(),
+    ));
+    let engine: &'static InferenceEngine = Box::leak(Box::new(InferenceEngine {
+        segmentation: Segmentation::Sam {
+            yolo: Box::new(detector),
+            sam2: Box::new(FakeSegmenter),
+        },
+        depth: None,
+        tts: Box::new(FakeSpeech::default()),
+        tts_zh: None,
+    }));
+
+    let image = Image::from_bytes(&crate::test_image()).unwrap();
+    let scene = analyse_scene(image, engine, config, Language::English, None, None)
+        .await
+        .unwrap();
+
+    assert_eq!(scene.surfaces.len(), 2);
+    let (highway, sidewalk) = (
+        &scene.surfaces[0].description,
+        &scene.surfaces[1].description,
+    );
+    assert!(highway.contains("a car, "), "{}", highway);
+    assert!(!highway.contains("people"), "{}", highway);
+    assert!(sidewalk.contains("two people"), "{}", sidewalk);
+    assert!(!sidewalk.contains("car"), "{}", sidewalk);
+}
+
+#[tokio::test]
+async fn test_batch_failure_only_fails_its_image() {
+    use spark_inference::inference::fake::{FakeDetector, FakeSegmenter, FakeSpeech};
+    use spark_inference::inference::yolo::inference_yolo_detect::YoloDetectInference;
+
+    /// The fake detector, failing on every image that is not 64 pixels wide.
+    struct PickyDetector(FakeDetector);
+
+    impl YoloDetectInference for PickyDetector {
+        fn inference_yolo(
+            &self,
+            image: Image,
+            confidence: f32,
+        ) -> anyhow::Result<Vec<YoloDetectResult>> {
+            anyhow::ensure!(image.get_width() == 64, "Unexpected width");
+            self.0.inference_yolo(image, confidence)
+        }
+    }
+
+    let config: &'static Config = Box::leak(Box::new(
+        Config::from_source("[model]\nbackend = \"fake\"", Vec::new()).unwrap(),
+    ));
+    let engine: &'static InferenceEngine = Box::leak(Box::new(InferenceEngine {
+        segmentation: Segmentation::Sam {
+            yolo: Box::new(PickyDetector(FakeDetector::default())),
+            sam2: Box::new(FakeSegmenter),
+        },
+        depth: None,
+        tts: Box::new(FakeSpeech::default()),
+        tts_zh: None,
+    }));
+
+    let mut narrow = b"P6\n32 48\n255\n".to_vec();
+    narrow.resize(narrow.len() + 32 * 48 * 3, 0x80);
+    let images = [crate::test_image(), narrow, crate::test_image()]
+        .iter()
+        .map(|bytes| Image::from_bytes(bytes).unwrap())
+        .collect();
+
+    let scenes = analyse_scenes_with_detections(images, engine, config, Language::English)
+        .await
+        .unwrap();
+    assert_eq!(scenes.len(), 3);
+    assert!(scenes[0].is_ok() && scenes[2].is_ok());
+    assert!(scenes[1].is_err());
+}
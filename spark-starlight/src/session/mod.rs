@@ -0,0 +1,176 @@
+pub(crate) mod smoothing;
+
+use crate::config::SessionConfig;
+use crate::session::smoothing::Session;
+use actix_web::cookie::Cookie;
+use actix_web::{HttpRequest, HttpResponseBuilder};
+use log::info;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Header a client sends (and receives) to keep its frames in one session.
+pub const SESSION_HEADER: &str = "X-Session-Id";
+/// Cookie carrying the same id for clients that prefer cookies over headers.
+pub const SESSION_COOKIE: &str = "starlight_session";
+
+type SharedSession = Arc<tokio::sync::Mutex<Session>>;
+
+struct SessionEntry {
+    session: SharedSession,
+    last_seen: Instant,
+}
+
+/// All live sessions, keyed by the id the client presents.
+/// Sessions without requests for `idle_timeout_secs` are dropped on the next lookup, and at most
+/// `max_sessions` are kept, so clients that never send their id back cannot exhaust the memory.
+pub struct SessionStore {
+    config: &'static SessionConfig,
+    sessions: Mutex<HashMap<String, SessionEntry>>,
+}
+
+impl SessionStore {
+    pub fn new(config: &'static SessionConfig) -> Self {
+        Self {
+            config,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Finds the session of the request, creating one (with a fresh id if the client sent none).
+    /// The session is locked per frame by the caller, so frames of one client are analysed in order.
+    pub fn resolve(&self, request: &HttpRequest) -> (String, SharedSession) {
+        let id = session_id(request).unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let now = Instant::now();
+        let idle_timeout = Duration::from_secs(self.config.idle_timeout_secs);
+
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, entry| now.duration_since(entry.last_seen) < idle_timeout);
+
+        if !sessions.contains_key(&id) && sessions.len() >= self.config.max_sessions {
+            let oldest = sessions
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_seen)
+                .map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                info!("Session limit reached, dropping session {}", oldest);
+                sessions.remove(&oldest);
+            }
+        }
+
+        let entry = sessions.entry(id.clone()).or_insert_with(|| {
+            info!("Creating session {}", id);
+            SessionEntry {
+                session: Arc::new(tokio::sync::Mutex::new(Session::new(self.config))),
+                last_seen: now,
+            }
+        });
+        entry.last_seen = now;
+
+        (id, entry.session.clone())
+    }
+}
+
+/// Reads the session id from the header first, then from the cookie.
+/// Ids that are empty, overly long or not plain ASCII are ignored, an invalid header falls
+/// back to the cookie.
+fn session_id(request: &HttpRequest) -> Option<String> {
+    let header = request
+        .headers()
+        .get(SESSION_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_string());
+    let cookie = request
+        .cookie(SESSION_COOKIE)
+        .map(|cookie| cookie.value().to_string());
+
+    header
+        .filter(|id| is_valid_session_id(id))
+        .or_else(|| cookie.filter(|id| is_valid_session_id(id)))
+}
+
+fn is_valid_session_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 128
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Hands the session id back to the client as both header and cookie.
+pub fn attach_session_id(response: &mut HttpResponseBuilder, id: &str) {
+    response.insert_header((SESSION_HEADER, id)).cookie(
+        Cookie::build(SESSION_COOKIE, id.to_string())
+            .path("/")
+            .finish(),
+    );
+}
+
+#[test]
+fn test_session_store_drops_least_recent() {
+    use actix_web::test::TestRequest;
+
+    let config: &'static SessionConfig = Box::leak(Box::new(SessionConfig {
+        max_sessions: 2,
+        ..SessionConfig::default()
+    }));
+    let store = SessionStore::new(config);
+    let request = |id: &str| {
+        TestRequest::default()
+            .insert_header((SESSION_HEADER, id))
+            .to_http_request()
+    };
+
+    let (_, first) = store.resolve(&request("first"));
+    store.resolve(&request("second"));
+    // Seen again, so the second one is the least recent
+    let (_, again) = store.resolve(&request("first"));
+    assert!(Arc::ptr_eq(&first, &again));
+
+    store.resolve(&request("third"));
+    {
+        let sessions = store.sessions.lock().unwrap();
+        assert!(sessions.contains_key("first") && sessions.contains_key("third"));
+        assert!(!sessions.contains_key("second"));
+    }
+
+    // Clients without an id take a slot each, but never more than the limit
+    for _ in 0..10 {
+        store.resolve(&TestRequest::default().to_http_request());
+    }
+    assert_eq!(store.sessions.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn test_invalid_header_falls_back_to_cookie() {
+    use actix_web::test::TestRequest;
+
+    let request = |header: &str| {
+        TestRequest::default()
+            .insert_header((SESSION_HEADER, header))
+            .cookie(Cookie::new(SESSION_COOKIE, "from-cookie"))
+            .to_http_request()
+    };
+
+    assert_eq!(
+        session_id(&request("from-header")).as_deref(),
+        Some("from-header")
+    );
+    assert_eq!(session_id(&request("")).as_deref(), Some("from-cookie"));
+    assert_eq!(
+        session_id(&request("not/valid")).as_deref(),
+        Some("from-cookie")
+    );
+    assert_eq!(
+        session_id(&request(&"a".repeat(129))).as_deref(),
+        Some("from-cookie")
+    );
+    assert_eq!(
+        session_id(
+            &TestRequest::default()
+                .insert_header((SESSION_HEADER, "not valid"))
+                .to_http_request()
+        ),
+        None
+    );
+}
@@ -0,0 +1,372 @@
+use crate::config::SessionConfig;
+use crate::detect::analysis::DescriptionPart;
+use crate::detect::property::analyse_result::RoadAnalysisData;
+use crate::detect::property::approach::TrackedObject;
+use crate::detect::property::direction::DirectionCategory;
+use crate::detect::property::road_shape::RoadShape;
+use spark_inference::inference::tracker::Tracker;
+use spark_inference::inference::yolo::inference_yolo_detect::YoloDetectResult;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Weight of the newest frame interval in the running average that turns motion per frame
+/// into motion per second.
+const FRAME_INTERVAL_SMOOTHING: f32 = 0.2;
+
+/// A judgment with hysteresis: the announced value only changes once a different
+/// value has been observed in `switch_frames` consecutive frames.
+#[derive(Debug, Clone)]
+pub struct Debounced<T> {
+    stable: Option<T>,
+    candidate: Option<(T, usize)>,
+}
+
+impl<T> Default for Debounced<T> {
+    fn default() -> Self {
+        Self {
+            stable: None,
+            candidate: None,
+        }
+    }
+}
+
+impl<T: PartialEq + Clone> Debounced<T> {
+    /// Feeds the value observed in the current frame and returns the value to announce.
+    pub fn update(&mut self, value: T, switch_frames: usize) -> T {
+        match &self.stable {
+            Some(stable) if *stable == value => self.candidate = None,
+            Some(_) => {
+                let seen = match &mut self.candidate {
+                    Some((candidate, seen)) if *candidate == value => {
+                        *seen += 1;
+                        *seen
+                    }
+                    _ => {
+                        self.candidate = Some((value.clone(), 1));
+                        1
+                    }
+                };
+
+                if seen >= switch_frames {
+                    self.stable = Some(value);
+                    self.candidate = None;
+                }
+            }
+            None => self.stable = Some(value),
+        }
+
+        self.stable.clone().unwrap()
+    }
+}
+
+/// Recent frames of one surface ("Highway", "Sidewalk") and the judgments smoothed over them.
+#[derive(Debug, Default)]
+struct SurfaceHistory {
+    frames: VecDeque<RoadAnalysisData>,
+    shape: Debounced<RoadShape>,
+    start: Debounced<(bool, Option<DirectionCategory>)>,
+    has_obstacles: Debounced<bool>,
+}
+
+impl SurfaceHistory {
+    fn smooth(&mut self, mut data: RoadAnalysisData, config: &SessionConfig) -> RoadAnalysisData {
+        let raw = data.clone();
+
+        // Without a centerline there is nothing to hold steady, the describers report the missing road.
+        if !data.center_lines.is_empty() {
+            let switch_frames = config.switch_frames;
+            data.shape = self.shape.update(data.shape, switch_frames);
+            (data.starts_at_feet, data.start_direction) = self
+                .start
+                .update((data.starts_at_feet, data.start_direction), switch_frames);
+
+            let has_obstacles = self
+                .has_obstacles
+                .update(!data.obstacles.is_empty(), switch_frames);
+            if !has_obstacles {
+                data.obstacles.clear();
+            } else if data.obstacles.is_empty() {
+                // Still considered blocked, keep announcing the most recent obstacles seen.
+                if let Some(frame) = self.frames.iter().rev().find(|f| !f.obstacles.is_empty()) {
+                    data.obstacles = frame.obstacles.clone();
+                }
+            }
+        }
+
+        self.frames.push_back(raw);
+        while self.frames.len() > config.history_size {
+            self.frames.pop_front();
+        }
+
+        data
+    }
+}
+
+/// When a frame was taken, what the motion of the tracked objects is measured against.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum FrameTime {
+    /// The client's capture timestamp in milliseconds, independent of how long the frame waited.
+    Captured(u64),
+    /// When the server got to the frame, for clients that send no timestamp.
+    Received(Instant),
+}
+
+impl FrameTime {
+    /// Time since `earlier`, `None` when the two come from different clocks or `earlier` is later.
+    fn since(self, earlier: FrameTime) -> Option<Duration> {
+        match (self, earlier) {
+            (FrameTime::Captured(now), FrameTime::Captured(earlier)) => {
+                now.checked_sub(earlier).map(Duration::from_millis)
+            }
+            (FrameTime::Received(now), FrameTime::Received(earlier)) => {
+                now.checked_duration_since(earlier)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The objects of the recent frames, followed across them independent of the surfaces.
+#[derive(Debug, Default)]
+struct ObjectHistory {
+    /// Created with the first frame, from the tracker options of the session config.
+    tracker: Option<Tracker>,
+    last_frame: Option<FrameTime>,
+    /// Running average of the seconds between two frames, unknown until the second frame.
+    frame_secs: Option<f32>,
+}
+
+impl ObjectHistory {
+    /// Follows the detections of the frame taken at `now` and returns the confirmed tracks
+    /// with their motion per second. Empty as long as the frame rate is unknown.
+    fn track(
+        &mut self,
+        detections: &[YoloDetectResult],
+        config: &SessionConfig,
+        now: FrameTime,
+    ) -> Vec<TrackedObject> {
+        let tracker = self
+            .tracker
+            .get_or_insert_with(|| Tracker::new(config.tracker.clone()));
+
+        if let Some(last) = self.last_frame {
+            // A long pause, a frame from the past or a switch of clocks, the tracks are stale
+            match now
+                .since(last)
+                .filter(|gap| *gap <= Duration::from_millis(config.max_frame_gap_ms))
+            {
+                None => {
+                    tracker.clear();
+                    self.frame_secs = None;
+                }
+                Some(gap) if !gap.is_zero() => {
+                    let secs = gap.as_secs_f32();
+                    self.frame_secs = Some(match self.frame_secs {
+                        Some(average) => average + FRAME_INTERVAL_SMOOTHING * (secs - average),
+                        None => secs,
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+        self.last_frame = Some(now);
+
+        let tracks = tracker.update(detections);
+        match self.frame_secs {
+            Some(frame_secs) => tracks
+                .iter()
+                .map(|track| TrackedObject::from_track(track, frame_secs))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct AnnouncedWarning {
+    text: String,
+    severity: u8,
+    at: Instant,
+}
+
+/// Server side state of one client, shared by all of its requests.
+#[derive(Debug)]
+pub struct Session {
+    config: &'static SessionConfig,
+    surfaces: HashMap<String, SurfaceHistory>,
+    objects: ObjectHistory,
+    warnings: HashMap<(String, &'static str), AnnouncedWarning>,
+}
+
+impl Session {
+    pub fn new(config: &'static SessionConfig) -> Self {
+        Self {
+            config,
+            surfaces: HashMap::new(),
+            objects: ObjectHistory::default(),
+            warnings: HashMap::new(),
+        }
+    }
+
+    /// Records the analysis of the current frame and returns it with shape, start position
+    /// and obstacle judgments smoothed over the recent frames of the same surface.
+    pub fn smooth(&mut self, surface: &str, data: RoadAnalysisData) -> RoadAnalysisData {
+        self.surfaces
+            .entry(surface.to_string())
+            .or_default()
+            .smooth(data, self.config)
+    }
+
+    /// Follows the objects of the current frame, e.g. cars and people, across the recent frames
+    /// and returns the ones tracked long enough with their motion per second. The motion is
+    /// measured between the client's `timestamp`s (milliseconds) of the frames, or between the
+    /// times the frames were analysed when the client sends none.
+    pub fn track(
+        &mut self,
+        objects: &[YoloDetectResult],
+        timestamp: Option<u64>,
+    ) -> Vec<TrackedObject> {
+        let now = match timestamp {
+            Some(timestamp) => FrameTime::Captured(timestamp),
+            None => FrameTime::Received(Instant::now()),
+        };
+        self.track_at(objects, now)
+    }
+
+    fn track_at(&mut self, objects: &[YoloDetectResult], now: FrameTime) -> Vec<TrackedObject> {
+        self.objects.track(objects, self.config, now)
+    }
+
+    /// Drops warnings the user heard within the cooldown, unless they got more severe
+    /// or changed at the same severity (e.g. the left edge became the right edge).
+    pub fn filter_warnings(
+        &mut self,
+        surface: &str,
+        parts: Vec<DescriptionPart>,
+    ) -> Vec<DescriptionPart> {
+        self.filter_warnings_at(surface, parts, Instant::now())
+    }
+
+    fn filter_warnings_at(
+        &mut self,
+        surface: &str,
+        parts: Vec<DescriptionPart>,
+        now: Instant,
+    ) -> Vec<DescriptionPart> {
+        let cooldown = Duration::from_secs(self.config.warning_cooldown_secs);
+
+        parts
+            .into_iter()
+            .filter(|part| {
+                let Some(severity) = part.severity else {
+                    return true;
+                };
+
+                let key = (surface.to_string(), part.source);
+                let muted = self.warnings.get(&key).is_some_and(|previous| {
+                    now.duration_since(previous.at) < cooldown
+                        && (severity < previous.severity
+                            || (severity == previous.severity && previous.text == part.text))
+                });
+                if muted {
+                    return false;
+                }
+
+                self.warnings.insert(
+                    key,
+                    AnnouncedWarning {
+                        text: part.text.clone(),
+                        severity,
+                        at: now,
+                    },
+                );
+                true
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_debounced_switches_after_consecutive_frames() {
+    let mut shape = Debounced::default();
+    assert_eq!(shape.update(RoadShape::Straight, 3), RoadShape::Straight);
+    assert_eq!(shape.update(RoadShape::CurvesLeft, 3), RoadShape::Straight);
+    assert_eq!(shape.update(RoadShape::Straight, 3), RoadShape::Straight);
+    assert_eq!(shape.update(RoadShape::CurvesLeft, 3), RoadShape::Straight);
+    assert_eq!(shape.update(RoadShape::CurvesLeft, 3), RoadShape::Straight);
+    assert_eq!(
+        shape.update(RoadShape::CurvesLeft, 3),
+        RoadShape::CurvesLeft
+    );
+}
+
+#[test]
+fn test_repeated_warnings_are_muted_until_worse() {
+    let config: &'static SessionConfig = Box::leak(Box::new(SessionConfig::default()));
+    let mut session = Session::new(config);
+    let part = |text: &str, severity| DescriptionPart {
+        source: "Obstacle",
+        text: text.to_string(),
+        severity: Some(severity),
+    };
+    let start = Instant::now();
+
+    let kept = session.filter_warnings_at("Sidewalk", vec![part("gap, Near", 2)], start);
+    assert_eq!(kept.len(), 1);
+
+    let later = start + Duration::from_secs(1);
+    let kept = session.filter_warnings_at("Sidewalk", vec![part("gap, Near", 2)], later);
+    assert!(kept.is_empty());
+    let kept = session.filter_warnings_at("Sidewalk", vec![part("gap, Far", 1)], later);
+    assert!(kept.is_empty());
+    let kept = session.filter_warnings_at("Sidewalk", vec![part("gap, Very near", 4)], later);
+    assert_eq!(kept.len(), 1);
+
+    let expired = later + Duration::from_secs(config.warning_cooldown_secs);
+    let kept = session.filter_warnings_at("Sidewalk", vec![part("gap, Very near", 4)], expired);
+    assert_eq!(kept.len(), 1);
+}
+
+#[test]
+fn test_tracks_move_per_second() {
+    let config: &'static SessionConfig = Box::leak(Box::new(SessionConfig::default()));
+    let mut session = Session::new(config);
+    // A bicycle growing and sinking towards the bottom of the frame, ten frames a second
+    let frame = |step: f32| {
+        [YoloDetectResult {
+            score: vec![0.0, 0.0, 0.9],
+            class_id: 2,
+            label: "bicycle".to_string(),
+            x: 100.0,
+            y: 80.0 + step,
+            width: 20.0 + step,
+            height: 20.0 + step,
+        }]
+    };
+    let start = Instant::now();
+    let at = |step: u64| FrameTime::Received(start + Duration::from_millis(100 * step));
+
+    let mut tracks = Vec::new();
+    for step in 0..10 {
+        tracks = session.track_at(&frame(step as f32), at(step));
+        if step < 2 {
+            assert!(tracks.is_empty());
+        }
+    }
+    assert_eq!(tracks.len(), 1);
+    assert!(tracks[0].velocity_y > 5.0 && tracks[0].growth > 0.1);
+
+    // After a pause the motion seen before says nothing anymore
+    assert!(session.track_at(&frame(10.0), at(40)).is_empty());
+
+    // The same motion between capture timestamps
+    let mut session = Session::new(config);
+    for step in 0..10 {
+        tracks = session.track(&frame(step as f32), Some(5_000 + 100 * step));
+    }
+    assert_eq!(tracks.len(), 1);
+    assert!(tracks[0].velocity_y > 5.0 && tracks[0].growth > 0.1);
+    // A frame from the past or from another clock starts over
+    assert!(session.track(&frame(10.0), Some(4_000)).is_empty());
+    assert!(session.track(&frame(10.0), None).is_empty());
+}
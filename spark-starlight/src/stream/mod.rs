@@ -0,0 +1,448 @@
+use crate::audio_output;
+use crate::config::{Config, StreamConfig};
+use crate::detect::analysis::locale::Language;
+use crate::engine::InferenceEngine;
+use crate::pipeline::{analyse_scene, synthesize, AudioOutput};
+use crate::session::smoothing::Session;
+use crate::session::{SessionStore, SESSION_HEADER};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_ws::AggregatedMessage;
+use bytes::Bytes;
+use futures::StreamExt;
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use spark_media::Image;
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, Mutex, Semaphore};
+use tokio::task::spawn_blocking;
+
+/// Length of the big-endian millisecond timestamp in front of every JPEG frame.
+const TIMESTAMP_LEN: usize = 8;
+
+/// Inference slots shared by all streaming clients. Waiters are served in FIFO order and every
+/// client waits with at most one frame, so a fast client cannot starve the others.
+pub struct InferenceSlots(Semaphore);
+
+impl InferenceSlots {
+    pub fn new(config: &StreamConfig) -> Self {
+        Self(Semaphore::new(config.max_concurrent_inferences))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StreamQuery {
+    /// Language of the descriptions, `en` or `zh`.
+    #[serde(default)]
+    lang: Language,
+    /// Push the description text as JSON messages.
+    #[serde(default = "default_true")]
+    text: bool,
+    /// Push the spoken description as binary messages.
+    #[serde(default)]
+    audio: bool,
+    /// Audio encoding: `wav` (32-bit float, default), `wav16`, `opus`, `m4a` or `mp3`.
+    format: Option<String>,
+    /// Sample rate of the audio in Hz, the TTS engine's own rate when unset.
+    rate: Option<u32>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Messages pushed to the client as JSON text. A `description` with `audio` set is
+/// followed by one binary message holding the encoded speech.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage<'a> {
+    Description {
+        /// Timestamp of the frame the description belongs to.
+        timestamp: u64,
+        text: Option<&'a str>,
+        audio: bool,
+        content_type: Option<&'static str>,
+    },
+    Error {
+        timestamp: Option<u64>,
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Frame {
+    timestamp: u64,
+    received: Instant,
+    jpeg: Bytes,
+}
+
+impl Frame {
+    /// Whether the frame waited so long that its description would come too late.
+    fn is_stale(&self, now: Instant, stale_after: Duration) -> bool {
+        now.duration_since(self.received) > stale_after
+    }
+}
+
+/// What a binary message of the client turned out to be.
+#[derive(Debug, PartialEq)]
+enum Incoming {
+    Frame {
+        timestamp: u64,
+        jpeg: Bytes,
+    },
+    /// Not older than the frame queued or analysed before it, i.e. arrived out of order.
+    Outdated,
+    /// Too short to hold a timestamp and an image.
+    Malformed,
+}
+
+/// Splits a binary message into its timestamp and JPEG, `last_timestamp` being the one of
+/// the frame accepted before.
+fn parse_frame(data: &Bytes, last_timestamp: Option<u64>) -> Incoming {
+    let Some((timestamp, jpeg)) = data
+        .split_first_chunk::<TIMESTAMP_LEN>()
+        .filter(|(_, jpeg)| !jpeg.is_empty())
+    else {
+        return Incoming::Malformed;
+    };
+
+    let timestamp = u64::from_be_bytes(*timestamp);
+    if last_timestamp.is_some_and(|last| timestamp <= last) {
+        return Incoming::Outdated;
+    }
+    Incoming::Frame {
+        timestamp,
+        jpeg: data.slice(TIMESTAMP_LEN..TIMESTAMP_LEN + jpeg.len()),
+    }
+}
+
+/// The newest frame sent so far, `frame` unless a newer one replaced it in the meantime.
+fn newest_frame(latest: &mut watch::Receiver<Option<Frame>>, frame: Frame) -> Frame {
+    if !latest.has_changed().unwrap_or(false) {
+        return frame;
+    }
+    latest.borrow_and_update().clone().unwrap_or(frame)
+}
+
+/// Whether a description is worth pushing: empty text means the session has nothing new
+/// to announce, and the client already heard the one it was sent last.
+fn is_new_description(text: &str, last_text: Option<&str>) -> bool {
+    !text.is_empty() && last_text != Some(text)
+}
+
+/// Everything the analysing side of one connection needs.
+struct StreamWorker {
+    engine: &'static InferenceEngine,
+    config: &'static Config,
+    slots: &'static InferenceSlots,
+    session: std::sync::Arc<Mutex<Session>>,
+    language: Language,
+    text: bool,
+    audio: Option<AudioOutput>,
+    ws: actix_ws::Session,
+}
+
+/// `GET /stream`: upgrades to a WebSocket. The client sends binary messages made of an 8 byte
+/// big-endian timestamp in milliseconds followed by a JPEG image. Only the newest frame is kept
+/// while an inference is running, and a description is only pushed when it differs from the last one.
+pub async fn stream_handler(
+    engine: web::Data<&'static InferenceEngine>,
+    config: web::Data<&'static Config>,
+    sessions: web::Data<&'static SessionStore>,
+    slots: web::Data<&'static InferenceSlots>,
+    request: HttpRequest,
+    query: web::Query<StreamQuery>,
+    body: web::Payload,
+) -> Result<HttpResponse, Error> {
+    info!("Received WebSocket request on /stream");
+    let output = match audio_output(query.format.as_deref(), query.rate) {
+        Ok(output) => output,
+        Err(response) => return Ok(response),
+    };
+    if !query.text && !query.audio {
+        return Ok(
+            HttpResponse::BadRequest().body("At least one of text and audio must be enabled")
+        );
+    }
+
+    let (session_id, session) = sessions.resolve(&request);
+    let (mut response, ws, messages) = actix_ws::handle(&request, body)?;
+    if let (Ok(name), Ok(value)) = (
+        HeaderName::try_from(SESSION_HEADER),
+        HeaderValue::from_str(&session_id),
+    ) {
+        response.headers_mut().insert(name, value);
+    }
+
+    let config: &'static Config = **config;
+    let messages = messages
+        .max_frame_size(config.stream.max_frame_size)
+        .aggregate_continuations()
+        .max_continuation_size(config.stream.max_frame_size);
+    let (frames, latest) = watch::channel(None);
+
+    let worker = StreamWorker {
+        engine: **engine,
+        config,
+        slots: **slots,
+        session,
+        language: query.lang,
+        text: query.text,
+        audio: query.audio.then_some(output),
+        ws: ws.clone(),
+    };
+    actix_web::rt::spawn(worker.run(latest));
+    actix_web::rt::spawn(receive_frames(
+        ws,
+        messages,
+        frames,
+        &config.stream,
+        session_id,
+    ));
+
+    Ok(response)
+}
+
+/// Reads the client's messages and keeps only the newest frame for the worker.
+/// Dropping `frames` on return stops the worker.
+async fn receive_frames(
+    mut ws: actix_ws::Session,
+    mut messages: actix_ws::AggregatedMessageStream,
+    frames: watch::Sender<Option<Frame>>,
+    config: &'static StreamConfig,
+    session_id: String,
+) {
+    let timeout = Duration::from_secs(config.client_timeout_secs);
+    let mut last_timestamp = None;
+    let mut received = 0usize;
+
+    loop {
+        let message = match tokio::time::timeout(timeout, messages.next()).await {
+            Ok(Some(Ok(message))) => message,
+            Ok(Some(Err(e))) => {
+                warn!("WebSocket protocol error in session {}: {}", session_id, e);
+                break;
+            }
+            Ok(None) => break,
+            Err(_) => {
+                info!("Stream of session {} timed out", session_id);
+                break;
+            }
+        };
+
+        match message {
+            AggregatedMessage::Binary(data) => match parse_frame(&data, last_timestamp) {
+                Incoming::Frame { timestamp, jpeg } => {
+                    last_timestamp = Some(timestamp);
+                    received += 1;
+
+                    // Replaces the previous frame if the worker has not picked it up yet
+                    frames.send_replace(Some(Frame {
+                        timestamp,
+                        received: Instant::now(),
+                        jpeg,
+                    }));
+                }
+                // Older than what is already queued or analysed
+                Incoming::Outdated => {}
+                Incoming::Malformed => {
+                    let _ = send_json(
+                        &mut ws,
+                        &ServerMessage::Error {
+                            timestamp: None,
+                            message: "Frames must be an 8 byte timestamp followed by a JPEG image"
+                                .to_string(),
+                        },
+                    )
+                    .await;
+                }
+            },
+            AggregatedMessage::Text(_) => {
+                let _ = send_json(
+                    &mut ws,
+                    &ServerMessage::Error {
+                        timestamp: None,
+                        message: "Expected binary frames".to_string(),
+                    },
+                )
+                .await;
+            }
+            AggregatedMessage::Ping(data) => {
+                if ws.pong(&data).await.is_err() {
+                    break;
+                }
+            }
+            AggregatedMessage::Pong(_) => {}
+            AggregatedMessage::Close(reason) => {
+                debug!("Stream of session {} closed: {:?}", session_id, reason);
+                break;
+            }
+        }
+    }
+
+    info!(
+        "Stream of session {} ended after {} frames",
+        session_id, received
+    );
+    let _ = ws.close(None).await;
+}
+
+impl StreamWorker {
+    async fn run(mut self, mut latest: watch::Receiver<Option<Frame>>) {
+        let stale_after = Duration::from_millis(self.config.stream.stale_frame_ms);
+        let mut last_text: Option<String> = None;
+        let mut analysed = 0usize;
+
+        while latest.changed().await.is_ok() {
+            let Some(frame) = latest.borrow_and_update().clone() else {
+                continue;
+            };
+
+            let Ok(_permit) = self.slots.0.acquire().await else {
+                break;
+            };
+            // A newer frame may have arrived while waiting for the slot
+            let frame = newest_frame(&mut latest, frame);
+            if frame.is_stale(Instant::now(), stale_after) {
+                debug!("Dropping stale frame {}", frame.timestamp);
+                continue;
+            }
+
+            analysed += 1;
+            let result = self.analyse(&frame, &mut last_text).await;
+            if let Err(e) = result {
+                error!("Error processing stream frame: {:?}", e);
+                let message = ServerMessage::Error {
+                    timestamp: Some(frame.timestamp),
+                    message: format!("Error processing frame: {}", e),
+                };
+                if send_json(&mut self.ws, &message).await.is_err() {
+                    break;
+                }
+            }
+        }
+
+        debug!("Stream worker stopped after analysing {} frames", analysed);
+    }
+
+    /// Analyses one frame and pushes its description if it changed.
+    async fn analyse(
+        &mut self,
+        frame: &Frame,
+        last_text: &mut Option<String>,
+    ) -> anyhow::Result<()> {
+        let jpeg = frame.jpeg.clone();
+        let image = spawn_blocking(move || Image::from_bytes(jpeg.as_ref())).await??;
+
+        let scene = analyse_scene(
+            image,
+            self.engine,
+            self.config,
+            self.language,
+            Some(&self.session),
+            Some(frame.timestamp),
+        )
+        .await?;
+        if !is_new_description(&scene.text, last_text.as_deref()) {
+            return Ok(());
+        }
+
+        let audio = match self.audio {
+            Some(output) => Some((
+                synthesize(scene.text.clone(), scene.language, output, self.engine).await?,
+                output.format.content_type(),
+            )),
+            None => None,
+        };
+
+        let message = ServerMessage::Description {
+            timestamp: frame.timestamp,
+            text: self.text.then_some(scene.text.as_str()),
+            audio: audio.is_some(),
+            content_type: audio.as_ref().map(|(_, content_type)| *content_type),
+        };
+        send_json(&mut self.ws, &message).await?;
+        if let Some((audio, _)) = audio {
+            self.ws.binary(audio).await?;
+        }
+
+        *last_text = Some(scene.text);
+        Ok(())
+    }
+}
+
+async fn send_json(ws: &mut actix_ws::Session, message: &ServerMessage<'_>) -> anyhow::Result<()> {
+    ws.text(serde_json::to_string(message)?).await?;
+    Ok(())
+}
+
+#[test]
+fn test_frames_are_parsed_in_order() {
+    let message = |timestamp: u64, jpeg: &[u8]| {
+        let mut data = timestamp.to_be_bytes().to_vec();
+        data.extend_from_slice(jpeg);
+        Bytes::from(data)
+    };
+
+    assert_eq!(
+        parse_frame(&message(1000, b"jpeg"), None),
+        Incoming::Frame {
+            timestamp: 1000,
+            jpeg: Bytes::from_static(b"jpeg"),
+        }
+    );
+    assert_eq!(
+        parse_frame(&message(1001, b"jpeg"), Some(1000)),
+        Incoming::Frame {
+            timestamp: 1001,
+            jpeg: Bytes::from_static(b"jpeg"),
+        }
+    );
+    assert_eq!(
+        parse_frame(&message(1000, b"jpeg"), Some(1000)),
+        Incoming::Outdated
+    );
+    assert_eq!(
+        parse_frame(&message(999, b"jpeg"), Some(1000)),
+        Incoming::Outdated
+    );
+
+    // A bare timestamp or less carries no image
+    assert_eq!(parse_frame(&message(1000, b""), None), Incoming::Malformed);
+    assert_eq!(
+        parse_frame(&Bytes::from_static(b"short"), None),
+        Incoming::Malformed
+    );
+}
+
+#[test]
+fn test_stale_and_replaced_frames() {
+    let received = Instant::now();
+    let frame = |timestamp: u64| Frame {
+        timestamp,
+        received,
+        jpeg: Bytes::new(),
+    };
+    let stale_after = Duration::from_millis(1000);
+    assert!(!frame(1).is_stale(received + Duration::from_millis(1000), stale_after));
+    assert!(frame(1).is_stale(received + Duration::from_millis(1001), stale_after));
+
+    let (frames, mut latest) = watch::channel(None);
+    frames.send_replace(Some(frame(1)));
+    let first = latest.borrow_and_update().clone().unwrap();
+    assert_eq!(newest_frame(&mut latest, first).timestamp, 1);
+
+    // Sent while the first one waited for a slot
+    let first = frame(1);
+    frames.send_replace(Some(frame(2)));
+    assert_eq!(newest_frame(&mut latest, first).timestamp, 2);
+    assert!(!latest.has_changed().unwrap());
+}
+
+#[test]
+fn test_repeated_descriptions_are_skipped() {
+    assert!(is_new_description("Road ahead", None));
+    assert!(is_new_description("Road ahead", Some("Sidewalk ahead")));
+    assert!(!is_new_description("Road ahead", Some("Road ahead")));
+    assert!(!is_new_description("", None));
+}